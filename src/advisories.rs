@@ -0,0 +1,237 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL-1.2 OR GPL-3.0
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Match security advisories against installed packages.
+//!
+//! Read advisories in the JSON format of the Arch Linux security tracker, as
+//! served at <https://security.archlinux.org/all.json>, from offline dumps.
+
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    io::Read,
+};
+
+use alpm::PackageReason;
+use petgraph::visit::{
+    GraphRef, IntoNeighbors, IntoNeighborsDirected, IntoNodeIdentifiers, NodeCount, Visitable,
+};
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::{dependencies::dependents, graph::PackageNode};
+
+/// A security advisory group of the Arch Linux security tracker.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Advisory {
+    /// The name of the advisory group, e.g. `AVG-1234`.
+    pub name: String,
+    /// The names of all affected packages.
+    pub packages: Vec<String>,
+    /// The status, e.g. `Vulnerable`, `Fixed` or `Not affected`.
+    pub status: String,
+    /// The severity, e.g. `High`.
+    pub severity: String,
+    /// The kind of vulnerability, e.g. `arbitrary code execution`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The version in which the vulnerabilities were found.
+    pub affected: String,
+    /// The first version which fixes the vulnerabilities, if any.
+    pub fixed: Option<String>,
+    /// The identifiers of all issues in this group, e.g. CVEs.
+    #[serde(default)]
+    pub issues: Vec<String>,
+    /// The identifiers of all published advisories for this group.
+    #[serde(default)]
+    pub advisories: Vec<String>,
+}
+
+impl Advisory {
+    /// Whether this advisory affects `version` of its packages.
+    ///
+    /// An advisory affects all versions before the fixed version, or all
+    /// versions if there is no fixed version yet.  An advisory with status
+    /// `Not affected` affects no version.
+    ///
+    /// Do not treat the affected version as lower bound: the tracker records
+    /// the version the vulnerability was found in, not the first vulnerable
+    /// version, so earlier versions are usually vulnerable as well.
+    #[must_use]
+    pub fn affects(&self, version: &str) -> bool {
+        if self.status == "Not affected" {
+            return false;
+        }
+        self.fixed
+            .as_deref()
+            .is_none_or(|fixed| alpm::vercmp(version, fixed) == Ordering::Less)
+    }
+}
+
+/// Read advisories from a JSON dump of the Arch Linux security tracker.
+///
+/// # Errors
+///
+/// Return an error if reading fails, or if the data is no list of advisories.
+pub fn read_advisories<R: Read>(reader: R) -> std::io::Result<Vec<Advisory>> {
+    serde_json::from_reader(reader)
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+}
+
+/// An installed package which an advisory affects.
+#[derive(Debug, Clone)]
+pub struct Finding<'a, 'b> {
+    /// The affected installed package.
+    pub package: PackageNode<'a>,
+    /// The advisory.
+    pub advisory: &'b Advisory,
+    /// All transitive dependents of the package, sorted by name.
+    pub dependents: Vec<PackageNode<'a>>,
+    /// All explicitly installed packages which pull in the package, sorted by name.
+    ///
+    /// This includes the package itself if it is explicitly installed.
+    pub explicit_roots: Vec<PackageNode<'a>>,
+}
+
+/// Match `advisories` against all packages in `graph`.
+///
+/// Find all packages in `graph` whose installed version an advisory affects,
+/// see [`Advisory::affects`], and collect their dependents (see [`dependents`])
+/// and the explicitly installed packages among these.
+///
+/// Return findings sorted by package name and advisory name.
+pub fn audit<'a, 'b, G>(graph: G, advisories: &'b [Advisory]) -> Vec<Finding<'a, 'b>>
+where
+    G: GraphRef
+        + NodeCount
+        + Visitable<NodeId = PackageNode<'a>>
+        + IntoNeighbors
+        + IntoNeighborsDirected
+        + IntoNodeIdentifiers,
+{
+    let installed = graph
+        .node_identifiers()
+        .map(|node| (node.package().name(), node))
+        .collect::<HashMap<_, _>>();
+    let mut findings = Vec::new();
+    for advisory in advisories {
+        // Advisories may list the same package more than once
+        let names = advisory
+            .packages
+            .iter()
+            .map(String::as_str)
+            .collect::<HashSet<_>>();
+        for name in names {
+            let Some(node) = installed.get(name).copied() else {
+                continue;
+            };
+            if !advisory.affects(node.version().as_str()) {
+                continue;
+            }
+            debug!(
+                package = name,
+                "{} affects {name} {}",
+                advisory.name,
+                node.version()
+            );
+            let mut dependents = dependents(graph, node.package())
+                .node_identifiers()
+                .collect::<Vec<_>>();
+            dependents.sort_by_key(|dependent| dependent.package().name());
+            let explicit_roots = dependents
+                .iter()
+                .copied()
+                .filter(|dependent| dependent.reason() == PackageReason::Explicit)
+                .collect();
+            dependents.retain(|dependent| *dependent != node);
+            findings.push(Finding {
+                package: node,
+                advisory,
+                dependents,
+                explicit_roots,
+            });
+        }
+    }
+    findings.sort_by(|a, b| {
+        (a.package.package().name(), &a.advisory.name)
+            .cmp(&(b.package.package().name(), &b.advisory.name))
+    });
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn advisory(status: &str, affected: &str, fixed: Option<&str>) -> Advisory {
+        Advisory {
+            name: "AVG-1".to_owned(),
+            packages: vec!["foo".to_owned()],
+            status: status.to_owned(),
+            severity: "High".to_owned(),
+            kind: "arbitrary code execution".to_owned(),
+            affected: affected.to_owned(),
+            fixed: fixed.map(ToOwned::to_owned),
+            issues: Vec::new(),
+            advisories: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn affects_all_versions_without_fix() {
+        let advisory = advisory("Vulnerable", "1.2-1", None);
+        assert!(advisory.affects("1.2-1"));
+        assert!(advisory.affects("1.5-1"));
+        assert!(advisory.affects("1:0.1-1"));
+    }
+
+    #[test]
+    fn affects_versions_before_fix() {
+        let advisory = advisory("Fixed", "1.2-1", Some("1.3-1"));
+        assert!(advisory.affects("1.2-1"));
+        assert!(advisory.affects("1.2.9-3"));
+        assert!(!advisory.affects("1.3-1"));
+        assert!(!advisory.affects("1.3-2"));
+        assert!(!advisory.affects("1:1.0-1"));
+    }
+
+    #[test]
+    fn affects_versions_older_than_affected() {
+        let advisory = advisory("Fixed", "1.2-1", Some("1.3-1"));
+        assert!(advisory.affects("1.0-1"));
+        assert!(advisory.affects("0.9-5"));
+    }
+
+    #[test]
+    fn not_affected_affects_no_version() {
+        let advisory = advisory("Not affected", "1.2-1", None);
+        assert!(!advisory.affects("1.2-1"));
+        assert!(!advisory.affects("0.1-1"));
+    }
+
+    #[test]
+    fn read_advisories_from_tracker_json() {
+        let json = r#"[{
+            "name": "AVG-2",
+            "packages": ["foo", "foo-libs"],
+            "status": "Vulnerable",
+            "severity": "Critical",
+            "type": "denial of service",
+            "affected": "2.0-1",
+            "fixed": null,
+            "ticket": null,
+            "issues": ["CVE-2024-0001"],
+            "advisories": []
+        }]"#;
+        let advisories = read_advisories(json.as_bytes()).unwrap();
+        assert_eq!(advisories.len(), 1);
+        let advisory = advisories.first().unwrap();
+        assert_eq!(advisory.packages, ["foo", "foo-libs"]);
+        assert_eq!(advisory.kind, "denial of service");
+        assert_eq!(advisory.fixed, None);
+        assert_eq!(advisory.issues, ["CVE-2024-0001"]);
+    }
+}
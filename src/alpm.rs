@@ -6,7 +6,8 @@
 
 //! Utilities for ALPM.
 
-use alpm::LogLevel;
+use alpm::{AlpmList, Db, Dep, LogLevel, Pkg};
+use alpm_utils::{DbListExt, depends::satisfies};
 use tracing::Level;
 
 /// Tracing log callback for [`alpm::Alpm::set_log_cb`].
@@ -21,3 +22,40 @@ pub fn tracing_log_cb(level: alpm::LogLevel, message: &str, _: &mut ()) {
         _ => tracing::event!(target: "alpm", Level::TRACE, "{}", message),
     }
 }
+
+/// Whether a package is foreign.
+///
+/// A package is foreign if no sync database in `syncdbs` contains a package of
+/// the same name, e.g. if the package was installed from the AUR, or built
+/// locally.
+pub fn is_foreign(package: &Pkg, syncdbs: AlpmList<&Db>) -> bool {
+    syncdbs.pkg(package.name()).is_err()
+}
+
+/// Whether a package satisfies a dependency.
+///
+/// A package satisfies a dependency if its name and version, or any of its
+/// provisions satisfy the dependency.
+pub fn satisfies_package(dep: &Dep, package: &Pkg) -> bool {
+    satisfies(
+        dep,
+        package.name(),
+        package.version(),
+        package.provides().iter(),
+    )
+}
+
+/// Whether two packages conflict with each other.
+///
+/// Two packages conflict if either declares a conflict which the other
+/// package satisfies, either directly or via its provisions.
+pub fn conflicts_with(package: &Pkg, other: &Pkg) -> bool {
+    package
+        .conflicts()
+        .iter()
+        .any(|dep| satisfies_package(dep, other))
+        || other
+            .conflicts()
+            .iter()
+            .any(|dep| satisfies_package(dep, package))
+}
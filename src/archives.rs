@@ -0,0 +1,625 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL-1.2 OR GPL-3.0
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Build package databases from package archives.
+//!
+//! ALPM cannot resolve dependencies between loose package archives, so we
+//! write the metadata of archives into a temporary local database instead.
+//! ALPM then computes reverse dependencies for these packages like for
+//! installed packages, and all graph analyses work unchanged.
+//!
+//! The temporary database only holds package metadata: packages from archives
+//! and repository databases have no file lists, backup files or mtree data.
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    hash::BuildHasher,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use alpm::{Alpm, Db, PackageReason, Pkg, SigLevel};
+use tracing::{debug, warn};
+
+use crate::cache::ArchiveName;
+
+/// The metadata of a package, as found in a `.PKGINFO` file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackageInfo {
+    /// The name of the package.
+    pub name: String,
+    /// The name of the base package.
+    pub base: Option<String>,
+    /// The full version of the package.
+    pub version: String,
+    /// The description of the package.
+    pub desc: Option<String>,
+    /// The upstream URL of the package.
+    pub url: Option<String>,
+    /// The architecture of the package.
+    pub arch: Option<String>,
+    /// The build date, in seconds since the epoch.
+    pub build_date: Option<i64>,
+    /// The packager.
+    pub packager: Option<String>,
+    /// The installed size in bytes.
+    pub size: Option<i64>,
+    /// Licenses.
+    pub licenses: Vec<String>,
+    /// Groups.
+    pub groups: Vec<String>,
+    /// Packages which this package replaces.
+    pub replaces: Vec<String>,
+    /// Dependencies.
+    pub depends: Vec<String>,
+    /// Optional dependencies, with descriptions.
+    pub optdepends: Vec<String>,
+    /// Conflicts.
+    pub conflicts: Vec<String>,
+    /// Provisions.
+    pub provides: Vec<String>,
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+impl PackageInfo {
+    /// Read package metadata from a `.PKGINFO` file.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if reading fails, if the file lacks a package name or
+    /// version, or if a number is invalid.
+    pub fn read<R: BufRead>(reader: R) -> std::io::Result<Self> {
+        let mut info = PackageInfo::default();
+        for line in reader.lines() {
+            let line = line?;
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim().to_owned());
+            let number = |value: &str| {
+                value
+                    .parse()
+                    .map_err(|_| invalid_data(format!("Invalid {key} in .PKGINFO: {value}")))
+            };
+            match key {
+                _ if key.starts_with('#') => {}
+                "pkgname" => info.name = value,
+                "pkgbase" => info.base = Some(value),
+                "pkgver" => info.version = value,
+                "pkgdesc" => info.desc = Some(value),
+                "url" => info.url = Some(value),
+                "arch" => info.arch = Some(value),
+                "builddate" => info.build_date = Some(number(&value)?),
+                "packager" => info.packager = Some(value),
+                "size" => info.size = Some(number(&value)?),
+                "license" => info.licenses.push(value),
+                "group" => info.groups.push(value),
+                "replaces" => info.replaces.push(value),
+                "depend" => info.depends.push(value),
+                "optdepend" => info.optdepends.push(value),
+                "conflict" => info.conflicts.push(value),
+                "provides" => info.provides.push(value),
+                _ => {}
+            }
+        }
+        if info.name.is_empty() || info.version.is_empty() {
+            return Err(invalid_data(
+                "Missing pkgname or pkgver in .PKGINFO".to_owned(),
+            ));
+        }
+        Ok(info)
+    }
+
+    /// Get the metadata of a loaded package.
+    #[must_use]
+    pub fn from_pkg(package: &Pkg) -> Self {
+        let strings = |list: alpm::AlpmList<&str>| list.iter().map(str::to_owned).collect();
+        let deps =
+            |list: alpm::AlpmList<&alpm::Dep>| list.iter().map(ToString::to_string).collect();
+        PackageInfo {
+            name: package.name().to_owned(),
+            base: package.base().map(str::to_owned),
+            version: package.version().to_string(),
+            desc: package.desc().map(str::to_owned),
+            url: package.url().map(str::to_owned),
+            arch: package.arch().map(str::to_owned),
+            build_date: Some(package.build_date()),
+            packager: package.packager().map(str::to_owned),
+            size: Some(package.isize()),
+            licenses: strings(package.licenses()),
+            groups: strings(package.groups()),
+            replaces: deps(package.replaces()),
+            depends: deps(package.depends()),
+            optdepends: deps(package.optdepends()),
+            conflicts: deps(package.conflicts()),
+            provides: deps(package.provides()),
+        }
+    }
+
+    /// Write this package as `desc` file of a local database.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if writing fails.
+    pub fn write_desc<W: Write>(
+        &self,
+        write: &mut W,
+        reason: PackageReason,
+    ) -> std::io::Result<()> {
+        let reason = match reason {
+            PackageReason::Explicit => "0",
+            PackageReason::Depend => "1",
+        };
+        let build_date = self.build_date.map(|date| date.to_string());
+        let size = self.size.map(|size| size.to_string());
+        let single = [
+            ("NAME", Some(self.name.as_str())),
+            ("VERSION", Some(self.version.as_str())),
+            ("BASE", self.base.as_deref()),
+            ("DESC", self.desc.as_deref()),
+            ("URL", self.url.as_deref()),
+            ("ARCH", self.arch.as_deref()),
+            ("BUILDDATE", build_date.as_deref()),
+            ("PACKAGER", self.packager.as_deref()),
+            ("SIZE", size.as_deref()),
+            ("REASON", Some(reason)),
+            ("VALIDATION", Some("none")),
+        ];
+        for (field, value) in single {
+            if let Some(value) = value {
+                writeln!(write, "%{field}%\n{value}\n")?;
+            }
+        }
+        let lists = [
+            ("GROUPS", &self.groups),
+            ("LICENSE", &self.licenses),
+            ("REPLACES", &self.replaces),
+            ("DEPENDS", &self.depends),
+            ("OPTDEPENDS", &self.optdepends),
+            ("CONFLICTS", &self.conflicts),
+            ("PROVIDES", &self.provides),
+        ];
+        for (field, values) in lists {
+            if !values.is_empty() {
+                writeln!(write, "%{field}%")?;
+                for value in values {
+                    writeln!(write, "{value}")?;
+                }
+                writeln!(write)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A file with package metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PackageFile {
+    /// A `.PKGINFO` file, or a file whose name ends in `.PKGINFO`.
+    PkgInfo,
+    /// A package archive, e.g. `.pkg.tar.zst`.
+    Archive,
+}
+
+impl PackageFile {
+    /// Get the kind of package file from a `file_name`.
+    ///
+    /// Return `None` if `file_name` has no package metadata, e.g. a signature.
+    fn of(file_name: &str) -> Option<Self> {
+        if file_name.ends_with("PKGINFO") {
+            Some(Self::PkgInfo)
+        } else if ArchiveName::parse(file_name).is_some() {
+            Some(Self::Archive)
+        } else {
+            None
+        }
+    }
+}
+
+/// Read the metadata of all packages in `directory`.
+///
+/// Load package archives, e.g. `.pkg.tar.zst`, with `alpm`, and read
+/// `.PKGINFO` files, as well as files ending in `.PKGINFO`, directly.  Also
+/// read `.PKGINFO` files of extracted packages in subdirectories of
+/// `directory`.  Log and skip archives which fail to load.  If `directory`
+/// has multiple versions of a package, only keep the newest one.
+///
+/// Only read package metadata, but not the file lists of packages.
+///
+/// Return packages sorted by name.
+///
+/// # Errors
+///
+/// Return an error if `directory` cannot be read or if a `.PKGINFO` file is
+/// invalid.
+pub fn read_packages(alpm: &Alpm, directory: &Path) -> std::io::Result<Vec<PackageInfo>> {
+    let mut packages = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file = if entry.file_type()?.is_dir() {
+            let pkginfo = path.join(".PKGINFO");
+            pkginfo.is_file().then_some((PackageFile::PkgInfo, pkginfo))
+        } else {
+            PackageFile::of(&entry.file_name().to_string_lossy()).map(|kind| (kind, path))
+        };
+        match file {
+            Some((PackageFile::PkgInfo, pkginfo)) => {
+                debug!("Reading {}", pkginfo.display());
+                let info = PackageInfo::read(BufReader::new(File::open(&pkginfo)?))
+                    .map_err(|error| invalid_data(format!("{}: {error}", pkginfo.display())))?;
+                packages.push(info);
+            }
+            Some((PackageFile::Archive, path)) => {
+                debug!("Loading {}", path.display());
+                match alpm.pkg_load(path.as_os_str().as_encoded_bytes(), false, SigLevel::NONE) {
+                    Ok(package) => packages.push(PackageInfo::from_pkg(&package)),
+                    Err(error) => warn!("Failed to load {}: {error}", path.display()),
+                }
+            }
+            None => {}
+        }
+    }
+    packages.sort_by(|a, b| {
+        a.name
+            .cmp(&b.name)
+            .then_with(|| alpm::vercmp(b.version.as_str(), a.version.as_str()))
+    });
+    packages.dedup_by(|older, newer| {
+        let duplicate = older.name == newer.name;
+        if duplicate {
+            debug!(
+                "Skipping {} {} in favour of {}",
+                older.name, older.version, newer.version
+            );
+        }
+        duplicate
+    });
+    Ok(packages)
+}
+
+/// Get the repository name of a database file name.
+///
+/// Strip the `.db` or `.files` extension and any following archive
+/// extension, e.g. `foo` for `foo.db.tar.zst`, `foo.db`, or `foo.files`.
+///
+/// Return `None` if `file_name` has no such extension or the name is empty.
+#[must_use]
+pub fn repository_name(file_name: &str) -> Option<&str> {
+    [".db", ".files"]
+        .into_iter()
+        .flat_map(|extension| {
+            file_name
+                .match_indices(extension)
+                .map(move |(index, _)| (index, index + extension.len()))
+        })
+        .filter(|(_, end)| {
+            file_name
+                .get(*end..)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(".tar"))
+        })
+        .filter_map(|(index, _)| file_name.get(..index))
+        .find(|name| !name.is_empty())
+}
+
+/// A temporary ALPM database directory.
+///
+/// Delete the directory when dropped.
+#[derive(Debug)]
+pub struct TemporaryDatabase {
+    path: PathBuf,
+    repositories: Vec<String>,
+}
+
+impl TemporaryDatabase {
+    /// The version of the local database format which we write.
+    const LOCAL_DB_VERSION: &str = "9";
+
+    /// Create a new temporary database with an empty local database.
+    ///
+    /// Link all sync databases of `dbpath` into the temporary database, so
+    /// that sync databases remain available.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if the directory cannot be created.
+    pub fn create(dbpath: &Path) -> std::io::Result<Self> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.subsec_nanos());
+        let path = std::env::temp_dir().join(format!("packit-{}-{nanos}", std::process::id()));
+        std::fs::create_dir(&path)?;
+        let database = Self {
+            path,
+            repositories: Vec::new(),
+        };
+        let local = database.path.join("local");
+        std::fs::create_dir(&local)?;
+        std::fs::write(
+            local.join("ALPM_DB_VERSION"),
+            format!("{}\n", Self::LOCAL_DB_VERSION),
+        )?;
+        let sync = database.path.join("sync");
+        std::fs::create_dir(&sync)?;
+        match std::fs::read_dir(dbpath.join("sync")) {
+            Ok(entries) => {
+                for entry in entries {
+                    let entry = entry?;
+                    std::os::unix::fs::symlink(entry.path(), sync.join(entry.file_name()))?;
+                }
+            }
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                debug!("No sync databases in {}", dbpath.display());
+            }
+            Err(error) => return Err(error),
+        }
+        debug!("Created temporary database at {}", database.path.display());
+        Ok(database)
+    }
+
+    /// The path of this database, for use as `DBPath`.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Add a standalone repository database file as sync database.
+    ///
+    /// Name the repository after `file`, see [`repository_name`], and remember
+    /// the name for [`Self::repositories`].  Callers still need to register the
+    /// repository with ALPM.
+    ///
+    /// If a configured sync database has the same name, replace it, so that
+    /// the file shadows the configured repository, e.g. for a snapshot of
+    /// `core.db`.  Callers then must not register the configured repository.
+    ///
+    /// Return the name of the repository.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if `file` has no valid name, if another file added
+    /// before has the same name, or if linking the file fails.
+    pub fn add_repository(&mut self, file: &Path) -> std::io::Result<String> {
+        let name = file
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(repository_name)
+            .ok_or_else(|| invalid_data(format!("Invalid database file {}", file.display())))?
+            .to_owned();
+        if self.repositories.contains(&name) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("Repository {name} already exists"),
+            ));
+        }
+        let sync = self.path.join("sync");
+        for extension in ["db", "files"] {
+            let link = sync.join(format!("{name}.{extension}"));
+            if link.symlink_metadata().is_ok() {
+                debug!("Shadowing sync database {}", link.display());
+                std::fs::remove_file(link)?;
+            }
+        }
+        std::os::unix::fs::symlink(std::path::absolute(file)?, sync.join(format!("{name}.db")))?;
+        debug!("Added repository {name} from {}", file.display());
+        self.repositories.push(name.clone());
+        Ok(name)
+    }
+
+    /// The names of all repositories added with [`Self::add_repository`].
+    #[must_use]
+    pub fn repositories(&self) -> &[String] {
+        &self.repositories
+    }
+
+    /// Add a package to the local database.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if writing the package fails.
+    pub fn add_package(&self, package: &PackageInfo, reason: PackageReason) -> std::io::Result<()> {
+        let directory = self
+            .path
+            .join("local")
+            .join(format!("{}-{}", package.name, package.version));
+        std::fs::create_dir(&directory)?;
+        let mut desc = BufWriter::new(File::create(directory.join("desc"))?);
+        package.write_desc(&mut desc, reason)?;
+        desc.flush()?;
+        std::fs::write(directory.join("files"), "%FILES%\n\n")
+    }
+
+    /// Copy installed packages from `localdb` to this database.
+    ///
+    /// Copy all packages from `localdb` at `dbpath`, except those in `skip`.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if copying a package fails.
+    pub fn copy_local_packages<S: BuildHasher>(
+        &self,
+        localdb: &Db,
+        dbpath: &Path,
+        skip: &HashSet<&str, S>,
+    ) -> std::io::Result<()> {
+        for package in localdb.pkgs() {
+            if skip.contains(package.name()) {
+                continue;
+            }
+            let name = format!("{}-{}", package.name(), package.version());
+            let target = self.path.join("local").join(&name);
+            std::fs::create_dir(&target)?;
+            let source = dbpath.join("local").join(&name);
+            for file in ["desc", "files"] {
+                std::fs::copy(source.join(file), target.join(file))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TemporaryDatabase {
+    fn drop(&mut self) {
+        if let Err(error) = std::fs::remove_dir_all(&self.path) {
+            warn!(
+                "Failed to remove temporary database {}: {error}",
+                self.path.display()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repository_name_of_database_files() {
+        assert_eq!(repository_name("foo.db.tar.zst"), Some("foo"));
+        assert_eq!(repository_name("foo.db"), Some("foo"));
+        assert_eq!(repository_name("foo.files"), Some("foo"));
+        assert_eq!(repository_name("foo.files.tar.gz"), Some("foo"));
+        assert_eq!(repository_name("core-testing.db"), Some("core-testing"));
+    }
+
+    #[test]
+    fn repository_name_with_db_in_name() {
+        assert_eq!(repository_name("dbus.db"), Some("dbus"));
+        assert_eq!(repository_name("foo.dbus.db"), Some("foo.dbus"));
+    }
+
+    #[test]
+    fn repository_name_of_invalid_files() {
+        assert_eq!(repository_name("foo"), None);
+        assert_eq!(repository_name("foo.pkg.tar.zst"), None);
+        assert_eq!(repository_name(".db"), None);
+        assert_eq!(repository_name("foo.db.sig"), None);
+    }
+
+    const PKGINFO: &str = "\
+# Generated by makepkg 7.0.0
+# using fakeroot version 1.36
+pkgname = foo
+pkgbase = foo-base
+xdata = pkgtype=split
+pkgver = 1:2.0-3
+pkgdesc = A package = with equals
+url = https://example.com
+builddate = 1700000000
+packager = Jane Doe <jane@example.com>
+size = 12345
+arch = x86_64
+license = MIT
+license = Apache-2.0
+group = foo-group
+replaces = foo-old
+conflict = foo-git
+provides = libfoo.so=1-64
+provides = foo-api=2
+depend = glibc>=2.38
+depend = libbar.so
+optdepend = python: for scripts
+optdepend = bash-completion
+";
+
+    #[test]
+    fn read_pkginfo() {
+        let info = PackageInfo::read(PKGINFO.as_bytes()).unwrap();
+        assert_eq!(
+            info,
+            PackageInfo {
+                name: "foo".to_owned(),
+                base: Some("foo-base".to_owned()),
+                version: "1:2.0-3".to_owned(),
+                desc: Some("A package = with equals".to_owned()),
+                url: Some("https://example.com".to_owned()),
+                arch: Some("x86_64".to_owned()),
+                build_date: Some(1_700_000_000),
+                packager: Some("Jane Doe <jane@example.com>".to_owned()),
+                size: Some(12345),
+                licenses: vec!["MIT".to_owned(), "Apache-2.0".to_owned()],
+                groups: vec!["foo-group".to_owned()],
+                replaces: vec!["foo-old".to_owned()],
+                depends: vec!["glibc>=2.38".to_owned(), "libbar.so".to_owned()],
+                optdepends: vec![
+                    "python: for scripts".to_owned(),
+                    "bash-completion".to_owned()
+                ],
+                conflicts: vec!["foo-git".to_owned()],
+                provides: vec!["libfoo.so=1-64".to_owned(), "foo-api=2".to_owned()],
+            }
+        );
+    }
+
+    #[test]
+    fn read_pkginfo_minimal() {
+        let info = PackageInfo::read("pkgname = foo\npkgver = 1.0-1\n".as_bytes()).unwrap();
+        assert_eq!(
+            info,
+            PackageInfo {
+                name: "foo".to_owned(),
+                version: "1.0-1".to_owned(),
+                ..PackageInfo::default()
+            }
+        );
+    }
+
+    #[test]
+    fn read_pkginfo_without_name_or_version() {
+        for pkginfo in ["pkgver = 1.0-1\n", "pkgname = foo\n", ""] {
+            let error = PackageInfo::read(pkginfo.as_bytes()).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData, "{pkginfo}");
+        }
+    }
+
+    #[test]
+    fn read_pkginfo_invalid_numbers() {
+        for pkginfo in [
+            "pkgname = foo\npkgver = 1.0-1\nsize = large\n",
+            "pkgname = foo\npkgver = 1.0-1\nbuilddate = today\n",
+        ] {
+            let error = PackageInfo::read(pkginfo.as_bytes()).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData, "{pkginfo}");
+        }
+    }
+
+    #[test]
+    fn write_desc_of_pkginfo() {
+        let info = PackageInfo::read(
+            "pkgname = foo\npkgver = 1.0-1\ndepend = bar\ndepend = baz>=2\n".as_bytes(),
+        )
+        .unwrap();
+        let mut desc = Vec::new();
+        info.write_desc(&mut desc, PackageReason::Depend).unwrap();
+        assert_eq!(
+            String::from_utf8(desc).unwrap(),
+            "%NAME%\nfoo\n\n%VERSION%\n1.0-1\n\n%REASON%\n1\n\n%VALIDATION%\nnone\n\n\
+%DEPENDS%\nbar\nbaz>=2\n\n"
+        );
+    }
+
+    #[test]
+    fn package_file_of_file_names() {
+        assert_eq!(PackageFile::of(".PKGINFO"), Some(PackageFile::PkgInfo));
+        assert_eq!(PackageFile::of("foo.PKGINFO"), Some(PackageFile::PkgInfo));
+        assert_eq!(
+            PackageFile::of("foo-1.0-1-x86_64.pkg.tar.xz"),
+            Some(PackageFile::Archive)
+        );
+        assert_eq!(
+            PackageFile::of("foo-1.0-1-any.pkg.tar.zst"),
+            Some(PackageFile::Archive)
+        );
+        assert_eq!(PackageFile::of("foo-1.0-1-x86_64.pkg.tar.xz.sig"), None);
+        assert_eq!(PackageFile::of("foo.db.tar.zst"), None);
+        assert_eq!(PackageFile::of("README"), None);
+    }
+}
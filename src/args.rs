@@ -4,7 +4,9 @@
 //
 // See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
 
-use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 use crate::print::PrintOneLine;
 
@@ -31,21 +33,118 @@ const LONG_VERSION: &str = concat!(
 #[derive(Debug, Parser)]
 #[command(version, about, after_long_help = AFTER_LONG_HELP, long_version = LONG_VERSION)]
 pub struct CliArgs {
+    #[clap(flatten)]
+    pub source: PackageSource,
     #[command(subcommand)]
     pub command: Command,
 }
 
+/// Where to read packages from.
+///
+/// Only commands which analyse the package graph support these options;
+/// commands which inspect files of installed packages reject them.
+#[derive(Args, Debug)]
+pub struct PackageSource {
+    /// Analyse package archives in a directory instead of installed packages.
+    ///
+    /// Read package archives, e.g. `.pkg.tar.zst`, `.PKGINFO` files, and
+    /// extracted packages in the directory, and treat them as if they were
+    /// the installed packages.  Only read the metadata of these packages, but
+    /// not their file lists.
+    #[clap(long, global = true, value_name = "DIRECTORY")]
+    pub archives: Option<PathBuf>,
+    /// Analyse the packages of a repository database instead of installed packages.
+    ///
+    /// Load a repository database file, e.g. `foo.db.tar.zst` as created by
+    /// `repo-add`, as additional sync database named after the file, and
+    /// treat its packages as if they were the installed packages.  The file
+    /// replaces a configured repository of the same name, e.g. for snapshots
+    /// of `core.db`.  Packages from `--archives` replace packages of the same
+    /// name from repository databases.
+    #[clap(long = "repo-db", global = true, value_name = "FILE")]
+    pub repo_dbs: Vec<PathBuf>,
+    /// Resolve packages from archives or repository databases against
+    /// installed packages as well.
+    ///
+    /// Packages from archives or repository databases replace installed
+    /// packages of the same name.
+    #[clap(long, global = true)]
+    pub with_localdb: bool,
+    /// Treat only these packages from archives or repository databases as
+    /// explicitly installed.
+    ///
+    /// Treat all other packages from archives or repository databases as
+    /// dependencies.  If not given, treat all of them as explicitly installed.
+    #[clap(long, global = true, value_name = "PACKAGE")]
+    pub explicit: Vec<String>,
+}
+
+impl PackageSource {
+    /// Whether to read packages from archives or repository databases.
+    pub fn is_given(&self) -> bool {
+        self.archives.is_some() || !self.repo_dbs.is_empty()
+    }
+}
+
 #[derive(Debug, Subcommand)]
 pub enum Command {
+    Alternatives(Alternatives),
+    Audit(Audit),
+    Cache(Cache),
+    CanDowngrade(CanDowngrade),
+    Check(Check),
+    Constraints(Constraints),
+    Dependencies(Dependencies),
     Dependents(Dependents),
+    Du(Du),
+    Export(Export),
+    Foreign(Foreign),
+    Groups(Groups),
+    History(History),
+    #[command(subcommand)]
+    Hook(Hook),
+    Linkcheck(Linkcheck),
+    Lostfiles(Lostfiles),
     Orphans(Orphans),
+    Pacnew(Pacnew),
+    RebuildOrder(RebuildOrder),
+    Reconcile(Reconcile),
+    Risk(Risk),
+    Sources(Sources),
+    Stale(Stale),
+    Stats(Stats),
+    UpgradePreview(UpgradePreview),
+    Verify(Verify),
     #[cfg(feature = "completions")]
     Completions(Completions),
 }
 
+impl Command {
+    /// Whether this command inspects the file system of installed packages.
+    ///
+    /// These commands need file lists, mtree and backup data of installed
+    /// packages, which packages from archives and repository databases lack,
+    /// so they do not support `--archives` and `--repo-db`.
+    pub fn inspects_files(&self) -> bool {
+        matches!(
+            self,
+            Self::Cache(_)
+                | Self::History(_)
+                | Self::Hook(_)
+                | Self::Linkcheck(_)
+                | Self::Lostfiles(_)
+                | Self::Pacnew(_)
+                | Self::Verify(_)
+        )
+    }
+}
+
 /// List orphan packages.
 #[derive(Args, Debug)]
 pub struct Orphans {
+    /// Keep installed members of package groups, as if they were explicitly installed.
+    #[clap(long)]
+    pub group_roots: bool,
     #[clap(flatten)]
     pub graph_options: GraphOptions,
 }
@@ -59,8 +158,523 @@ pub struct Dependents {
     pub graph_options: GraphOptions,
 }
 
+/// List packages which a package depends on.
+#[derive(Args, Debug)]
+pub struct Dependencies {
+    /// The package whose dependencies to list.
+    pub package: String,
+    #[clap(flatten)]
+    pub graph_options: GraphOptions,
+}
+
+/// Rank explicitly installed packages by the size of their dependencies.
+///
+/// For every explicitly installed package print the installed size of the
+/// package and all its dependencies, followed by the installed size of those
+/// packages which no other explicitly installed package depends on, i.e. the
+/// size which removing the package with all its dependencies would free.
+#[derive(Args, Debug)]
+pub struct Du {
+    /// Ignore optional dependencies.
+    #[clap(long)]
+    pub ignore_optdepends: bool,
+    /// Sort by exclusive size instead of total size.
+    #[clap(long)]
+    pub exclusive: bool,
+    /// Print as JSON.
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// List foreign packages.
+///
+/// List installed packages which are not in any sync database, e.g. packages
+/// from the AUR or locally built packages.  Mark foreign packages which are
+/// orphans.
+///
+/// For every foreign package list those dependencies which only the foreign
+/// package keeps installed, and all packages from sync databases the foreign
+/// package depends on.
+#[derive(Args, Debug)]
+pub struct Foreign {
+    /// Ignore optional dependencies.
+    #[clap(long)]
+    pub ignore_optdepends: bool,
+    /// Only list foreign packages, without their dependencies.
+    #[clap(short = 'q', long = "quiet")]
+    pub quiet: bool,
+    /// Print as JSON.
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// Show the installation history of a package.
+///
+/// List when the package was installed, upgraded, downgraded, or removed
+/// according to the pacman log, along with the pacman command which did so.
+///
+/// If the package is installed, show whether it's explicitly installed, a
+/// dependency, or an orphan, and which command last installed it, and which
+/// packages installed along with it depend on it.
+#[derive(Args, Debug)]
+pub struct History {
+    /// The package whose history to show.
+    pub package: String,
+    /// Ignore optional dependencies.
+    #[clap(long)]
+    pub ignore_optdepends: bool,
+    /// Print as JSON.
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// Commands for pacman hooks.
+#[derive(Debug, Subcommand)]
+pub enum Hook {
+    PostTransaction(PostTransaction),
+}
+
+/// Report new orphans after a pacman transaction.
+///
+/// Compare the current orphans to the orphans recorded after the last
+/// transaction, print all orphans which the transaction created, and record
+/// the current orphans for the next transaction.  Mark new orphans which are
+/// part of a dependency cycle of orphans, and thus not reported by pacman -Qtd.
+///
+/// Upon first invocation, only record the current orphans.
+#[derive(Args, Debug)]
+pub struct PostTransaction {
+    /// Ignore optional dependencies.
+    #[clap(long)]
+    pub ignore_optdepends: bool,
+    /// The file to record orphans in.
+    #[clap(long, default_value = "/var/lib/packit/orphans")]
+    pub state_file: PathBuf,
+}
+
+/// Check dependencies against linked libraries.
+///
+/// Read the libraries which ELF files of installed packages link against, and
+/// find the owners of these libraries in the file lists of installed packages.
+///
+/// Report libraries a package links against without depending on their
+/// owners, libraries no installed package owns, and dependencies which own
+/// libraries of which the package links none.
+///
+/// Only consider required dependencies.
+#[derive(Args, Debug)]
+pub struct Linkcheck {
+    /// Packages to check; check all packages if empty.
+    pub packages: Vec<String>,
+    /// Accept libraries of transitive dependencies.
+    #[clap(long)]
+    pub transitive: bool,
+    /// Print as JSON.
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// List packages dropped or replaced upstream.
+///
+/// List installed packages which no longer exist in any sync database, even
+/// though they were installed from a repository, and installed packages which
+/// a package in a sync database replaces.  List every package once, as
+/// replaced by all its replacements if any.  For every such package, show
+/// whether it is explicitly installed, a dependency, or an orphan, and list
+/// the installed packages which directly or transitively depend on it.
+#[derive(Args, Debug)]
+pub struct Stale {
+    /// Ignore optional dependencies.
+    #[clap(long)]
+    pub ignore_optdepends: bool,
+    /// Print as JSON.
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// Find packages in sync databases which could replace an installed package.
+///
+/// Find all packages in sync databases which satisfy dependencies of dependents
+/// of the installed package, either by name or by their provisions.  For every
+/// candidate, list the dependents it satisfies and those it doesn't, and
+/// installed packages it conflicts with.
+#[derive(Args, Debug)]
+pub struct Alternatives {
+    /// The installed package to replace.
+    pub package: String,
+    /// Ignore optional dependencies.
+    #[clap(long)]
+    pub ignore_optdepends: bool,
+    /// Print as JSON.
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// List the constraints which dependents place on a package.
+///
+/// For every installed package which depends on the package, list the
+/// dependency as written by the dependent package, e.g. `foo>=2.3`, and the
+/// provision by which the package satisfies the dependency, if any.
+#[derive(Args, Debug)]
+pub struct Constraints {
+    /// The package whose constraints to list.
+    pub package: String,
+    /// Ignore optional dependencies.
+    #[clap(long)]
+    pub ignore_optdepends: bool,
+    /// Only list dependencies with a version constraint.
+    #[clap(long)]
+    pub versioned: bool,
+    /// Print as JSON.
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// Match security advisories against installed packages.
+///
+/// Read an offline dump of advisories in the JSON format of the Arch Linux
+/// security tracker, e.g. from <https://security.archlinux.org/all.json>, and
+/// list all installed packages whose version an advisory affects.  For every
+/// affected package list all its dependents, and the explicitly installed
+/// packages which pull it in.
+#[derive(Args, Debug)]
+pub struct Audit {
+    /// The JSON file with advisories.
+    #[clap(long, value_name = "FILE")]
+    pub advisories: PathBuf,
+    /// Ignore optional dependencies.
+    #[clap(long)]
+    pub ignore_optdepends: bool,
+    /// Print as JSON.
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// Check whether dependents tolerate another version of a package.
+///
+/// Check the constraints which installed dependents place on the package
+/// against the version, and list all dependents which the version would not
+/// satisfy.  Check all versions of the package in the package cache as well,
+/// and list those which satisfy all dependents.
+///
+/// Without a cached archive of the version its provisions are not known, so
+/// mark dependencies satisfied by a provision as unknown.
+#[derive(Args, Debug)]
+pub struct CanDowngrade {
+    /// The installed package to downgrade.
+    pub package: String,
+    /// The version to downgrade to, including release, e.g. `2.3-1`.
+    pub version: String,
+    /// Ignore optional dependencies.
+    #[clap(long)]
+    pub ignore_optdepends: bool,
+    /// Print as JSON.
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// Check packages for conflicts.
+///
+/// Check the conflicts of every installed package against all other installed
+/// packages, including their provisions.  With `--add`, check packages from
+/// sync databases against installed packages and each other instead, as if
+/// they were installed.
+#[derive(Args, Debug)]
+pub struct Check {
+    /// Check this package from sync databases as if it were installed.
+    #[clap(long = "add", value_name = "PACKAGE")]
+    pub additions: Vec<String>,
+    /// Render conflicting packages and dependencies between them as dot.
+    #[clap(long)]
+    pub dot: bool,
+    /// Print as JSON.
+    #[clap(long, conflicts_with = "dot")]
+    pub json: bool,
+}
+
+/// List package groups.
+///
+/// List all package groups in sync databases and of installed packages, whether
+/// they are fully, partially, or not installed, their missing members, and
+/// their installed members which are orphans.
+#[derive(Args, Debug)]
+#[allow(
+    clippy::struct_excessive_bools,
+    reason = "These are independent command line flags"
+)]
+pub struct Groups {
+    /// Ignore optional dependencies.
+    #[clap(long)]
+    pub ignore_optdepends: bool,
+    /// Only list groups with installed members.
+    #[clap(long)]
+    pub installed: bool,
+    /// Render installed members of groups as clusters in dot.
+    #[clap(long)]
+    pub dot: bool,
+    /// Print as JSON.
+    #[clap(long, conflicts_with = "dot")]
+    pub json: bool,
+}
+
+/// Export a minimal list of explicitly installed packages.
+///
+/// List all explicitly installed packages which no other explicitly installed
+/// package requires, grouped by repository, with foreign packages in a separate
+/// section.  Use `packit reconcile` to converge a system with such a list.
+#[derive(Args, Debug)]
+pub struct Export {}
+
+/// Print pacman commands to converge installed packages with a package list.
+///
+/// Install all packages in the list which are not installed, mark all listed
+/// packages as explicitly installed, and mark all other explicitly installed
+/// packages as dependencies.  Remove all packages which become orphans.
+#[derive(Args, Debug)]
+pub struct Reconcile {
+    /// The package list, as written by `packit export`.
+    pub list: PathBuf,
+    /// Ignore optional dependencies when determining packages to remove.
+    #[clap(long)]
+    pub ignore_optdepends: bool,
+}
+
+/// Verify installed files against the package database.
+///
+/// Compare the files of installed packages with the mtree data in the local
+/// database: existence, type, permissions, owner, size, modification time and
+/// checksum.  Changed contents of backup files, e.g. configuration files in
+/// `/etc`, are expected and not reported.
+#[derive(Args, Debug)]
+pub struct Verify {
+    /// The packages to verify; verify all installed packages if empty.
+    pub packages: Vec<String>,
+    /// Print as JSON.
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// Find files and directories which no package owns.
+///
+/// Walk directories under the root directory and list all files and directories
+/// which no installed package owns.  List lost directories as a whole, without
+/// their contents.
+#[derive(Args, Debug)]
+pub struct Lostfiles {
+    /// Directories to search.
+    #[clap(
+        long = "dir",
+        value_name = "DIRECTORY",
+        default_values = ["/etc", "/opt", "/usr"]
+    )]
+    pub directories: Vec<String>,
+    /// Ignore files matching the pattern, e.g. `/etc/ssh/ssh_host_*`.
+    ///
+    /// `*` matches any sequence of characters, including `/`, and `?` matches
+    /// any single character.
+    #[clap(long, value_name = "PATTERN")]
+    pub ignore: Vec<String>,
+    /// Print as JSON.
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// Analyse package archives in the package cache.
+///
+/// Group package archives in all configured cache directories by package, and
+/// show which packages are still installed, orphaned or removed.  Mark
+/// archives which the retention policy removes: Keep the installed version
+/// and the most recent versions of installed packages, and drop archives of
+/// removed packages.
+#[derive(Args, Debug)]
+#[allow(
+    clippy::struct_excessive_bools,
+    reason = "These are independent command line flags"
+)]
+pub struct Cache {
+    /// Keep this many of the most recent versions of installed packages.
+    #[clap(long, value_name = "N", default_value_t = 3)]
+    pub keep: usize,
+    /// Keep this many of the most recent versions of removed packages.
+    #[clap(long, value_name = "N", default_value_t = 0)]
+    pub keep_removed: usize,
+    /// Treat orphans like removed packages.
+    #[clap(long)]
+    pub remove_orphans: bool,
+    /// Ignore optional dependencies when finding orphans.
+    #[clap(long)]
+    pub ignore_optdepends: bool,
+    /// Only print paths of archives and signatures to remove, one per line.
+    #[clap(long, conflicts_with = "json")]
+    pub removable: bool,
+    /// Print as JSON.
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// Find `.pacnew` and `.pacsave` files.
+///
+/// List `.pacnew` and `.pacsave` files next to backup files of installed
+/// packages, and whether the live file differs from the packaged original.
+/// Without packages, also list saved files in `/etc` which belong to no
+/// installed package, e.g. `.pacsave` files of removed packages.
+#[derive(Args, Debug)]
+pub struct Pacnew {
+    /// Only look at backup files of these packages.
+    pub packages: Vec<String>,
+    /// Print unified diffs between live and saved files.
+    #[clap(long, conflicts_with = "json")]
+    pub diff: bool,
+    /// Print as JSON.
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// Analyse build dependencies of source packages.
+///
+/// Read all `.SRCINFO` files under a directory, and print the dependencies of
+/// every package base on other package bases in the directory, including
+/// dependencies needed to build and check packages.  Ignore dependencies on
+/// packages from outside the directory.
+#[derive(Args, Debug)]
+#[allow(
+    clippy::struct_excessive_bools,
+    reason = "These are independent command line flags"
+)]
+pub struct Sources {
+    /// The directory to search for `.SRCINFO` files.
+    pub directory: PathBuf,
+    /// Only include package bases which this package base needs to build.
+    #[clap(long, value_name = "PKGBASE")]
+    pub needs: Option<String>,
+    /// Print the order in which to build package bases.
+    ///
+    /// Print package bases in batches, such that every batch only depends on
+    /// package bases in earlier batches or in the same batch.  A batch with
+    /// multiple package bases is a dependency cycle.
+    #[clap(long, conflicts_with = "dot")]
+    pub order: bool,
+    /// Include optional dependencies.
+    ///
+    /// Optional dependencies are not needed to build a package, so ignore them
+    /// by default.
+    #[clap(long)]
+    pub with_optdepends: bool,
+    /// Render the graph as dot.
+    #[clap(long)]
+    pub dot: bool,
+    /// Print as JSON.
+    #[clap(long, conflicts_with = "dot")]
+    pub json: bool,
+}
+
+/// Print the order in which to rebuild dependents of a package.
+///
+/// Print the package and all packages which depend on it in batches, such that
+/// every batch only depends on packages in earlier batches or in the same
+/// batch.  A batch with multiple packages is a dependency cycle.
+#[derive(Args, Debug)]
+pub struct RebuildOrder {
+    /// The package whose dependents to rebuild.
+    pub package: String,
+    /// Ignore optional dependencies.
+    #[clap(long)]
+    pub ignore_optdepends: bool,
+    /// Only include foreign packages, e.g. packages from the AUR or locally built packages.
+    #[clap(long)]
+    pub foreign: bool,
+    /// Print as JSON.
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// How to sort packages by their blast radius.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum RiskOrder {
+    /// Sort by score, i.e. affected packages weighted by affected explicit packages.
+    Score,
+    /// Sort by the number of transitive dependents.
+    Dependents,
+    /// Sort by the installed size of transitive dependents.
+    Size,
+    /// Sort by the number of affected explicitly installed packages.
+    Explicit,
+}
+
+/// Rank packages by their blast radius.
+///
+/// For every package count its transitive dependents, their installed size,
+/// and the explicitly installed packages among them, and score the package by
+/// the number of affected packages weighted by the number of affected explicit
+/// packages.  List the packages with the highest blast radius first.
+#[derive(Args, Debug)]
+pub struct Risk {
+    /// Ignore optional dependencies.
+    #[clap(long)]
+    pub ignore_optdepends: bool,
+    /// How to sort packages.
+    #[clap(long, value_enum, default_value_t = RiskOrder::Score)]
+    pub sort: RiskOrder,
+    /// How many packages to list; list all packages if zero.
+    #[clap(short = 'n', long, default_value_t = 20)]
+    pub top: usize,
+    /// Print as JSON.
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// Print statistics about the dependency graph.
+///
+/// Print the number of packages and dependencies, the share of explicitly
+/// installed packages, the number of strongly connected components, i.e.
+/// dependency cycles, and the longest chain of dependencies.
+///
+/// Then list the packages with the most direct and transitive dependents, and
+/// the packages with the highest betweenness centrality, i.e. the packages
+/// which lie on most of the shortest paths between other packages.
+#[derive(Args, Debug)]
+pub struct Stats {
+    /// Ignore optional dependencies.
+    #[clap(long)]
+    pub ignore_optdepends: bool,
+    /// How many packages to list for every metric.
+    #[clap(short = 'n', long, default_value_t = 10)]
+    pub top: usize,
+    /// Print as JSON.
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// Preview the effect of a system upgrade on the dependency graph.
+///
+/// Compare installed packages to sync databases, and build the dependency
+/// graph after upgrading all packages, without touching the system.  List all
+/// upgrades and replacements, packages which the upgrade newly pulls in,
+/// packages which become orphans because upgraded packages dropped
+/// dependencies, and new conflicts.
+///
+/// Ignore `IgnorePkg` and `IgnoreGroup` from `pacman.conf`.  Refresh sync
+/// databases first for an accurate preview, e.g. with `checkupdates`.
+#[derive(Args, Debug)]
+pub struct UpgradePreview {
+    /// Ignore optional dependencies.
+    #[clap(long)]
+    pub ignore_optdepends: bool,
+    /// Render the dependency graph after the upgrade as dot.
+    #[clap(long)]
+    pub dot: bool,
+    /// Print as JSON.
+    #[clap(long, conflicts_with = "dot")]
+    pub json: bool,
+}
+
 #[derive(Debug, Args)]
 /// Options for package graphs.
+#[allow(
+    clippy::struct_excessive_bools,
+    reason = "These are independent command line flags"
+)]
+#[clap(group = clap::ArgGroup::new("graph_format").args(["dot", "json"]))]
 pub struct GraphOptions {
     /// Ignore optional dependencies.
     #[clap(long)]
@@ -68,15 +682,26 @@ pub struct GraphOptions {
     /// Show less information.
     #[clap(short = 'q', long = "quiet")]
     pub quiet: bool,
+    /// Show the installed size of every package.
+    #[clap(long, conflicts_with = "quiet")]
+    pub sizes: bool,
     /// Render the graph as dot.
     #[clap(long)]
     pub dot: bool,
+    /// Print the graph as JSON.
+    #[clap(long, conflicts_with = "dot")]
+    pub json: bool,
+    /// Show provisions, e.g. `sh`, as nodes between packages and their providers.
+    #[clap(long, requires = "graph_format")]
+    pub provisions: bool,
 }
 
 impl GraphOptions {
     pub fn oneline_style(&self) -> PrintOneLine {
         if self.quiet {
             PrintOneLine::NameOnly
+        } else if self.sizes {
+            PrintOneLine::WithSize
         } else {
             PrintOneLine::WithVersion
         }
@@ -0,0 +1,409 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL-1.2 OR GPL-3.0
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Analyse package archives in the package cache.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Display,
+    hash::BuildHasher,
+    path::{Path, PathBuf},
+};
+
+use alpm::Db;
+use tracing::{debug, warn};
+
+/// The name, version and architecture of a package archive file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveName {
+    /// The name of the package.
+    pub name: String,
+    /// The full version of the package, including epoch and release.
+    pub version: String,
+    /// The architecture of the package.
+    pub arch: String,
+}
+
+impl ArchiveName {
+    /// Parse the file name of a package archive.
+    ///
+    /// Parse file names like `name-1:2.0-1-x86_64.pkg.tar.zst`, with any
+    /// compression extension after `.pkg.tar`.
+    ///
+    /// Return `None` if `file_name` is not a package archive, e.g. a signature.
+    #[must_use]
+    pub fn parse(file_name: &str) -> Option<Self> {
+        let (stem, extension) = file_name.split_once(".pkg.tar")?;
+        // Reject signatures and partial downloads, e.g. `.pkg.tar.zst.sig`
+        let compression = extension.strip_prefix('.');
+        if !extension.is_empty() && compression.is_none_or(|c| c.is_empty() || c.contains('.')) {
+            return None;
+        }
+        let mut parts = stem.rsplitn(4, '-');
+        let arch = parts.next()?;
+        let pkgrel = parts.next()?;
+        let pkgver = parts.next()?;
+        let name = parts.next()?;
+        (!name.is_empty()).then(|| ArchiveName {
+            name: name.to_owned(),
+            version: format!("{pkgver}-{pkgrel}"),
+            arch: arch.to_owned(),
+        })
+    }
+}
+
+/// A package archive in the cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedArchive {
+    /// The path of the archive.
+    pub path: PathBuf,
+    /// The path of the detached signature of the archive, if any.
+    pub signature: Option<PathBuf>,
+    /// The package in the archive.
+    pub package: ArchiveName,
+    /// The size of the archive in bytes, without the signature.
+    pub size: i64,
+}
+
+/// Find all package archives in `directories`.
+///
+/// Do not descend into subdirectories.  Skip directories which do not exist,
+/// and log and skip directories which cannot be read.
+pub fn scan_cache<P: AsRef<Path>>(directories: &[P]) -> Vec<CachedArchive> {
+    let mut archives = Vec::new();
+    for directory in directories {
+        let directory = directory.as_ref();
+        let entries = match std::fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                debug!("Skipping missing cache directory {}", directory.display());
+                continue;
+            }
+            Err(error) => {
+                warn!("Failed to read directory {}: {error}", directory.display());
+                continue;
+            }
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let Some(package) = entry.file_name().to_str().and_then(ArchiveName::parse) else {
+                continue;
+            };
+            let Ok(metadata) = entry.metadata() else {
+                warn!("Failed to read metadata of {}", entry.path().display());
+                continue;
+            };
+            if !metadata.is_file() {
+                continue;
+            }
+            let path = entry.path();
+            let mut signature = path.clone().into_os_string();
+            signature.push(".sig");
+            let signature = PathBuf::from(signature);
+            archives.push(CachedArchive {
+                signature: signature.exists().then_some(signature),
+                path,
+                package,
+                size: i64::try_from(metadata.len()).unwrap_or(i64::MAX),
+            });
+        }
+    }
+    archives
+}
+
+/// Whether the package of cached archives is still installed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    /// The package is installed, and no orphan.
+    Installed,
+    /// The package is installed, but an orphan.
+    Orphan,
+    /// The package is not installed.
+    Removed,
+}
+
+impl Display for CacheStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            CacheStatus::Installed => write!(f, "installed"),
+            CacheStatus::Orphan => write!(f, "orphan"),
+            CacheStatus::Removed => write!(f, "removed"),
+        }
+    }
+}
+
+/// All cached archives of a package.
+#[derive(Debug, Clone)]
+pub struct CachedPackage {
+    /// The name of the package.
+    pub name: String,
+    /// Whether the package is installed.
+    pub status: CacheStatus,
+    /// The installed version of the package, if any.
+    pub installed_version: Option<String>,
+    /// All cached archives of the package, newest version first.
+    pub archives: Vec<CachedArchive>,
+}
+
+impl CachedPackage {
+    /// The total size of all cached archives of this package.
+    #[must_use]
+    pub fn size(&self) -> i64 {
+        self.archives.iter().map(|archive| archive.size).sum()
+    }
+}
+
+/// Group cached `archives` by package.
+///
+/// Look up every package in `localdb` to determine whether it is installed,
+/// and mark installed packages in `orphans` as orphans.
+///
+/// Return packages sorted by name, with archives sorted by descending version.
+pub fn cached_packages<S: BuildHasher>(
+    archives: Vec<CachedArchive>,
+    localdb: &Db,
+    orphans: &HashSet<&str, S>,
+) -> Vec<CachedPackage> {
+    let mut by_name = BTreeMap::<String, Vec<CachedArchive>>::new();
+    for archive in archives {
+        by_name
+            .entry(archive.package.name.clone())
+            .or_default()
+            .push(archive);
+    }
+    by_name
+        .into_iter()
+        .map(|(name, mut archives)| {
+            archives.sort_by(|a, b| {
+                alpm::vercmp(b.package.version.as_str(), a.package.version.as_str())
+                    .then_with(|| a.path.cmp(&b.path))
+            });
+            let installed = localdb.pkg(name.as_str()).ok();
+            let status = match installed {
+                None => CacheStatus::Removed,
+                Some(_) if orphans.contains(name.as_str()) => CacheStatus::Orphan,
+                Some(_) => CacheStatus::Installed,
+            };
+            CachedPackage {
+                installed_version: installed.map(|package| package.version().to_string()),
+                name,
+                status,
+                archives,
+            }
+        })
+        .collect()
+}
+
+/// Which cached archives to keep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// How many of the most recent versions to keep for installed packages.
+    ///
+    /// Always keep the installed version in addition.
+    pub keep: usize,
+    /// How many of the most recent versions to keep for removed packages.
+    pub keep_removed: usize,
+    /// Whether to treat orphans like removed packages.
+    ///
+    /// If true, remove archives of orphans even for the installed version.
+    pub remove_orphans: bool,
+}
+
+impl RetentionPolicy {
+    /// Get all archives of `package` which this policy removes.
+    ///
+    /// Count versions rather than archives, so that all archives of the same
+    /// version, e.g. in different cache directories, share the same fate.
+    #[must_use]
+    pub fn removable<'a>(&self, package: &'a CachedPackage) -> Vec<&'a CachedArchive> {
+        let removed = match package.status {
+            CacheStatus::Installed => false,
+            CacheStatus::Orphan => self.remove_orphans,
+            CacheStatus::Removed => true,
+        };
+        let keep = if removed {
+            self.keep_removed
+        } else {
+            self.keep
+        };
+        let mut kept_versions = Vec::with_capacity(keep);
+        for archive in &package.archives {
+            let version = archive.package.version.as_str();
+            if kept_versions.len() < keep && !kept_versions.contains(&version) {
+                kept_versions.push(version);
+            }
+        }
+        package
+            .archives
+            .iter()
+            .filter(|archive| {
+                let version = archive.package.version.as_str();
+                let installed = !removed && package.installed_version.as_deref() == Some(version);
+                !installed && !kept_versions.contains(&version)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_archive_name() {
+        assert_eq!(
+            ArchiveName::parse("foo-1.0-1-x86_64.pkg.tar.zst"),
+            Some(ArchiveName {
+                name: "foo".to_owned(),
+                version: "1.0-1".to_owned(),
+                arch: "x86_64".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_archive_name_with_epoch() {
+        assert_eq!(
+            ArchiveName::parse("foo-2:1.0.r3.gabc-1.1-any.pkg.tar.xz"),
+            Some(ArchiveName {
+                name: "foo".to_owned(),
+                version: "2:1.0.r3.gabc-1.1".to_owned(),
+                arch: "any".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_archive_name_with_hyphens() {
+        assert_eq!(
+            ArchiveName::parse("python-foo-bar-1.0-1-any.pkg.tar.gz"),
+            Some(ArchiveName {
+                name: "python-foo-bar".to_owned(),
+                version: "1.0-1".to_owned(),
+                arch: "any".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_archive_name_uncompressed() {
+        assert_eq!(
+            ArchiveName::parse("foo-1.0-1-x86_64.pkg.tar").map(|name| name.name),
+            Some("foo".to_owned())
+        );
+    }
+
+    #[test]
+    fn parse_archive_name_rejects_non_archives() {
+        for file_name in [
+            "foo-1.0-1-x86_64.pkg.tar.zst.sig",
+            "foo-1.0-1-x86_64.pkg.tar.zst.part",
+            "foo-1.0-1-x86_64.pkg.tar.",
+            "foo-1.0-1-x86_64.tar.zst",
+            "download-abcdef",
+            "1.0-1-x86_64.pkg.tar.zst",
+            "-1.0-1-x86_64.pkg.tar.zst",
+        ] {
+            assert_eq!(ArchiveName::parse(file_name), None, "{file_name}");
+        }
+    }
+
+    fn archive(version: &str, directory: &str) -> CachedArchive {
+        CachedArchive {
+            path: PathBuf::from(format!("{directory}/foo-{version}-any.pkg.tar.zst")),
+            signature: None,
+            package: ArchiveName {
+                name: "foo".to_owned(),
+                version: version.to_owned(),
+                arch: "any".to_owned(),
+            },
+            size: 100,
+        }
+    }
+
+    fn package(status: CacheStatus, installed_version: Option<&str>) -> CachedPackage {
+        CachedPackage {
+            name: "foo".to_owned(),
+            status,
+            installed_version: installed_version.map(ToOwned::to_owned),
+            archives: vec![
+                archive("4.0-1", "/a"),
+                archive("3.0-1", "/a"),
+                archive("3.0-1", "/b"),
+                archive("2.0-1", "/a"),
+                archive("1.0-1", "/a"),
+            ],
+        }
+    }
+
+    fn removable_versions(policy: RetentionPolicy, package: &CachedPackage) -> Vec<&str> {
+        policy
+            .removable(package)
+            .into_iter()
+            .map(|archive| archive.package.version.as_str())
+            .collect()
+    }
+
+    const POLICY: RetentionPolicy = RetentionPolicy {
+        keep: 2,
+        keep_removed: 1,
+        remove_orphans: false,
+    };
+
+    #[test]
+    fn removable_keeps_recent_versions_of_installed_package() {
+        let package = package(CacheStatus::Installed, Some("4.0-1"));
+        assert_eq!(removable_versions(POLICY, &package), vec!["2.0-1", "1.0-1"]);
+    }
+
+    #[test]
+    fn removable_keeps_installed_version_in_addition() {
+        let package = package(CacheStatus::Installed, Some("1.0-1"));
+        assert_eq!(removable_versions(POLICY, &package), vec!["2.0-1"]);
+    }
+
+    #[test]
+    fn removable_keep_nothing() {
+        let policy = RetentionPolicy { keep: 0, ..POLICY };
+        let package = package(CacheStatus::Installed, Some("3.0-1"));
+        assert_eq!(
+            removable_versions(policy, &package),
+            vec!["4.0-1", "2.0-1", "1.0-1"]
+        );
+    }
+
+    #[test]
+    fn removable_of_removed_package() {
+        let package = package(CacheStatus::Removed, None);
+        assert_eq!(
+            removable_versions(POLICY, &package),
+            vec!["3.0-1", "3.0-1", "2.0-1", "1.0-1"]
+        );
+        let policy = RetentionPolicy {
+            keep_removed: 0,
+            ..POLICY
+        };
+        assert_eq!(removable_versions(policy, &package).len(), 5);
+    }
+
+    #[test]
+    fn removable_of_orphan() {
+        let package = package(CacheStatus::Orphan, Some("1.0-1"));
+        assert_eq!(removable_versions(POLICY, &package), vec!["2.0-1"]);
+        let policy = RetentionPolicy {
+            remove_orphans: true,
+            ..POLICY
+        };
+        assert_eq!(
+            removable_versions(policy, &package),
+            vec!["3.0-1", "3.0-1", "2.0-1", "1.0-1"]
+        );
+    }
+
+    #[test]
+    fn size_of_cached_package() {
+        assert_eq!(package(CacheStatus::Removed, None).size(), 500);
+    }
+}
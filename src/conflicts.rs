@@ -0,0 +1,59 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL-1.2 OR GPL-3.0
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Conflicts between packages.
+
+use alpm::{Dep, Package};
+use tracing::debug;
+
+use crate::{alpm::satisfies_package, graph::PackageNode};
+
+/// A conflict between two packages.
+#[derive(Debug, Clone, Copy)]
+pub struct Conflict<'a> {
+    /// The package which declares the conflict.
+    pub package: PackageNode<'a>,
+    /// The package which satisfies the conflict, either directly or via its provisions.
+    pub conflicting: PackageNode<'a>,
+    /// The conflict as declared by `package`.
+    pub conflict: &'a Dep,
+}
+
+/// Find all conflicts between `packages`.
+///
+/// Check the `conflicts` of every package against all other packages, including
+/// their provisions.  Ignore conflicts of a package with itself, e.g. if a
+/// package conflicts with something it provides, and packages of the same name.
+///
+/// Return conflicts sorted by the names of the declaring and conflicting packages.
+pub fn find_conflicts<'a>(packages: &[&'a Package]) -> Vec<Conflict<'a>> {
+    let mut conflicts = Vec::new();
+    for package in packages {
+        for conflict in package.conflicts() {
+            for other in packages {
+                if other.name() != package.name() && satisfies_package(conflict, other) {
+                    debug!(
+                        package = package.name(),
+                        "{} conflicts with {} via {}",
+                        package.name(),
+                        other.name(),
+                        conflict.name()
+                    );
+                    conflicts.push(Conflict {
+                        package: PackageNode::new(package),
+                        conflicting: PackageNode::new(other),
+                        conflict,
+                    });
+                }
+            }
+        }
+    }
+    conflicts.sort_by(|a, b| {
+        (a.package.package().name(), a.conflicting.package().name())
+            .cmp(&(b.package.package().name(), b.conflicting.package().name()))
+    });
+    conflicts
+}
@@ -6,16 +6,62 @@
 
 //! Analyse dependencies of ALPM packages.
 
-use std::collections::{HashSet, VecDeque};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Display,
+    hash::{BuildHasher, Hash},
+};
 
-use alpm::{Package, PackageReason};
-use petgraph::visit::{
-    Bfs, GraphRef, IntoNeighbors, IntoNeighborsDirected, IntoNodeIdentifiers, NodeCount,
-    NodeFiltered, Reversed, VisitMap as _, Visitable, Walker,
+use alpm::{AlpmList, Db, Package, PackageReason};
+use petgraph::{
+    algo::tarjan_scc,
+    visit::{
+        Bfs, GraphRef, IntoNeighbors, IntoNeighborsDirected, IntoNodeIdentifiers, NodeCount,
+        NodeFiltered, NodeIndexable, Reversed, VisitMap as _, Visitable, Walker,
+    },
 };
 use tracing::{debug, debug_span};
 
-use crate::graph::PackageNode;
+use crate::{alpm::is_foreign, graph::PackageNode};
+
+/// The position of a package in the dependency graph.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GraphPosition {
+    /// An explicitly installed package.
+    Explicit,
+    /// A package installed as dependency of another package.
+    Dependency,
+    /// A package not required by any explicitly installed package.
+    Orphan,
+}
+
+impl GraphPosition {
+    /// Get the position of `node`, given all `orphans` of the graph.
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    #[must_use]
+    pub fn of<S: BuildHasher>(node: PackageNode, orphans: &HashSet<PackageNode, S>) -> Self {
+        if orphans.contains(&node) {
+            GraphPosition::Orphan
+        } else if node.reason() == PackageReason::Explicit {
+            GraphPosition::Explicit
+        } else {
+            GraphPosition::Dependency
+        }
+    }
+
+    /// A short description of this position.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GraphPosition::Explicit => "explicit",
+            GraphPosition::Dependency => "dependency",
+            GraphPosition::Orphan => "orphan",
+        }
+    }
+}
 
 /// Get the subgraph of all orphans in a dependency graph.
 ///
@@ -32,31 +78,38 @@ where
         + IntoNeighbors
         + IntoNodeIdentifiers,
 {
-    #[allow(
-        clippy::mutable_key_type,
-        reason = "We do not mutate the package pointer while traversing the graph"
-    )]
+    orphans_with_roots(graph, |package| package.reason() == PackageReason::Explicit)
+}
+
+/// Get the subgraph of all orphans in a dependency graph, with custom roots.
+///
+/// Like [`orphans`], but consider all packages for which `is_root` returns
+/// true as roots, instead of all explicitly installed packages.
+pub fn orphans_with_roots<G, F>(graph: G, is_root: F) -> NodeFiltered<G, impl Fn(G::NodeId) -> bool>
+where
+    G: GraphRef + NodeCount + Visitable + IntoNeighbors + IntoNodeIdentifiers,
+    G::NodeId: Hash + Eq + Display,
+    F: Fn(G::NodeId) -> bool,
+{
     let mut marked_pkgs = HashSet::with_capacity(graph.node_count());
-    let explicit_pkgs = graph
-        .node_identifiers()
-        .filter(|p| p.reason() == PackageReason::Explicit);
+    let root_pkgs = graph.node_identifiers().filter(|p| is_root(*p));
     // We manually initialize BFS, because we'd like to retain the visit map
-    // for all explicit packages, so as to avoid repeatedly traversing branches
-    // that were already marked by another explicit package.
+    // for all root packages, so as to avoid repeatedly traversing branches
+    // that were already marked by another root package.
     let mut bfs = Bfs {
         discovered: graph.visit_map(),
         stack: VecDeque::new(),
     };
-    for node in explicit_pkgs {
+    for node in root_pkgs {
         bfs.stack.push_front(node);
         bfs.discovered.visit(node);
-        debug!("Marking from {}", node.name());
-        let _guard = debug_span!("mark-bfs", package = node.name()).entered();
+        debug!("Marking from {node}");
+        let _guard = debug_span!("mark-bfs", package = %node).entered();
         marked_pkgs.insert(node);
         let mut bfs = Bfs::new(&graph, node);
         while let Some(node) = bfs.next(&graph) {
             if marked_pkgs.insert(node) {
-                debug!(package = node.name(), "Marking {}", node.name());
+                debug!(package = %node, "Marking {node}");
             }
         }
     }
@@ -90,3 +143,364 @@ where
     let reachable_nodes = bfs.iter(&reversed).collect::<HashSet<_>>();
     NodeFiltered::from_fn(graph, move |node| reachable_nodes.contains(&node))
 }
+
+/// Determine the order in which to rebuild dependents of a node.
+///
+/// Return batches of `node` and all nodes which transitively depend on it in
+/// topological order, i.e. every batch only depends on nodes in the same or in
+/// earlier batches.  The first batch contains `node`.
+///
+/// Every batch is a strongly connected component of the dependents of `node`,
+/// i.e. either a single node or a dependency cycle whose nodes need to be
+/// rebuilt together.  Nodes in a batch are sorted by `key`.
+pub fn rebuild_batches<G, F, K>(graph: G, node: G::NodeId, mut key: F) -> Vec<Vec<G::NodeId>>
+where
+    G: GraphRef + NodeIndexable + Visitable + IntoNeighborsDirected + IntoNodeIdentifiers,
+    G::NodeId: Hash + Eq,
+    F: FnMut(&G::NodeId) -> K,
+    K: Ord,
+{
+    let reversed = Reversed(graph);
+    let reachable_nodes = Bfs::new(reversed, node)
+        .iter(reversed)
+        .collect::<HashSet<_>>();
+    let dependents = NodeFiltered::from_fn(graph, |node| reachable_nodes.contains(&node));
+    // Tarjan's algorithm returns components in reverse topological order, i.e.
+    // dependencies come before their dependents.  Since all dependents reach
+    // `node`, its component is the only one without dependencies, and thus
+    // comes first.
+    let mut batches = tarjan_scc(&dependents);
+    for batch in &mut batches {
+        batch.sort_by_key(&mut key);
+    }
+    batches
+}
+
+/// Determine the order in which to rebuild dependents of a package.
+///
+/// See [`rebuild_batches`]; packages in a batch are sorted by name.
+pub fn rebuild_order<'a, G>(graph: G, package: &'a Package) -> Vec<Vec<PackageNode<'a>>>
+where
+    G: GraphRef
+        + NodeIndexable
+        + Visitable<NodeId = PackageNode<'a>>
+        + IntoNeighborsDirected
+        + IntoNodeIdentifiers,
+{
+    rebuild_batches(graph, PackageNode::new(package), |node| {
+        node.package().name()
+    })
+}
+
+/// Get all packages a package depends on.
+///
+/// Return the subgraph of all packages transitively reachable from `package`,
+/// including `package` itself.
+pub fn dependencies<'a, G>(
+    graph: G,
+    package: &'a Package,
+) -> NodeFiltered<G, impl Fn(PackageNode<'a>) -> bool>
+where
+    G: GraphRef + Visitable<NodeId = PackageNode<'a>> + IntoNeighbors,
+{
+    let bfs = Bfs::new(&graph, PackageNode::new(package));
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let reachable_nodes = bfs.iter(&graph).collect::<HashSet<_>>();
+    NodeFiltered::from_fn(graph, move |node| reachable_nodes.contains(&node))
+}
+
+/// Get all dependencies which only a package keeps installed.
+///
+/// Return the subgraph of `package` and all its transitive dependencies which
+/// are not reachable from any other explicitly installed package, i.e. those
+/// dependencies which would become orphans if `package` were removed.
+pub fn exclusive_dependencies<'a, G>(
+    graph: G,
+    package: &'a Package,
+) -> NodeFiltered<G, impl Fn(PackageNode<'a>) -> bool>
+where
+    G: GraphRef + Visitable<NodeId = PackageNode<'a>> + IntoNeighbors + IntoNodeIdentifiers,
+{
+    let package = PackageNode::new(package);
+    let without_package = NodeFiltered::from_fn(graph, |node| node != package);
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let mut marked_pkgs = HashSet::new();
+    let explicit_pkgs = graph
+        .node_identifiers()
+        .filter(|p| p.reason() == PackageReason::Explicit && *p != package);
+    for node in explicit_pkgs {
+        // If the package was already marked, so were all its dependencies
+        if !marked_pkgs.contains(&node) {
+            marked_pkgs.extend(Bfs::new(&without_package, node).iter(&without_package));
+        }
+    }
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let exclusive_nodes = Bfs::new(&graph, package)
+        .iter(&graph)
+        .filter(|node| !marked_pkgs.contains(node))
+        .collect::<HashSet<_>>();
+    NodeFiltered::from_fn(graph, move |node| exclusive_nodes.contains(&node))
+}
+
+/// Get the total installed size of all packages in `graph`.
+///
+/// See [`alpm::Pkg::isize`].
+pub fn installed_size<'a, G>(graph: G) -> i64
+where
+    G: IntoNodeIdentifiers<NodeId = PackageNode<'a>>,
+{
+    graph.node_identifiers().map(|node| node.isize()).sum()
+}
+
+/// The installed size of the dependency closure of an explicit package.
+#[derive(Debug, Clone, Copy)]
+pub struct ClosureSize<'a> {
+    /// The explicitly installed package.
+    pub package: PackageNode<'a>,
+    /// The number of packages in the closure, including the package itself.
+    pub packages: usize,
+    /// The installed size of all packages in the closure.
+    pub total_size: i64,
+    /// The installed size of all packages in the closure which no other explicit
+    /// package depends on.
+    ///
+    /// This is the size which removing the package along with its dependencies
+    /// would free.
+    pub exclusive_size: i64,
+}
+
+/// Compute the size of the dependency closure of every explicit package.
+///
+/// Return the closure sizes in no particular order.
+pub fn closure_sizes<'a, G>(graph: G) -> Vec<ClosureSize<'a>>
+where
+    G: GraphRef + Visitable<NodeId = PackageNode<'a>> + IntoNeighbors + IntoNodeIdentifiers,
+{
+    let closures = graph
+        .node_identifiers()
+        .filter(|p| p.reason() == PackageReason::Explicit)
+        .map(|node| {
+            let _guard = debug_span!("closure", package = node.name()).entered();
+            let closure = Bfs::new(&graph, node).iter(&graph).collect::<Vec<_>>();
+            debug!(
+                "{} has {} packages in its closure",
+                node.name(),
+                closure.len()
+            );
+            (node, closure)
+        })
+        .collect::<Vec<_>>();
+
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let mut roots_per_node = HashMap::<PackageNode<'a>, usize>::new();
+    for node in closures.iter().flat_map(|(_, closure)| closure) {
+        *roots_per_node.entry(*node).or_default() += 1;
+    }
+
+    closures
+        .into_iter()
+        .map(|(package, closure)| ClosureSize {
+            package,
+            packages: closure.len(),
+            total_size: closure.iter().map(|node| node.isize()).sum(),
+            exclusive_size: closure
+                .iter()
+                .filter(|node| roots_per_node.get(node) == Some(&1))
+                .map(|node| node.isize())
+                .sum(),
+        })
+        .collect()
+}
+
+/// How much of the system a package affects.
+#[derive(Debug, Clone, Copy)]
+pub struct BlastRadius<'a> {
+    /// The package.
+    pub package: PackageNode<'a>,
+    /// The number of transitive dependents, excluding the package itself.
+    pub dependents: usize,
+    /// The installed size of all transitive dependents, excluding the package itself.
+    pub dependents_size: i64,
+    /// The number of explicitly installed packages among the package and its
+    /// transitive dependents.
+    pub explicit: usize,
+}
+
+impl BlastRadius<'_> {
+    /// The score of this blast radius.
+    ///
+    /// Weight the number of affected packages, i.e. the package and its
+    /// transitive dependents, by the number of affected explicitly installed
+    /// packages.  The score of orphans is zero.
+    #[must_use]
+    pub fn score(&self) -> usize {
+        (self.dependents + 1).saturating_mul(self.explicit)
+    }
+}
+
+/// Compute the blast radius of every package in `graph`.
+///
+/// For every package collect all its transitive dependents (see [`dependents`])
+/// and count them, sum their installed size, and count the explicitly installed
+/// packages among the package and its dependents.
+///
+/// Return blast radii in no particular order.
+pub fn blast_radii<'a, G>(graph: G) -> Vec<BlastRadius<'a>>
+where
+    G: GraphRef + Visitable<NodeId = PackageNode<'a>> + IntoNeighborsDirected + IntoNodeIdentifiers,
+{
+    let reversed = Reversed(graph);
+    graph
+        .node_identifiers()
+        .map(|node| {
+            let mut radius = BlastRadius {
+                package: node,
+                dependents: 0,
+                dependents_size: 0,
+                explicit: 0,
+            };
+            for dependent in Bfs::new(reversed, node).iter(reversed) {
+                if dependent.reason() == PackageReason::Explicit {
+                    radius.explicit += 1;
+                }
+                if dependent != node {
+                    radius.dependents += 1;
+                    radius.dependents_size += dependent.isize();
+                }
+            }
+            debug!(
+                package = node.name(),
+                "{} has {} dependents, {} of them explicit",
+                node.name(),
+                radius.dependents,
+                radius.explicit
+            );
+            radius
+        })
+        .collect()
+}
+
+/// A foreign package with its dependencies.
+#[derive(Debug)]
+pub struct ForeignPackage<'a> {
+    /// The foreign package.
+    pub package: PackageNode<'a>,
+    /// Whether the foreign package is an orphan.
+    pub orphan: bool,
+    /// Dependencies which only this package keeps installed, sorted by name.
+    pub exclusive_dependencies: Vec<PackageNode<'a>>,
+    /// Dependencies from sync databases, sorted by name.
+    pub repo_dependencies: Vec<PackageNode<'a>>,
+}
+
+/// Find all foreign packages in `graph`, i.e. packages not in any of `syncdbs`.
+///
+/// Return foreign packages sorted by name, along with the dependencies which
+/// only they keep installed, and their dependencies from sync databases.
+pub fn foreign_packages<'a, G>(graph: G, syncdbs: AlpmList<&Db>) -> Vec<ForeignPackage<'a>>
+where
+    G: GraphRef
+        + NodeCount
+        + Visitable<NodeId = PackageNode<'a>>
+        + IntoNeighbors
+        + IntoNodeIdentifiers,
+{
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let orphans = orphans(graph).node_identifiers().collect::<HashSet<_>>();
+    let sorted_by_name = |mut nodes: Vec<PackageNode<'a>>| {
+        nodes.sort_by_key(|node| node.package().name());
+        nodes
+    };
+    let mut packages = graph
+        .node_identifiers()
+        .filter(|node| is_foreign(node, syncdbs))
+        .map(|node| ForeignPackage {
+            package: node,
+            orphan: orphans.contains(&node),
+            exclusive_dependencies: sorted_by_name(
+                exclusive_dependencies(graph, node.package())
+                    .node_identifiers()
+                    .filter(|dependency| *dependency != node)
+                    .collect(),
+            ),
+            repo_dependencies: sorted_by_name(
+                dependencies(graph, node.package())
+                    .node_identifiers()
+                    .filter(|dependency| !is_foreign(dependency, syncdbs))
+                    .collect(),
+            ),
+        })
+        .collect::<Vec<_>>();
+    packages.sort_by_key(|package| package.package.package().name());
+    packages
+}
+
+#[cfg(test)]
+mod tests {
+    use petgraph::prelude::DiGraphMap;
+
+    use super::*;
+
+    fn batches<'a>(edges: &[(&'a str, &'a str)], node: &'a str) -> Vec<Vec<&'a str>> {
+        let graph = DiGraphMap::<&str, ()>::from_edges(edges);
+        rebuild_batches(&graph, node, |node| *node)
+    }
+
+    #[test]
+    fn rebuild_batches_of_chain() {
+        assert_eq!(
+            batches(&[("c", "b"), ("b", "a"), ("x", "c")], "b"),
+            vec![vec!["b"], vec!["c"], vec!["x"]]
+        );
+    }
+
+    #[test]
+    fn rebuild_batches_ignore_dependencies() {
+        assert_eq!(
+            batches(&[("b", "a"), ("a", "y"), ("z", "y")], "a"),
+            vec![vec!["a"], vec!["b"]]
+        );
+    }
+
+    #[test]
+    fn rebuild_batches_with_cycle_among_dependents() {
+        assert_eq!(
+            batches(
+                &[("c", "a"), ("b", "a"), ("b", "c"), ("c", "b"), ("d", "b")],
+                "a"
+            ),
+            vec![vec!["a"], vec!["b", "c"], vec!["d"]]
+        );
+    }
+
+    #[test]
+    fn rebuild_batches_with_cycle_through_node() {
+        assert_eq!(
+            batches(&[("b", "a"), ("a", "b"), ("c", "a"), ("a", "x")], "b"),
+            vec![vec!["a", "b"], vec!["c"]]
+        );
+    }
+
+    #[test]
+    fn rebuild_batches_of_diamond() {
+        let order = batches(&[("b", "a"), ("c", "a"), ("d", "b"), ("d", "c")], "a");
+        assert_eq!(order.len(), 4);
+        assert_eq!(order.first(), Some(&vec!["a"]));
+        assert_eq!(order.last(), Some(&vec!["d"]));
+    }
+}
@@ -0,0 +1,323 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL-1.2 OR GPL-3.0
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Line-based unified diffs.
+
+/// The number of unchanged lines around changes in a hunk.
+const CONTEXT: usize = 3;
+
+/// A single line of a diff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    /// The line at the given indexes is unchanged.
+    Equal(usize, usize),
+    /// The line at the given index of the old text was deleted.
+    Delete(usize),
+    /// The line at the given index of the new text was inserted.
+    Insert(usize),
+}
+
+/// The maximum number of edits for which we compute a shortest edit script.
+///
+/// The trace of Myers' algorithm takes memory quadratic in the number of
+/// edits, so we give up on texts which differ too much.
+const MAX_EDITS: usize = 2000;
+
+/// Compute the shortest edit script from `old` to `new` with Myers' algorithm.
+///
+/// Return `None` if the shortest edit script has more than [`MAX_EDITS`]
+/// edits, or if the internal bookkeeping goes out of bounds, which should
+/// never happen.
+fn myers(old: &[&str], new: &[&str]) -> Option<Vec<Edit>> {
+    let (old_len, new_len) = (old.len(), new.len());
+    let max = old_len + new_len;
+    let offset = isize::try_from(max).ok()?;
+    let index = |k: isize| usize::try_from(k + offset).ok();
+    let mut endpoints = vec![0_usize; 2 * max + 2];
+    // The endpoints of diagonals -d to d before every step d
+    let mut trace = Vec::new();
+    let mut done = false;
+    'search: for d in 0..=offset.min(isize::try_from(MAX_EDITS).ok()?) {
+        trace.push(endpoints.get(index(-d)?..=index(d)?)?.to_vec());
+        for k in (-d..=d).step_by(2) {
+            let down = k == -d
+                || (k != d && endpoints.get(index(k - 1)?)? < endpoints.get(index(k + 1)?)?);
+            let mut x = if down {
+                *endpoints.get(index(k + 1)?)?
+            } else {
+                endpoints.get(index(k - 1)?)? + 1
+            };
+            let mut y = x.checked_add_signed(-k)?;
+            while x < old_len && y < new_len && old.get(x) == new.get(y) {
+                x += 1;
+                y += 1;
+            }
+            *endpoints.get_mut(index(k)?)? = x;
+            if old_len <= x && new_len <= y {
+                done = true;
+                break 'search;
+            }
+        }
+    }
+    if !done {
+        return None;
+    }
+
+    let mut edits = Vec::with_capacity(max);
+    let (mut x, mut y) = (old_len, new_len);
+    for (d, endpoints) in trace.iter().enumerate().rev() {
+        if d == 0 {
+            // Only the leading snake of equal lines remains
+            while 0 < x && 0 < y {
+                x -= 1;
+                y -= 1;
+                edits.push(Edit::Equal(x, y));
+            }
+            break;
+        }
+        let d = isize::try_from(d).ok()?;
+        let endpoint = |k: isize| endpoints.get(usize::try_from(k + d).ok()?).copied();
+        let k = isize::try_from(x).ok()? - isize::try_from(y).ok()?;
+        let down = k == -d || (k != d && endpoint(k - 1)? < endpoint(k + 1)?);
+        let previous_k = if down { k + 1 } else { k - 1 };
+        let previous_x = endpoint(previous_k)?;
+        let previous_y = previous_x.checked_add_signed(-previous_k)?;
+        while previous_x < x && previous_y < y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Equal(x, y));
+        }
+        if x == previous_x {
+            edits.push(Edit::Insert(previous_y));
+        } else {
+            edits.push(Edit::Delete(previous_x));
+        }
+        (x, y) = (previous_x, previous_y);
+    }
+    edits.reverse();
+    Some(edits)
+}
+
+/// Format the start and length of a hunk range.
+fn format_range(start: usize, length: usize) -> String {
+    // An empty range starts at the line before the hunk
+    let start = if length == 0 { start } else { start + 1 };
+    if length == 1 {
+        start.to_string()
+    } else {
+        format!("{start},{length}")
+    }
+}
+
+/// Create a unified diff between two texts.
+///
+/// Compare `old` and `new` line by line, and return a unified diff with three
+/// lines of context, and `old_name` and `new_name` in the header.  Return an
+/// empty string if both texts have the same lines.
+///
+/// If the texts differ too much to compute a shortest edit script, replace
+/// all old lines with all new lines.
+#[must_use]
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let old_lines = old.lines().collect::<Vec<_>>();
+    let new_lines = new.lines().collect::<Vec<_>>();
+    let edits = myers(&old_lines, &new_lines).unwrap_or_else(|| {
+        (0..old_lines.len())
+            .map(Edit::Delete)
+            .chain((0..new_lines.len()).map(Edit::Insert))
+            .collect()
+    });
+    let changes = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, Edit::Equal(..)))
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    let mut diff = String::new();
+    let Some(first_change) = changes.first() else {
+        return diff;
+    };
+    for (prefix, name) in [("--- ", old_name), ("+++ ", new_name)] {
+        diff.push_str(prefix);
+        diff.push_str(name);
+        diff.push('\n');
+    }
+
+    // Split changes into hunks, and merge changes whose context overlaps
+    let mut hunks = vec![(*first_change, *first_change)];
+    for change in changes.iter().skip(1) {
+        match hunks.last_mut() {
+            Some((_, end)) if *change <= *end + 2 * CONTEXT + 1 => *end = *change,
+            _ => hunks.push((*change, *change)),
+        }
+    }
+    for (start, end) in hunks {
+        let start = start.saturating_sub(CONTEXT);
+        let end = (end + CONTEXT + 1).min(edits.len());
+        let hunk = edits.get(start..end).unwrap_or_default();
+        // The position of the hunk in the old and new text, from the number
+        // of old and new lines before the hunk.
+        let before = edits.get(..start).unwrap_or_default();
+        let old_start = before
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Insert(_)))
+            .count();
+        let new_start = before
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Delete(_)))
+            .count();
+        let old_length = hunk
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Insert(_)))
+            .count();
+        let new_length = hunk
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Delete(_)))
+            .count();
+        diff.push_str("@@ -");
+        diff.push_str(&format_range(old_start, old_length));
+        diff.push_str(" +");
+        diff.push_str(&format_range(new_start, new_length));
+        diff.push_str(" @@\n");
+        for edit in hunk {
+            let (prefix, line) = match *edit {
+                Edit::Equal(x, _) => (' ', old_lines.get(x)),
+                Edit::Delete(x) => ('-', old_lines.get(x)),
+                Edit::Insert(y) => ('+', new_lines.get(y)),
+            };
+            diff.push(prefix);
+            diff.push_str(line.unwrap_or(&""));
+            diff.push('\n');
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Apply `edits` to get the lines of the old and the new text back.
+    fn apply<'a>(
+        old: &[&'a str],
+        new: &[&'a str],
+        edits: &[Edit],
+    ) -> (Vec<Option<&'a str>>, Vec<Option<&'a str>>) {
+        let mut from = Vec::new();
+        let mut to = Vec::new();
+        for edit in edits {
+            match *edit {
+                Edit::Equal(x, y) => {
+                    assert_eq!(old.get(x), new.get(y));
+                    from.push(old.get(x).copied());
+                    to.push(new.get(y).copied());
+                }
+                Edit::Delete(x) => from.push(old.get(x).copied()),
+                Edit::Insert(y) => to.push(new.get(y).copied()),
+            }
+        }
+        (from, to)
+    }
+
+    #[test]
+    fn myers_of_empty_inputs() {
+        assert_eq!(myers(&[], &[]), Some(Vec::new()));
+        assert_eq!(
+            myers(&[], &["a", "b"]),
+            Some(vec![Edit::Insert(0), Edit::Insert(1)])
+        );
+        assert_eq!(
+            myers(&["a", "b"], &[]),
+            Some(vec![Edit::Delete(0), Edit::Delete(1)])
+        );
+    }
+
+    #[test]
+    fn myers_of_identical_inputs() {
+        assert_eq!(
+            myers(&["a", "b"], &["a", "b"]),
+            Some(vec![Edit::Equal(0, 0), Edit::Equal(1, 1)])
+        );
+    }
+
+    #[test]
+    fn myers_finds_shortest_edit_script() {
+        let old = ["a", "b", "c", "a", "b", "b", "a"];
+        let new = ["c", "b", "a", "b", "a", "c"];
+        let edits = myers(&old, &new).unwrap();
+        let changes = edits
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Equal(..)))
+            .count();
+        assert_eq!(changes, 5);
+        let (from, to) = apply(&old, &new, &edits);
+        assert_eq!(from, old.map(Some));
+        assert_eq!(to, new.map(Some));
+    }
+
+    #[test]
+    fn myers_gives_up_on_too_many_edits() {
+        let old = (0..=MAX_EDITS).map(|n| n.to_string()).collect::<Vec<_>>();
+        let old = old.iter().map(String::as_str).collect::<Vec<_>>();
+        assert_eq!(myers(&old, &[]), None);
+        let (_, fewer) = old.split_first().unwrap();
+        assert!(myers(fewer, &[]).is_some());
+    }
+
+    #[test]
+    fn unified_diff_of_identical_texts() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb\n", "old", "new"), "");
+        assert_eq!(unified_diff("", "", "old", "new"), "");
+    }
+
+    #[test]
+    fn unified_diff_of_pure_insert() {
+        assert_eq!(
+            unified_diff("", "a\nb\n", "old", "new"),
+            "--- old\n+++ new\n@@ -0,0 +1,2 @@\n+a\n+b\n"
+        );
+    }
+
+    #[test]
+    fn unified_diff_of_pure_delete() {
+        assert_eq!(
+            unified_diff("a\n", "", "old", "new"),
+            "--- old\n+++ new\n@@ -1 +0,0 @@\n-a\n"
+        );
+    }
+
+    #[test]
+    fn unified_diff_hunk_headers() {
+        let old = (1..=20).map(|n| n.to_string() + "\n").collect::<String>();
+        let new = old.replacen("2\n", "two\n", 1).replace("18\n", "");
+        assert_eq!(
+            unified_diff(&old, &new, "old", "new"),
+            "--- old\n+++ new\n\
+             @@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n 4\n 5\n\
+             @@ -15,6 +15,5 @@\n 15\n 16\n 17\n-18\n 19\n 20\n"
+        );
+    }
+
+    #[test]
+    fn unified_diff_splits_distant_changes() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let new = "one\n2\n3\n4\n5\n6\n7\n8\nnine\n";
+        assert_eq!(
+            unified_diff(old, new, "old", "new"),
+            "--- old\n+++ new\n@@ -1,4 +1,4 @@\n-1\n+one\n 2\n 3\n 4\n@@ -6,4 +6,4 @@\n 6\n 7\n 8\n-9\n+nine\n"
+        );
+    }
+
+    #[test]
+    fn unified_diff_merges_close_changes() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n";
+        let new = "one\n2\n3\n4\n5\n6\n7\neight\n";
+        assert_eq!(
+            unified_diff(old, new, "old", "new"),
+            "--- old\n+++ new\n@@ -1,8 +1,8 @@\n-1\n+one\n 2\n 3\n 4\n 5\n 6\n 7\n-8\n+eight\n"
+        );
+    }
+}
@@ -0,0 +1,297 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL-1.2 OR GPL-3.0
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Check whether dependents tolerate another version of a package.
+
+use std::{fmt::Display, path::PathBuf};
+
+use alpm::{Alpm, SigLevel};
+use tracing::{debug, warn};
+
+use crate::{
+    cache::CachedArchive,
+    graph::{Constraint, Provision},
+};
+
+/// Whether a version of a package satisfies a dependency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Satisfaction {
+    /// The version satisfies the dependency.
+    Satisfied,
+    /// The version does not satisfy the dependency.
+    Unsatisfied,
+    /// Only a provision can satisfy the dependency, but the provisions of the
+    /// version are not known.
+    Unknown,
+}
+
+impl Satisfaction {
+    /// A short name for this satisfaction.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Satisfaction::Satisfied => "satisfied",
+            Satisfaction::Unsatisfied => "unsatisfied",
+            Satisfaction::Unknown => "unknown",
+        }
+    }
+}
+
+impl Display for Satisfaction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Satisfaction::Unknown => write!(f, "unknown: provision not verifiable"),
+            satisfaction => write!(f, "{}", satisfaction.as_str()),
+        }
+    }
+}
+
+/// Whether package `name` at `version` satisfies `dependency`.
+///
+/// Check the dependency against the name and `version` of the package, and
+/// against `provides` of the package at `version`.  Without `provides` the
+/// satisfaction of dependencies on other names is unknown, but a dependency on
+/// the name of the package is unsatisfied if its version does not match.
+#[must_use]
+pub fn satisfaction(
+    dependency: &Provision,
+    name: &str,
+    version: &str,
+    provides: Option<&[Provision]>,
+) -> Satisfaction {
+    if dependency.is_satisfied_by(name, version) {
+        return Satisfaction::Satisfied;
+    }
+    match provides {
+        Some(provides) if provides.iter().any(|p| dependency.is_provided_by(p)) => {
+            Satisfaction::Satisfied
+        }
+        Some(_) => Satisfaction::Unsatisfied,
+        None if dependency.name == name => Satisfaction::Unsatisfied,
+        None => Satisfaction::Unknown,
+    }
+}
+
+/// A version of a package, and the constraints it does not satisfy.
+#[derive(Debug, Clone)]
+pub struct VersionCheck<'a> {
+    /// The full version, including epoch and release.
+    pub version: String,
+    /// Cached archives of this version, if any.
+    pub archives: Vec<PathBuf>,
+    /// Constraints of dependents which this version does not satisfy.
+    pub unsatisfied: Vec<Constraint<'a>>,
+    /// Constraints of dependents which only a provision can satisfy, if the
+    /// provisions of this version are not known.
+    pub unverifiable: Vec<Constraint<'a>>,
+}
+
+impl<'a> VersionCheck<'a> {
+    /// Check package `name` at `version` against all `constraints`.
+    ///
+    /// See [`satisfaction`] for `provides`.  Return a check without archives.
+    #[must_use]
+    pub fn new(
+        constraints: &[Constraint<'a>],
+        name: &str,
+        version: &str,
+        provides: Option<&[Provision]>,
+    ) -> Self {
+        let mut check = Self {
+            version: version.to_owned(),
+            archives: Vec::new(),
+            unsatisfied: Vec::new(),
+            unverifiable: Vec::new(),
+        };
+        for constraint in constraints {
+            match satisfaction(&constraint.dependency, name, version, provides) {
+                Satisfaction::Satisfied => {}
+                Satisfaction::Unsatisfied => check.unsatisfied.push(*constraint),
+                Satisfaction::Unknown => check.unverifiable.push(*constraint),
+            }
+        }
+        check
+    }
+
+    /// Whether this version satisfies all constraints.
+    ///
+    /// Unsatisfied if this version does not satisfy any constraint, and
+    /// unknown if the satisfaction of any constraint is unknown.
+    #[must_use]
+    pub fn satisfaction(&self) -> Satisfaction {
+        if !self.unsatisfied.is_empty() {
+            Satisfaction::Unsatisfied
+        } else if !self.unverifiable.is_empty() {
+            Satisfaction::Unknown
+        } else {
+            Satisfaction::Satisfied
+        }
+    }
+}
+
+/// Check all cached versions of package `name` against `constraints`.
+///
+/// Group `archives` of the package by version, and load the first archive of
+/// every version to check dependencies against its provisions as well.  If an
+/// archive fails to load, log a warning and check without provisions; see
+/// [`satisfaction`].
+///
+/// Return versions in the order of `archives`, ignoring archives of other
+/// packages.
+#[must_use]
+pub fn check_cached_versions<'a>(
+    alpm: &Alpm,
+    constraints: &[Constraint<'a>],
+    name: &str,
+    archives: &[CachedArchive],
+) -> Vec<VersionCheck<'a>> {
+    let mut checks: Vec<VersionCheck> = Vec::new();
+    for archive in archives
+        .iter()
+        .filter(|archive| archive.package.name == name)
+    {
+        let version = archive.package.version.as_str();
+        if let Some(check) = checks.iter_mut().find(|check| check.version == version) {
+            check.archives.push(archive.path.clone());
+            continue;
+        }
+        debug!("Loading {}", archive.path.display());
+        let loaded = alpm.pkg_load(
+            archive.path.as_os_str().as_encoded_bytes(),
+            false,
+            SigLevel::NONE,
+        );
+        let provides = match &loaded {
+            Ok(package) => Some(
+                package
+                    .provides()
+                    .iter()
+                    .map(Provision::new)
+                    .collect::<Vec<_>>(),
+            ),
+            Err(error) => {
+                warn!("Failed to load {}: {error}", archive.path.display());
+                None
+            }
+        };
+        let mut check = VersionCheck::new(constraints, name, version, provides.as_deref());
+        check.archives.push(archive.path.clone());
+        checks.push(check);
+    }
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use alpm::DepMod;
+
+    use super::*;
+
+    fn provision<'a>(name: &'a str, depmod: DepMod, version: Option<&'a str>) -> Provision<'a> {
+        Provision {
+            name,
+            depmod,
+            version,
+        }
+    }
+
+    #[test]
+    fn satisfaction_by_name() {
+        let dependency = provision("foo", DepMod::Any, None);
+        assert_eq!(
+            satisfaction(&dependency, "foo", "1.0-1", None),
+            Satisfaction::Satisfied
+        );
+        assert_eq!(
+            satisfaction(&dependency, "foo", "1:0.1-1", Some(&[])),
+            Satisfaction::Satisfied
+        );
+    }
+
+    #[test]
+    fn satisfaction_of_versioned_dependency() {
+        let dependency = provision("foo", DepMod::Ge, Some("2.0"));
+        assert_eq!(
+            satisfaction(&dependency, "foo", "2.0-1", None),
+            Satisfaction::Satisfied
+        );
+        assert_eq!(
+            satisfaction(&dependency, "foo", "2.1-3", Some(&[])),
+            Satisfaction::Satisfied
+        );
+        assert_eq!(
+            satisfaction(&dependency, "foo", "1.9-1", None),
+            Satisfaction::Unsatisfied
+        );
+        assert_eq!(
+            satisfaction(&dependency, "foo", "1.9-1", Some(&[])),
+            Satisfaction::Unsatisfied
+        );
+
+        let exact = provision("foo", DepMod::Eq, Some("2.0-1"));
+        assert_eq!(
+            satisfaction(&exact, "foo", "2.0-1", None),
+            Satisfaction::Satisfied
+        );
+        assert_eq!(
+            satisfaction(&exact, "foo", "2.0-2", None),
+            Satisfaction::Unsatisfied
+        );
+        let below = provision("foo", DepMod::Lt, Some("2.0"));
+        assert_eq!(
+            satisfaction(&below, "foo", "1.9-1", None),
+            Satisfaction::Satisfied
+        );
+        assert_eq!(
+            satisfaction(&below, "foo", "1:1.0-1", None),
+            Satisfaction::Unsatisfied
+        );
+    }
+
+    #[test]
+    fn satisfaction_by_provision() {
+        let dependency = provision("sh", DepMod::Any, None);
+        let provides = [provision("sh", DepMod::Eq, Some("5.2"))];
+        assert_eq!(
+            satisfaction(&dependency, "bash", "5.2-1", Some(&provides)),
+            Satisfaction::Satisfied
+        );
+        assert_eq!(
+            satisfaction(&dependency, "bash", "5.2-1", Some(&[])),
+            Satisfaction::Unsatisfied
+        );
+
+        let versioned = provision("libfoo.so", DepMod::Ge, Some("2-64"));
+        let provides = [provision("libfoo.so", DepMod::Eq, Some("2-64"))];
+        assert_eq!(
+            satisfaction(&versioned, "foo", "2.0-1", Some(&provides)),
+            Satisfaction::Satisfied
+        );
+        let older = [provision("libfoo.so", DepMod::Eq, Some("1-64"))];
+        assert_eq!(
+            satisfaction(&versioned, "foo", "1.0-1", Some(&older)),
+            Satisfaction::Unsatisfied
+        );
+        let unversioned = [provision("libfoo.so", DepMod::Any, None)];
+        assert_eq!(
+            satisfaction(&versioned, "foo", "1.0-1", Some(&unversioned)),
+            Satisfaction::Unsatisfied
+        );
+    }
+
+    #[test]
+    fn satisfaction_of_provision_without_provides() {
+        let dependency = provision("sh", DepMod::Any, None);
+        assert_eq!(
+            satisfaction(&dependency, "bash", "5.2-1", None),
+            Satisfaction::Unknown
+        );
+        assert_eq!(
+            Satisfaction::Unknown.to_string(),
+            "unknown: provision not verifiable"
+        );
+    }
+}
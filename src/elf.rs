@@ -0,0 +1,479 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL-1.2 OR GPL-3.0
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Read dynamic linking information from ELF files.
+//!
+//! This module only reads as much of ELF as needed to find the libraries a
+//! binary links against.
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// Section type of the dynamic linking table.
+const SHT_DYNAMIC: u32 = 6;
+/// Dynamic table entry for a needed library.
+const DT_NEEDED: u64 = 1;
+/// Dynamic table entry which terminates the table.
+const DT_NULL: u64 = 0;
+
+/// The layout of an ELF file.
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    is_64bit: bool,
+    little_endian: bool,
+}
+
+impl Layout {
+    fn word_size(self) -> usize {
+        if self.is_64bit { 8 } else { 4 }
+    }
+
+    fn read_u16(self, bytes: &[u8], offset: usize) -> Option<u64> {
+        let bytes: [u8; 2] = bytes.get(offset..offset + 2)?.try_into().ok()?;
+        Some(u64::from(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        }))
+    }
+
+    fn read_u32(self, bytes: &[u8], offset: usize) -> Option<u64> {
+        let bytes: [u8; 4] = bytes.get(offset..offset + 4)?.try_into().ok()?;
+        Some(u64::from(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }))
+    }
+
+    fn read_u64(self, bytes: &[u8], offset: usize) -> Option<u64> {
+        let bytes: [u8; 8] = bytes.get(offset..offset + 8)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u64::from_le_bytes(bytes)
+        } else {
+            u64::from_be_bytes(bytes)
+        })
+    }
+
+    /// Read a word, i.e. an address, offset, or size.
+    fn read_word(self, bytes: &[u8], offset: usize) -> Option<u64> {
+        if self.is_64bit {
+            self.read_u64(bytes, offset)
+        } else {
+            self.read_u32(bytes, offset)
+        }
+    }
+}
+
+/// A section of an ELF file.
+#[derive(Debug, Clone, Copy)]
+struct Section {
+    kind: u32,
+    offset: u64,
+    size: u64,
+    link: u32,
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+fn to_usize(value: u64) -> std::io::Result<usize> {
+    usize::try_from(value).map_err(|_| invalid_data("Value out of range"))
+}
+
+/// Read `size` bytes at `offset` from `file` of length `file_size`.
+///
+/// Check that the range lies within the file before allocating a buffer, to
+/// reject bogus sizes from malformed headers.
+fn read_at(file: &mut File, file_size: u64, offset: u64, size: u64) -> std::io::Result<Vec<u8>> {
+    if offset.checked_add(size).is_none_or(|end| file_size < end) {
+        return Err(invalid_data("Data out of file bounds"));
+    }
+    file.seek(SeekFrom::Start(offset))?;
+    let mut buffer = vec![0; to_usize(size)?];
+    file.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+fn read_sections(
+    file: &mut File,
+    file_size: u64,
+    layout: Layout,
+    header: &[u8],
+) -> std::io::Result<Vec<Section>> {
+    let (offset_of_shoff, offset_of_shentsize) = if layout.is_64bit {
+        (0x28, 0x3A)
+    } else {
+        (0x20, 0x2E)
+    };
+    let invalid_header = || invalid_data("Truncated ELF header");
+    let shoff = layout
+        .read_word(header, offset_of_shoff)
+        .ok_or_else(invalid_header)?;
+    let shentsize = layout
+        .read_u16(header, offset_of_shentsize)
+        .ok_or_else(invalid_header)?;
+    let shnum = layout
+        .read_u16(header, offset_of_shentsize + 2)
+        .ok_or_else(invalid_header)?;
+    if shoff == 0 || shnum == 0 {
+        return Ok(Vec::new());
+    }
+    if shentsize == 0 {
+        return Err(invalid_data("Invalid section header size"));
+    }
+    let table = read_at(file, file_size, shoff, shentsize * shnum)?;
+    let word = layout.word_size();
+    table
+        .chunks_exact(to_usize(shentsize)?)
+        .map(|entry| {
+            // Offsets of sh_type, sh_offset, sh_size and sh_link in the
+            // section header
+            let section = Section {
+                kind: u32::try_from(layout.read_u32(entry, 4)?).ok()?,
+                offset: layout.read_word(entry, 8 + 2 * word)?,
+                size: layout.read_word(entry, 8 + 3 * word)?,
+                link: u32::try_from(layout.read_u32(entry, 8 + 4 * word)?).ok()?,
+            };
+            Some(section)
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| invalid_data("Truncated section header"))
+}
+
+/// Read a NUL-terminated string at `offset` from a string table.
+fn read_string(table: &[u8], offset: u64) -> Option<String> {
+    let bytes = table.get(usize::try_from(offset).ok()?..)?;
+    let end = bytes.iter().position(|b| *b == 0)?;
+    Some(String::from_utf8_lossy(bytes.get(..end)?).into_owned())
+}
+
+/// Read the libraries an ELF file needs.
+///
+/// Read all `DT_NEEDED` entries from the dynamic section of the ELF file at
+/// `path`, i.e. the sonames of all shared libraries it links against.
+///
+/// Return `None` if `path` is no ELF file, and an empty list if the ELF file
+/// has no dynamic section, e.g. because it's statically linked.
+///
+/// # Errors
+///
+/// Return an error if reading `path` fails, or if `path` is a malformed ELF
+/// file.
+pub fn needed_libraries(path: &Path) -> std::io::Result<Option<Vec<String>>> {
+    let mut file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    let mut header = [0; 64];
+    let mut read = 0;
+    // Read as much of the header as possible; the header of 32-bit ELF files
+    // is shorter, and other files may be shorter still.
+    loop {
+        match file.read(header.get_mut(read..).unwrap_or_default())? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    let header = header.get(..read).unwrap_or_default();
+    if header.get(..4) != Some(b"\x7fELF") {
+        return Ok(None);
+    }
+    let layout = match (header.get(4), header.get(5)) {
+        (Some(1), Some(1)) => Layout {
+            is_64bit: false,
+            little_endian: true,
+        },
+        (Some(1), Some(2)) => Layout {
+            is_64bit: false,
+            little_endian: false,
+        },
+        (Some(2), Some(1)) => Layout {
+            is_64bit: true,
+            little_endian: true,
+        },
+        (Some(2), Some(2)) => Layout {
+            is_64bit: true,
+            little_endian: false,
+        },
+        _ => return Err(invalid_data("Unsupported ELF class or data encoding")),
+    };
+
+    let sections = read_sections(&mut file, file_size, layout, header)?;
+    let Some(dynamic) = sections.iter().find(|section| section.kind == SHT_DYNAMIC) else {
+        return Ok(Some(Vec::new()));
+    };
+    let strings = sections
+        .get(to_usize(u64::from(dynamic.link))?)
+        .ok_or_else(|| invalid_data("Missing string table of dynamic section"))?;
+    let dynamic_table = read_at(&mut file, file_size, dynamic.offset, dynamic.size)?;
+    let string_table = read_at(&mut file, file_size, strings.offset, strings.size)?;
+
+    let word = layout.word_size();
+    let mut needed = Vec::new();
+    for entry in dynamic_table.chunks_exact(2 * word) {
+        let (Some(tag), Some(value)) = (layout.read_word(entry, 0), layout.read_word(entry, word))
+        else {
+            break;
+        };
+        match tag {
+            DT_NULL => break,
+            DT_NEEDED => needed.push(
+                read_string(&string_table, value)
+                    .ok_or_else(|| invalid_data("Invalid string in dynamic section"))?,
+            ),
+            _ => {}
+        }
+    }
+    Ok(Some(needed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write ELF data in a given layout.
+    struct Writer {
+        layout: Layout,
+        bytes: Vec<u8>,
+    }
+
+    impl Writer {
+        fn u16(&mut self, value: u16) {
+            if self.layout.little_endian {
+                self.bytes.extend_from_slice(&value.to_le_bytes());
+            } else {
+                self.bytes.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+
+        fn u32(&mut self, value: u32) {
+            if self.layout.little_endian {
+                self.bytes.extend_from_slice(&value.to_le_bytes());
+            } else {
+                self.bytes.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+
+        fn word(&mut self, value: u32) {
+            if self.layout.is_64bit {
+                let value = u64::from(value);
+                if self.layout.little_endian {
+                    self.bytes.extend_from_slice(&value.to_le_bytes());
+                } else {
+                    self.bytes.extend_from_slice(&value.to_be_bytes());
+                }
+            } else {
+                self.u32(value);
+            }
+        }
+
+        fn section(&mut self, kind: u32, offset: u32, size: u32, link: u32) {
+            self.u32(0); // sh_name
+            self.u32(kind);
+            self.word(0); // sh_flags
+            self.word(0); // sh_addr
+            self.word(offset);
+            self.word(size);
+            self.u32(link);
+            self.u32(0); // sh_info
+            self.word(0); // sh_addralign
+            self.word(0); // sh_entsize
+        }
+    }
+
+    /// Build a minimal ELF file which needs `libc.so.6` and `libm.so.6`.
+    fn elf_file(layout: Layout) -> Vec<u8> {
+        let strings = b"\0libc.so.6\0libm.so.6\0";
+        let (header_size, section_header_size): (u16, u16) =
+            if layout.is_64bit { (64, 64) } else { (52, 40) };
+        let entry_size = u32::try_from(2 * layout.word_size()).unwrap();
+        let strings_offset = u32::from(header_size);
+        let strings_size = u32::try_from(strings.len()).unwrap();
+        let dynamic_offset = strings_offset + strings_size;
+        let dynamic_size = 3 * entry_size;
+        let sections_offset = dynamic_offset + dynamic_size;
+
+        let mut writer = Writer {
+            layout,
+            bytes: Vec::new(),
+        };
+        writer.bytes.extend_from_slice(b"\x7fELF");
+        writer.bytes.push(if layout.is_64bit { 2 } else { 1 });
+        writer.bytes.push(if layout.little_endian { 1 } else { 2 });
+        writer.bytes.push(1); // EI_VERSION
+        writer.bytes.resize(16, 0);
+        writer.u16(3); // e_type: ET_DYN
+        writer.u16(62); // e_machine
+        writer.u32(1); // e_version
+        writer.word(0); // e_entry
+        writer.word(0); // e_phoff
+        writer.word(sections_offset);
+        writer.u32(0); // e_flags
+        writer.u16(header_size);
+        writer.u16(0); // e_phentsize
+        writer.u16(0); // e_phnum
+        writer.u16(section_header_size);
+        writer.u16(3); // e_shnum
+        writer.u16(0); // e_shstrndx
+        assert_eq!(writer.bytes.len(), usize::from(header_size));
+
+        writer.bytes.extend_from_slice(strings);
+
+        writer.word(1); // DT_NEEDED
+        writer.word(1);
+        writer.word(1); // DT_NEEDED
+        writer.word(11);
+        writer.word(0); // DT_NULL
+        writer.word(0);
+
+        writer.section(0, 0, 0, 0);
+        writer.section(SHT_DYNAMIC, dynamic_offset, dynamic_size, 2);
+        writer.section(3, strings_offset, strings_size, 0); // SHT_STRTAB
+        writer.bytes
+    }
+
+    fn needed_libraries_of(name: &str, contents: &[u8]) -> std::io::Result<Option<Vec<String>>> {
+        let path = std::env::temp_dir().join(format!("packit-elf-{}-{name}", std::process::id()));
+        std::fs::write(&path, contents)?;
+        let result = needed_libraries(&path);
+        std::fs::remove_file(&path)?;
+        result
+    }
+
+    fn libraries() -> Vec<String> {
+        vec!["libc.so.6".to_owned(), "libm.so.6".to_owned()]
+    }
+
+    #[test]
+    fn needed_libraries_64bit_little_endian() {
+        let file = elf_file(Layout {
+            is_64bit: true,
+            little_endian: true,
+        });
+        assert_eq!(
+            needed_libraries_of("64le", &file).unwrap(),
+            Some(libraries())
+        );
+    }
+
+    #[test]
+    fn needed_libraries_64bit_big_endian() {
+        let file = elf_file(Layout {
+            is_64bit: true,
+            little_endian: false,
+        });
+        assert_eq!(
+            needed_libraries_of("64be", &file).unwrap(),
+            Some(libraries())
+        );
+    }
+
+    #[test]
+    fn needed_libraries_32bit_little_endian() {
+        let file = elf_file(Layout {
+            is_64bit: false,
+            little_endian: true,
+        });
+        assert_eq!(
+            needed_libraries_of("32le", &file).unwrap(),
+            Some(libraries())
+        );
+    }
+
+    #[test]
+    fn needed_libraries_32bit_big_endian() {
+        let file = elf_file(Layout {
+            is_64bit: false,
+            little_endian: false,
+        });
+        assert_eq!(
+            needed_libraries_of("32be", &file).unwrap(),
+            Some(libraries())
+        );
+    }
+
+    #[test]
+    fn needed_libraries_truncated_section_headers() {
+        let mut file = elf_file(Layout {
+            is_64bit: true,
+            little_endian: true,
+        });
+        file.truncate(file.len() - 10);
+        let error = needed_libraries_of("truncated-sections", &file).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn needed_libraries_oversized_section_table() {
+        let mut file = elf_file(Layout {
+            is_64bit: true,
+            little_endian: true,
+        });
+        // Set e_shentsize and e_shnum to their maximum, for a 4 GiB table
+        file.splice(0x3A..0x3E, [0xff; 4]);
+        let error = needed_libraries_of("oversized-sections", &file).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn needed_libraries_oversized_dynamic_section() {
+        let mut file = elf_file(Layout {
+            is_64bit: true,
+            little_endian: true,
+        });
+        // sh_size of the dynamic section, i.e. the second section header
+        let sh_size = file.len() - 3 * 64 + 64 + 32;
+        file.splice(sh_size..sh_size + 8, u64::MAX.to_le_bytes());
+        let error = needed_libraries_of("oversized-dynamic", &file).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn needed_libraries_truncated_header() {
+        let mut file = elf_file(Layout {
+            is_64bit: true,
+            little_endian: true,
+        });
+        file.truncate(0x30);
+        let error = needed_libraries_of("truncated-header", &file).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn needed_libraries_unsupported_class() {
+        let error = needed_libraries_of("class", b"\x7fELF\x03\x01\x01").unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn needed_libraries_non_elf() {
+        assert_eq!(
+            needed_libraries_of("script", b"#!/bin/sh\necho hello\n").unwrap(),
+            None
+        );
+        assert_eq!(needed_libraries_of("empty", b"").unwrap(), None);
+        assert_eq!(needed_libraries_of("short", b"\x7fEL").unwrap(), None);
+    }
+
+    #[test]
+    fn needed_libraries_without_sections() {
+        let layout = Layout {
+            is_64bit: true,
+            little_endian: true,
+        };
+        let mut file = elf_file(layout);
+        // Clear e_shnum and e_shstrndx
+        file.truncate(0x3C);
+        file.extend_from_slice(&[0, 0, 0, 0]);
+        assert_eq!(
+            needed_libraries_of("static", &file).unwrap(),
+            Some(Vec::new())
+        );
+    }
+}
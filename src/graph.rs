@@ -6,10 +6,15 @@
 
 //! Graphs of ALPM packages.
 
-use std::{fmt::Display, hash::Hash, ops::Deref};
+use std::{cmp::Ordering, fmt::Display, hash::Hash, ops::Deref};
 
-use alpm::{Db, Package, Pkg};
-use petgraph::prelude::DiGraphMap;
+use alpm::{Db, Dep, DepMod, Package, Pkg, Ver};
+use alpm_utils::depends::{satisfies_dep, satisfies_provide};
+use petgraph::{
+    Direction,
+    prelude::DiGraphMap,
+    visit::{Data, EdgeRef, GraphBase, IntoEdgeReferences, IntoEdgesDirected, IntoNodeIdentifiers},
+};
 use tracing::{debug, debug_span, warn};
 
 /// The weight of a dependency edge.
@@ -19,6 +24,16 @@ pub enum DependencyEdge {
     Required,
     /// An optional dependency.
     Optional,
+    /// A dependency required to build a package.
+    ///
+    /// Local databases do not record build dependencies; only graphs of source
+    /// packages contain these edges.  See [`crate::srcinfo`].
+    Make,
+    /// A dependency required to run the tests of a package.
+    ///
+    /// Like [`DependencyEdge::Make`], only graphs of source packages contain
+    /// these edges.
+    Check,
 }
 
 impl Display for DependencyEdge {
@@ -26,6 +41,8 @@ impl Display for DependencyEdge {
         match *self {
             DependencyEdge::Required => write!(f, "required"),
             DependencyEdge::Optional => write!(f, "optional"),
+            DependencyEdge::Make => write!(f, "make"),
+            DependencyEdge::Check => write!(f, "check"),
         }
     }
 }
@@ -171,3 +188,346 @@ pub fn build_graph_for_localdb(db: &Db) -> AlpmDepGraphMap<'_> {
     }
     g
 }
+
+/// A provision which satisfies a dependency, e.g. `sh` or `libfoo.so=1-64`.
+///
+/// This is the dependency as written by the dependent package, i.e. including
+/// its version constraint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Provision<'a> {
+    /// The name of the provision.
+    pub name: &'a str,
+    /// The version constraint of the dependency.
+    pub depmod: DepMod,
+    /// The version of the dependency, if any.
+    pub version: Option<&'a str>,
+}
+
+impl<'a> Provision<'a> {
+    /// Create a provision from a dependency.
+    #[must_use]
+    pub fn new(dep: &'a Dep) -> Self {
+        Self {
+            name: dep.name(),
+            depmod: dep.depmod(),
+            version: dep.version().map(Ver::as_str),
+        }
+    }
+
+    /// The operator of the version constraint, e.g. `>=`.
+    ///
+    /// Return an empty string if the dependency has no version constraint.
+    #[must_use]
+    pub fn operator(&self) -> &'static str {
+        match self.depmod {
+            DepMod::Any => "",
+            DepMod::Eq => "=",
+            DepMod::Ge => ">=",
+            DepMod::Le => "<=",
+            DepMod::Gt => ">",
+            DepMod::Lt => "<",
+        }
+    }
+
+    /// Whether `version` satisfies the version constraint of this dependency.
+    fn matches_version(&self, version: &str) -> bool {
+        let Some(required) = self.version else {
+            return true;
+        };
+        let ordering = alpm::vercmp(version, required);
+        match self.depmod {
+            DepMod::Any => true,
+            DepMod::Eq => ordering == Ordering::Equal,
+            DepMod::Ge => ordering != Ordering::Less,
+            DepMod::Le => ordering != Ordering::Greater,
+            DepMod::Gt => ordering == Ordering::Greater,
+            DepMod::Lt => ordering == Ordering::Less,
+        }
+    }
+
+    /// Whether a package `name` at `version` satisfies this dependency by its name.
+    #[must_use]
+    pub fn is_satisfied_by(&self, name: &str, version: &str) -> bool {
+        self.name == name && self.matches_version(version)
+    }
+
+    /// Whether `provide`, a provision of a package, satisfies this dependency.
+    ///
+    /// Like pacman, an unversioned provision only satisfies unversioned
+    /// dependencies.
+    #[must_use]
+    pub fn is_provided_by(&self, provide: &Provision) -> bool {
+        self.name == provide.name
+            && (self.version.is_none()
+                || provide
+                    .version
+                    .is_some_and(|version| self.matches_version(version)))
+    }
+}
+
+impl Display for Provision<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(version) = self.version {
+            write!(f, "{}{version}", self.operator())?;
+        }
+        Ok(())
+    }
+}
+
+/// A dependency of a package which another package satisfies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Constraint<'a> {
+    /// The package which declares the dependency.
+    pub dependent: PackageNode<'a>,
+    /// The package which satisfies the dependency.
+    pub provider: PackageNode<'a>,
+    /// The kind of the dependency.
+    pub edge: DependencyEdge,
+    /// The dependency as written by the dependent package.
+    pub dependency: Provision<'a>,
+    /// The provision of the provider which satisfies the dependency.
+    ///
+    /// `None` if the provider satisfies the dependency by its name.
+    pub provision: Option<Provision<'a>>,
+}
+
+impl Constraint<'_> {
+    /// Whether this constraint restricts the provider beyond its name.
+    ///
+    /// That is, whether the dependency has a version constraint, or whether
+    /// the provider satisfies it by a provision.
+    #[must_use]
+    pub fn is_restrictive(&self) -> bool {
+        self.dependency.version.is_some() || self.provision.is_some()
+    }
+}
+
+/// Find all dependencies of `dependent` which `provider` satisfies.
+///
+/// Return all dependencies of the kind of `edge`, i.e. dependencies for
+/// required edges and optional dependencies for optional edges, which the
+/// provider satisfies either by name or by a provision.  Return nothing for
+/// all other edges, because databases do not record their constraints.
+#[must_use]
+pub fn edge_constraints<'a>(
+    dependent: PackageNode<'a>,
+    provider: PackageNode<'a>,
+    edge: DependencyEdge,
+) -> Vec<Constraint<'a>> {
+    let dependencies = match edge {
+        DependencyEdge::Required => dependent.package().depends(),
+        DependencyEdge::Optional => dependent.package().optdepends(),
+        DependencyEdge::Make | DependencyEdge::Check => return Vec::new(),
+    };
+    let package = provider.package();
+    dependencies
+        .into_iter()
+        .filter_map(|dep| {
+            let provision = if satisfies_dep(dep, package.name(), package.version()) {
+                None
+            } else {
+                let provide = package
+                    .provides()
+                    .into_iter()
+                    .find(|provide| satisfies_provide(dep, provide))?;
+                Some(Provision::new(provide))
+            };
+            Some(Constraint {
+                dependent,
+                provider,
+                edge,
+                dependency: Provision::new(dep),
+                provision,
+            })
+        })
+        .collect()
+}
+
+/// Find all constraints which dependents in `graph` place on `package`.
+///
+/// Return constraints of all edges into `package`, sorted by the name of the
+/// dependent package.
+pub fn constraints_on<'a, G>(graph: G, package: PackageNode<'a>) -> Vec<Constraint<'a>>
+where
+    G: GraphBase<NodeId = PackageNode<'a>> + Data<EdgeWeight = DependencyEdge> + IntoEdgesDirected,
+{
+    let mut constraints = graph
+        .edges_directed(package, Direction::Incoming)
+        .flat_map(|edge| edge_constraints(edge.source(), edge.target(), *edge.weight()))
+        .collect::<Vec<_>>();
+    constraints.sort_by_key(|constraint| constraint.dependent.package().name());
+    constraints
+}
+
+/// A node in a graph with provisions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ProvisionGraphNode<'a> {
+    /// An installed package.
+    Package(PackageNode<'a>),
+    /// A virtual node for a provision, between dependent packages and all providers.
+    Provision(Provision<'a>),
+}
+
+impl Display for ProvisionGraphNode<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProvisionGraphNode::Package(package) => package.fmt(f),
+            ProvisionGraphNode::Provision(provision) => provision.fmt(f),
+        }
+    }
+}
+
+/// An ALPM dependency graph with virtual nodes for provisions.
+pub type ProvisionGraphMap<'a> = DiGraphMap<ProvisionGraphNode<'a>, DependencyEdge>;
+
+/// Add a dependency edge, unless a required edge already exists.
+fn add_dependency_edge<'a>(
+    graph: &mut ProvisionGraphMap<'a>,
+    from: ProvisionGraphNode<'a>,
+    to: ProvisionGraphNode<'a>,
+    edge: DependencyEdge,
+) {
+    if graph.edge_weight(from, to) != Some(&DependencyEdge::Required) {
+        graph.add_edge(from, to, edge);
+    }
+}
+
+/// Insert virtual nodes for provisions into a dependency graph.
+///
+/// For every edge in `graph` find the dependencies of the dependent package
+/// which the target package satisfies.  If the target package satisfies a
+/// dependency by its name, keep the direct edge; otherwise route the edge
+/// through a virtual node for the dependency, e.g. `sh` or `java-runtime`.
+///
+/// The local database records edges to all installed packages which satisfy a
+/// dependency, so alternative providers of a dependency share the same
+/// provision node.  Copy all other edges, e.g. build dependencies, as they are.
+pub fn with_provisions<'a, G>(graph: G) -> ProvisionGraphMap<'a>
+where
+    G: GraphBase<NodeId = PackageNode<'a>>
+        + Data<EdgeWeight = DependencyEdge>
+        + IntoNodeIdentifiers
+        + IntoEdgeReferences,
+{
+    let mut g = DiGraphMap::new();
+    for node in graph.node_identifiers() {
+        g.add_node(ProvisionGraphNode::Package(node));
+    }
+    for edge in graph.edge_references() {
+        let (dependent, provider) = (edge.source(), edge.target());
+        if !matches!(
+            *edge.weight(),
+            DependencyEdge::Required | DependencyEdge::Optional
+        ) {
+            g.add_edge(
+                ProvisionGraphNode::Package(dependent),
+                ProvisionGraphNode::Package(provider),
+                *edge.weight(),
+            );
+            continue;
+        }
+        let constraints = edge_constraints(dependent, provider, *edge.weight());
+        for constraint in &constraints {
+            if constraint.provision.is_none() {
+                add_dependency_edge(
+                    &mut g,
+                    ProvisionGraphNode::Package(dependent),
+                    ProvisionGraphNode::Package(provider),
+                    constraint.edge,
+                );
+            } else {
+                let provision = ProvisionGraphNode::Provision(constraint.dependency);
+                debug!(
+                    package = dependent.name(),
+                    "{} depends on {provision} provided by {}",
+                    dependent.name(),
+                    provider.name()
+                );
+                add_dependency_edge(
+                    &mut g,
+                    ProvisionGraphNode::Package(dependent),
+                    provision,
+                    constraint.edge,
+                );
+                add_dependency_edge(
+                    &mut g,
+                    provision,
+                    ProvisionGraphNode::Package(provider),
+                    constraint.edge,
+                );
+            }
+        }
+        if constraints.is_empty() {
+            // The local database may record dependencies which no longer
+            // resolve, e.g. after a forced installation; keep the edge as is.
+            warn!(
+                package = dependent.name(),
+                "No dependency of {} is satisfied by {}",
+                dependent.name(),
+                provider.name()
+            );
+            add_dependency_edge(
+                &mut g,
+                ProvisionGraphNode::Package(dependent),
+                ProvisionGraphNode::Package(provider),
+                *edge.weight(),
+            );
+        }
+    }
+    g
+}
+
+/// A node in a graph with package groups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GroupGraphNode<'a> {
+    /// An installed package.
+    Package(PackageNode<'a>),
+    /// A virtual node for a package group, with edges to all its members.
+    Group(&'a str),
+}
+
+impl Display for GroupGraphNode<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GroupGraphNode::Package(package) => package.fmt(f),
+            GroupGraphNode::Group(group) => write!(f, "{group}"),
+        }
+    }
+}
+
+/// An ALPM dependency graph with virtual nodes for package groups.
+pub type GroupGraphMap<'a> = DiGraphMap<GroupGraphNode<'a>, DependencyEdge>;
+
+/// Add virtual nodes for package groups to a dependency graph.
+///
+/// Copy all nodes and edges of `graph`, and add a virtual node for every group
+/// of a package in `graph`, with a required edge from the group to each of its
+/// members, as if the group depended on its members.
+pub fn with_groups<'a, G>(graph: G) -> GroupGraphMap<'a>
+where
+    G: GraphBase<NodeId = PackageNode<'a>>
+        + Data<EdgeWeight = DependencyEdge>
+        + IntoNodeIdentifiers
+        + IntoEdgeReferences,
+{
+    let mut g = DiGraphMap::new();
+    for node in graph.node_identifiers() {
+        g.add_node(GroupGraphNode::Package(node));
+        for group in node.package().groups() {
+            g.add_edge(
+                GroupGraphNode::Group(group),
+                GroupGraphNode::Package(node),
+                DependencyEdge::Required,
+            );
+        }
+    }
+    for edge in graph.edge_references() {
+        g.add_edge(
+            GroupGraphNode::Package(edge.source()),
+            GroupGraphNode::Package(edge.target()),
+            *edge.weight(),
+        );
+    }
+    g
+}
@@ -0,0 +1,193 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL-1.2 OR GPL-3.0
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Package groups.
+
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    fmt::Display,
+};
+
+use alpm::{AlpmList, Db};
+use petgraph::visit::{
+    Bfs, GraphRef, IntoNeighbors, IntoNodeIdentifiers, NodeCount, VisitMap as _, Visitable,
+};
+use tracing::warn;
+
+use crate::{
+    dependencies::orphans,
+    graph::{GroupGraphNode, PackageNode},
+};
+
+/// How much of a package group is installed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupStatus {
+    /// All members of the group are installed.
+    Installed,
+    /// Some members of the group are installed.
+    PartiallyInstalled,
+    /// No member of the group is installed.
+    NotInstalled,
+}
+
+impl Display for GroupStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            GroupStatus::Installed => write!(f, "installed"),
+            GroupStatus::PartiallyInstalled => write!(f, "partially installed"),
+            GroupStatus::NotInstalled => write!(f, "not installed"),
+        }
+    }
+}
+
+/// A package group, e.g. `base-devel`.
+#[derive(Debug, Clone)]
+pub struct PackageGroup<'a> {
+    /// The name of the group.
+    pub name: &'a str,
+    /// Installed members of the group, sorted by name.
+    pub installed: Vec<PackageNode<'a>>,
+    /// Members of the group in sync databases which are not installed, sorted by name.
+    pub missing: Vec<PackageNode<'a>>,
+    /// Installed members of the group which are orphans, sorted by name.
+    pub orphans: Vec<PackageNode<'a>>,
+}
+
+impl PackageGroup<'_> {
+    /// How much of this group is installed.
+    #[must_use]
+    pub fn status(&self) -> GroupStatus {
+        if self.installed.is_empty() {
+            GroupStatus::NotInstalled
+        } else if self.missing.is_empty() {
+            GroupStatus::Installed
+        } else {
+            GroupStatus::PartiallyInstalled
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Members<'a> {
+    installed: BTreeMap<&'a str, PackageNode<'a>>,
+    missing: BTreeMap<&'a str, PackageNode<'a>>,
+}
+
+/// Find all package groups.
+///
+/// Collect all groups in `syncdbs` and all groups of installed packages in
+/// `localdb`, along with their installed and missing members.  Determine
+/// which installed members are orphans in `graph` (see [`orphans`]).
+///
+/// Return groups sorted by name.
+pub fn package_groups<'a, G>(
+    graph: G,
+    localdb: &'a Db,
+    syncdbs: AlpmList<&'a Db>,
+) -> Vec<PackageGroup<'a>>
+where
+    G: GraphRef
+        + NodeCount
+        + Visitable<NodeId = PackageNode<'a>>
+        + IntoNeighbors
+        + IntoNodeIdentifiers,
+{
+    let mut groups = BTreeMap::<&str, Members>::new();
+    for db in syncdbs {
+        let db_groups = match db.groups() {
+            Ok(db_groups) => db_groups,
+            Err(error) => {
+                warn!("Failed to read groups of {}: {error}", db.name());
+                continue;
+            }
+        };
+        for group in db_groups {
+            let members = groups.entry(group.name()).or_default();
+            for package in group.packages() {
+                match localdb.pkg(package.name()) {
+                    Ok(installed) => {
+                        members
+                            .installed
+                            .insert(installed.name(), PackageNode::new(installed));
+                    }
+                    Err(_) => {
+                        members
+                            .missing
+                            .insert(package.name(), PackageNode::new(package));
+                    }
+                }
+            }
+        }
+    }
+    // Groups of installed packages may no longer exist in sync databases, or
+    // come from foreign packages.
+    for package in localdb.pkgs() {
+        for group in package.groups() {
+            groups
+                .entry(group)
+                .or_default()
+                .installed
+                .insert(package.name(), PackageNode::new(package));
+        }
+    }
+
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let orphans = orphans(graph).node_identifiers().collect::<HashSet<_>>();
+    groups
+        .into_iter()
+        .map(|(name, members)| {
+            let installed = members.installed.into_values().collect::<Vec<_>>();
+            let orphans = installed
+                .iter()
+                .copied()
+                .filter(|node| orphans.contains(node))
+                .collect();
+            PackageGroup {
+                name,
+                installed,
+                missing: members.missing.into_values().collect(),
+                orphans,
+            }
+        })
+        .collect()
+}
+
+/// Find all packages which a package group pulls in.
+///
+/// Walk `graph` from all virtual group nodes, see
+/// [`crate::graph::with_groups`], and return all packages reachable from any
+/// group, i.e. all group members and their dependencies.
+#[allow(
+    clippy::mutable_key_type,
+    reason = "We do not mutate the package pointer while traversing the graph"
+)]
+pub fn grouped_packages<'a, G>(graph: G) -> HashSet<PackageNode<'a>>
+where
+    G: GraphRef + Visitable<NodeId = GroupGraphNode<'a>> + IntoNeighbors + IntoNodeIdentifiers,
+{
+    // Share the visit map between all groups, to walk every package only once
+    let mut bfs = Bfs {
+        discovered: graph.visit_map(),
+        stack: VecDeque::new(),
+    };
+    let mut packages = HashSet::new();
+    for group in graph
+        .node_identifiers()
+        .filter(|node| matches!(node, GroupGraphNode::Group(_)))
+    {
+        bfs.discovered.visit(group);
+        bfs.stack.push_back(group);
+        while let Some(node) = bfs.next(graph) {
+            if let GroupGraphNode::Package(package) = node {
+                packages.insert(package);
+            }
+        }
+    }
+    packages
+}
@@ -33,6 +33,25 @@
 )]
 #![forbid(unsafe_code)]
 
+pub mod advisories;
 pub mod alpm;
+pub mod archives;
+pub mod cache;
+pub mod conflicts;
 pub mod dependencies;
+pub mod diff;
+pub mod downgrade;
+pub mod elf;
 pub mod graph;
+pub mod groups;
+pub mod linkcheck;
+pub mod lostfiles;
+pub mod mtree;
+pub mod package_list;
+pub mod pacman_log;
+pub mod pacnew;
+pub mod srcinfo;
+pub mod stats;
+pub mod upgrade;
+pub mod upstream;
+pub mod verify;
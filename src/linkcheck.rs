@@ -0,0 +1,234 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL-1.2 OR GPL-3.0
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Check dependencies of packages against the libraries they link.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
+};
+
+use alpm::{Db, Package};
+use petgraph::visit::{Bfs, GraphRef, IntoNeighbors, Visitable, Walker};
+use tracing::{debug, warn};
+
+use crate::{elf::needed_libraries, graph::PackageNode};
+
+/// Directories which contain shared libraries, relative to the root directory.
+const LIBRARY_DIRECTORIES: [&str; 2] = ["usr/lib/", "usr/lib32/"];
+
+/// Shared libraries of installed packages.
+#[derive(Debug)]
+pub struct LibraryOwners<'a> {
+    owners: HashMap<String, Vec<&'a Package>>,
+}
+
+impl<'a> LibraryOwners<'a> {
+    /// Find all shared libraries in the file lists of all packages in `db`.
+    ///
+    /// Consider all files directly in a system library directory, e.g.
+    /// `/usr/lib`, whose name contains `.so` as shared libraries.
+    pub fn from_db(db: &'a Db) -> Self {
+        let mut owners = HashMap::<_, Vec<_>>::new();
+        for package in db.pkgs() {
+            for file in package.files().files() {
+                let Some(soname) = LIBRARY_DIRECTORIES
+                    .iter()
+                    .find_map(|directory| file.name().strip_prefix(directory))
+                else {
+                    continue;
+                };
+                if !soname.contains('/') && soname.contains(".so") {
+                    owners.entry(soname.to_owned()).or_default().push(package);
+                }
+            }
+        }
+        Self { owners }
+    }
+
+    /// Get all packages which own a library with the given `soname`.
+    pub fn owners(&self, soname: &str) -> &[&'a Package] {
+        self.owners.get(soname).map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether `package` owns any shared library.
+    pub fn owns_libraries(&self, package: &Package) -> bool {
+        package.files().files().iter().any(|file| {
+            LIBRARY_DIRECTORIES
+                .iter()
+                .filter_map(|directory| file.name().strip_prefix(directory))
+                .any(|soname| self.owners.contains_key(soname))
+        })
+    }
+}
+
+/// The libraries a package links against.
+#[derive(Debug)]
+pub struct LinkedLibraries<'a> {
+    /// Whether the package contains any ELF file.
+    pub has_elf_files: bool,
+    /// All libraries the package links against, with their owners.
+    ///
+    /// Libraries the package contains itself are not included.  The list of owners
+    /// is empty if no installed package owns the library.
+    pub libraries: BTreeMap<String, Vec<&'a Package>>,
+}
+
+/// Find all libraries which a package links against.
+///
+/// Read the `DT_NEEDED` entries of all ELF files of `package` under `root`, and
+/// look up the owners of each library in `owners`.
+///
+/// Log and ignore files which cannot be read.
+pub fn linked_libraries<'a>(
+    root: &Path,
+    owners: &LibraryOwners<'a>,
+    package: &'a Package,
+) -> LinkedLibraries<'a> {
+    // Libraries of the package itself, in any directory, e.g. in a private
+    // library directory on the package's rpath
+    let own_files = package
+        .files()
+        .files()
+        .iter()
+        .filter_map(|file| file.name().rsplit('/').next().map(ToOwned::to_owned))
+        .collect::<HashSet<_>>();
+    let mut has_elf_files = false;
+    let mut libraries = BTreeMap::new();
+    for file in package.files().files() {
+        if file.name().ends_with('/') {
+            continue;
+        }
+        let path = root.join(file.name());
+        // Skip symlinks to avoid reading the same library twice
+        if path.symlink_metadata().is_ok_and(|m| !m.is_file()) {
+            continue;
+        }
+        match needed_libraries(&path) {
+            Ok(None) => {}
+            Ok(Some(needed)) => {
+                has_elf_files = true;
+                for soname in needed {
+                    if !own_files.contains(&soname) {
+                        let library_owners = owners.owners(&soname).to_vec();
+                        libraries.insert(soname, library_owners);
+                    }
+                }
+            }
+            Err(error) => {
+                warn!(
+                    package = package.name(),
+                    "Failed to read {}: {error}",
+                    path.display()
+                );
+            }
+        }
+    }
+    LinkedLibraries {
+        has_elf_files,
+        libraries,
+    }
+}
+
+/// A library which a package links against without declaring a dependency on
+/// the library's owner.
+#[derive(Debug, Clone)]
+pub struct UndeclaredLibrary<'a> {
+    /// The soname of the library.
+    pub soname: String,
+    /// The packages owning the library.
+    pub owners: Vec<&'a Package>,
+}
+
+/// The result of checking a package's dependencies against its libraries.
+#[derive(Debug)]
+pub struct LinkCheck<'a> {
+    /// The package.
+    pub package: PackageNode<'a>,
+    /// Libraries the package links against without depending on their owner.
+    pub undeclared: Vec<UndeclaredLibrary<'a>>,
+    /// Libraries the package links against, which no installed package owns.
+    pub unresolved: Vec<String>,
+    /// Dependencies which own shared libraries none of which the package links against.
+    pub unused: Vec<PackageNode<'a>>,
+}
+
+/// Check the dependencies of `package` against the libraries it links.
+///
+/// Report all `libraries` whose owners `package` doesn't depend on, either
+/// directly or, if `transitive` is true, transitively.
+///
+/// If `package` contains ELF files also report all direct dependencies which
+/// own shared libraries, but none that `package` links against.
+pub fn check_package<'a, G>(
+    graph: G,
+    owners: &LibraryOwners<'a>,
+    package: PackageNode<'a>,
+    libraries: &LinkedLibraries<'a>,
+    transitive: bool,
+) -> LinkCheck<'a>
+where
+    G: GraphRef + Visitable<NodeId = PackageNode<'a>> + IntoNeighbors,
+{
+    let direct_dependencies = graph.neighbors(package).collect::<Vec<_>>();
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let dependencies = if transitive {
+        Bfs::new(graph, package).iter(graph).collect::<HashSet<_>>()
+    } else {
+        direct_dependencies.iter().copied().collect::<HashSet<_>>()
+    };
+
+    let mut undeclared = Vec::new();
+    let mut unresolved = Vec::new();
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let mut linked_owners = HashSet::new();
+    for (soname, library_owners) in &libraries.libraries {
+        if library_owners.is_empty() {
+            unresolved.push(soname.clone());
+            continue;
+        }
+        linked_owners.extend(library_owners.iter().copied().map(PackageNode::new));
+        if !library_owners
+            .iter()
+            .any(|owner| dependencies.contains(&PackageNode::new(owner)))
+        {
+            debug!(
+                package = package.name(),
+                "{} links {soname} without depending on its owner",
+                package.name()
+            );
+            undeclared.push(UndeclaredLibrary {
+                soname: soname.clone(),
+                owners: library_owners.clone(),
+            });
+        }
+    }
+
+    let mut unused = if libraries.has_elf_files {
+        direct_dependencies
+            .into_iter()
+            .filter(|dependency| {
+                !linked_owners.contains(dependency) && owners.owns_libraries(dependency.package())
+            })
+            .collect::<Vec<_>>()
+    } else {
+        Vec::new()
+    };
+    unused.sort_by_key(|node| node.package().name());
+
+    LinkCheck {
+        package,
+        undeclared,
+        unresolved,
+        unused,
+    }
+}
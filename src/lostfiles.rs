@@ -0,0 +1,247 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL-1.2 OR GPL-3.0
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Find files which no package owns.
+
+use std::{collections::HashSet, hash::BuildHasher, path::Path};
+
+use alpm::Db;
+use tracing::{debug, warn};
+
+/// A pattern for paths to ignore.
+///
+/// `*` matches any sequence of characters, including `/`, and `?` matches any
+/// single character.  All other characters match themselves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IgnorePattern(String);
+
+impl IgnorePattern {
+    /// Create a new pattern.
+    #[must_use]
+    pub fn new(pattern: String) -> Self {
+        Self(pattern)
+    }
+
+    /// Whether `path` matches this pattern.
+    #[must_use]
+    pub fn matches(&self, path: &str) -> bool {
+        let pattern = self.0.chars().collect::<Vec<_>>();
+        let path = path.chars().collect::<Vec<_>>();
+        let (mut p, mut s) = (0, 0);
+        // Position of the last star in the pattern, and of the path where we
+        // started matching after that star
+        let mut backtrack = None;
+        while let Some(c) = path.get(s) {
+            match pattern.get(p) {
+                Some('*') => {
+                    backtrack = Some((p, s));
+                    p += 1;
+                }
+                Some(expected) if *expected == '?' || expected == c => {
+                    p += 1;
+                    s += 1;
+                }
+                _ => match backtrack {
+                    Some((star, start)) => {
+                        p = star + 1;
+                        s = start + 1;
+                        backtrack = Some((star, start + 1));
+                    }
+                    None => return false,
+                },
+            }
+        }
+        pattern
+            .get(p..)
+            .is_some_and(|rest| rest.iter().all(|c| *c == '*'))
+    }
+}
+
+/// Normalise a path from a package file list for lookup in owned files.
+///
+/// File lists contain paths relative to the root directory, with a trailing
+/// slash for directories; strip the trailing slash.
+fn owned_path(name: &str) -> &str {
+    name.trim_end_matches('/')
+}
+
+/// Join `name` to the `relative` path of a directory.
+///
+/// `relative` is empty for the root directory.
+fn child_path(relative: &str, name: &str) -> String {
+    if relative.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{relative}/{name}")
+    }
+}
+
+/// Get all files and directories which packages in `db` own.
+///
+/// Return paths relative to the root directory, without trailing slashes.
+pub fn owned_files(db: &Db) -> HashSet<String> {
+    let mut owned = HashSet::new();
+    for package in db.pkgs() {
+        for file in package.files().files() {
+            owned.insert(owned_path(file.name()).to_owned());
+        }
+    }
+    owned
+}
+
+/// A file or directory which no package owns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LostFile {
+    /// The absolute path of the file, as seen from within the root directory.
+    pub path: String,
+    /// Whether the file is a directory.
+    ///
+    /// No package owns any file in a lost directory.
+    pub is_directory: bool,
+}
+
+fn walk<S: BuildHasher>(
+    root: &Path,
+    relative: &str,
+    owned: &HashSet<String, S>,
+    ignore: &[IgnorePattern],
+    lost: &mut Vec<LostFile>,
+) {
+    let directory = root.join(relative);
+    let entries = match std::fs::read_dir(&directory) {
+        Ok(entries) => entries,
+        Err(error) => {
+            warn!("Failed to read directory {}: {error}", directory.display());
+            return;
+        }
+    };
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                warn!("Failed to read directory {}: {error}", directory.display());
+                continue;
+            }
+        };
+        let path = child_path(relative, &entry.file_name().to_string_lossy());
+        if ignore
+            .iter()
+            .any(|pattern| pattern.matches(&format!("/{path}")))
+        {
+            debug!("Ignoring /{path}");
+            continue;
+        }
+        // Do not follow symlinks to directories
+        let is_directory = entry.file_type().is_ok_and(|t| t.is_dir());
+        if !owned.contains(&path) {
+            lost.push(LostFile {
+                path: format!("/{path}"),
+                is_directory,
+            });
+        } else if is_directory {
+            walk(root, &path, owned, ignore, lost);
+        }
+    }
+}
+
+/// Find all files and directories under `directories` which no package owns.
+///
+/// Recursively walk all `directories`, relative to `root`, and report every
+/// file or directory which is not in `owned` (see [`owned_files`]) and matches
+/// no pattern in `ignore`.  Report lost directories as a whole, without their
+/// contents.  Patterns match absolute paths as seen from within `root`, e.g.
+/// `/etc/ssh/ssh_host_*`.
+///
+/// Log and skip directories which cannot be read.
+///
+/// Return lost files sorted by path.
+#[must_use]
+pub fn lost_files<S: BuildHasher>(
+    root: &Path,
+    directories: &[String],
+    owned: &HashSet<String, S>,
+    ignore: &[IgnorePattern],
+) -> Vec<LostFile> {
+    let mut lost = Vec::new();
+    for directory in directories {
+        let directory = directory.trim_matches('/');
+        walk(root, directory, owned, ignore, &mut lost);
+    }
+    lost.sort_by(|a, b| a.path.cmp(&b.path));
+    lost.dedup();
+    lost
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(pattern: &str) -> IgnorePattern {
+        IgnorePattern::new(pattern.to_owned())
+    }
+
+    #[test]
+    fn pattern_matches_literally() {
+        assert!(pattern("/etc/fstab").matches("/etc/fstab"));
+        assert!(!pattern("/etc/fstab").matches("/etc/fstab.bak"));
+        assert!(!pattern("/etc/fstab").matches("/etc"));
+    }
+
+    #[test]
+    fn pattern_matches_wildcards() {
+        let ssh = pattern("/etc/ssh/ssh_host_*");
+        assert!(ssh.matches("/etc/ssh/ssh_host_ed25519_key"));
+        assert!(ssh.matches("/etc/ssh/ssh_host_"));
+        assert!(!ssh.matches("/etc/ssh/sshd_config"));
+        assert!(pattern("/var/*/cache").matches("/var/lib/foo/cache"));
+        assert!(pattern("/home/*").matches("/home/user/.cache"));
+        assert!(pattern("*.pyc").matches("/usr/lib/foo.pyc"));
+        assert!(pattern("/etc/?sh").matches("/etc/ssh"));
+        assert!(!pattern("/etc/?sh").matches("/etc/sh"));
+        assert!(pattern("**").matches(""));
+    }
+
+    #[test]
+    fn owned_path_strips_trailing_slash() {
+        assert_eq!(owned_path("usr/"), "usr");
+        assert_eq!(owned_path("usr/bin/"), "usr/bin");
+        assert_eq!(owned_path("usr/bin/ls"), "usr/bin/ls");
+    }
+
+    #[test]
+    fn child_path_of_root() {
+        assert_eq!(child_path("", "usr"), "usr");
+        assert_eq!(child_path("usr", "bin"), "usr/bin");
+    }
+
+    #[test]
+    fn lost_files_from_root_directory() {
+        let root = std::env::temp_dir().join(format!("packit-lostfiles-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("usr/bin")).unwrap();
+        std::fs::create_dir_all(root.join("srv/data")).unwrap();
+        std::fs::write(root.join("usr/bin/ls"), "").unwrap();
+        std::fs::write(root.join("usr/bin/stray"), "").unwrap();
+        let owned = ["usr/", "usr/bin/", "usr/bin/ls"]
+            .into_iter()
+            .map(|name| owned_path(name).to_owned())
+            .collect::<HashSet<_>>();
+        let lost = lost_files(&root, &["/".to_owned()], &owned, &[]);
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            lost,
+            [
+                LostFile {
+                    path: "/srv".to_owned(),
+                    is_directory: true,
+                },
+                LostFile {
+                    path: "/usr/bin/stray".to_owned(),
+                    is_directory: false,
+                },
+            ]
+        );
+    }
+}
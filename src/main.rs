@@ -31,70 +31,155 @@
 )]
 #![forbid(unsafe_code)]
 
-use alpm::{Alpm, Package};
-use clap::Parser;
-use packit::graph::{DependencyEdge, PackageNode};
-use petgraph::visit::{
-    Data, EdgeFiltered, EdgeRef, GraphProp, GraphRef, IntoEdgeReferences, IntoNeighbors,
-    IntoNeighborsDirected, IntoNodeIdentifiers, IntoNodeReferences, NodeCount, NodeIndexable,
-    Visitable,
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    io::{BufReader, Write},
+    path::Path,
+};
+
+use alpm::{Alpm, Package, PackageReason, SigLevel};
+use alpm_utils::{DbListExt as _, config::Config};
+use clap::{CommandFactory, Parser, error::ErrorKind};
+use packit::{
+    archives::{PackageInfo, TemporaryDatabase},
+    cache::RetentionPolicy,
+    downgrade::VersionCheck,
+    graph::{DependencyEdge, PackageNode},
+    lostfiles::IgnorePattern,
+    package_list::PackageList,
+    srcinfo::SourcePackage,
+    verify::VerifyPackage,
+};
+use petgraph::{
+    algo::tarjan_scc,
+    visit::{
+        Data, EdgeFiltered, EdgeRef, GraphBase, GraphProp, GraphRef, IntoEdgeReferences, IntoEdges,
+        IntoNeighbors, IntoNeighborsDirected, IntoNodeIdentifiers, IntoNodeReferences, NodeCount,
+        NodeFiltered, NodeIndexable, Visitable,
+    },
 };
 
 use crate::{
     args::CliArgs,
-    print::{print_package_graph, print_package_one_line},
+    print::{
+        PrintOneLine, print_alternatives, print_alternatives_json, print_batches,
+        print_batches_json, print_blast_radii, print_blast_radii_json, print_cached_packages,
+        print_cached_packages_json, print_closure_sizes, print_closure_sizes_json,
+        print_conflict_graph, print_conflicts, print_conflicts_json, print_constraints,
+        print_constraints_json, print_diff, print_findings, print_findings_json,
+        print_foreign_packages, print_foreign_packages_json, print_graph_statistics,
+        print_graph_statistics_json, print_group_graph, print_groups, print_groups_json,
+        print_link_checks, print_link_checks_json, print_lost_files, print_lost_files_json,
+        print_orphan, print_package_graph, print_package_graph_json, print_package_history,
+        print_package_history_json, print_package_one_line, print_provision_graph,
+        print_provision_graph_json, print_reconciliation, print_saved_files,
+        print_saved_files_json, print_size_footer, print_source_graph, print_source_graph_json,
+        print_source_packages, print_stale_packages, print_stale_packages_json,
+        print_upgrade_preview, print_upgrade_preview_json, print_verifications,
+        print_verifications_json, print_version_checks, print_version_checks_json,
+    },
 };
 
 mod args;
 mod print;
 
-fn list_orphans<'a, G>(options: &args::Orphans, graph: G) -> std::io::Result<()>
+/// Evaluate `$body` with `$graph` bound to a reference to `$pkg_graph`, or to
+/// its subgraph of required dependencies if `$ignore_optdepends` is true.
+///
+/// A closure cannot be generic over the type of the graph, hence a macro.
+macro_rules! with_graph {
+    ($pkg_graph:expr, $ignore_optdepends:expr, |$graph:ident| $body:expr) => {
+        if $ignore_optdepends {
+            let $graph = &EdgeFiltered::from_fn(&$pkg_graph, |edge| {
+                *edge.weight() == DependencyEdge::Required
+            });
+            $body
+        } else {
+            let $graph = &$pkg_graph;
+            $body
+        }
+    };
+}
+
+fn print_subgraph<'a, G>(options: &args::GraphOptions, graph: G) -> std::io::Result<()>
 where
-    G: GraphRef
-        + GraphProp
+    G: GraphProp
+        + GraphBase<NodeId = PackageNode<'a>>
         + Data<EdgeWeight = DependencyEdge, NodeWeight = PackageNode<'a>>
-        + NodeCount
         + NodeIndexable
-        + Visitable<NodeId = PackageNode<'a>>
-        + IntoNeighbors
         + IntoNodeIdentifiers
         + IntoEdgeReferences
         + IntoNodeReferences,
 {
-    let orphans = packit::dependencies::orphans(&graph);
-
     let mut stdout = anstream::stdout().lock();
 
-    if options.graph_options.dot {
-        print_package_graph(&mut stdout, graph, options.graph_options.oneline_style())
+    if options.provisions {
+        let graph = packit::graph::with_provisions(graph);
+        if options.dot {
+            print_provision_graph(&mut stdout, &graph, options.oneline_style())
+        } else {
+            print_provision_graph_json(&mut stdout, &graph)
+        }
+    } else if options.dot {
+        print_package_graph(&mut stdout, graph, options.oneline_style())
+    } else if options.json {
+        print_package_graph_json(&mut stdout, graph)
     } else {
-        let mut orphan_nodes = orphans
+        let mut nodes = graph
             .node_identifiers()
             .map(PackageNode::package)
             .collect::<Vec<_>>();
         // Sort alphabetically
-        orphan_nodes.sort_by_key(|pkg| pkg.name());
+        nodes.sort_by_key(|pkg| pkg.name());
 
-        for pkg in orphan_nodes {
-            print_package_one_line(&mut stdout, pkg, options.graph_options.oneline_style())?;
+        for pkg in nodes {
+            print_package_one_line(&mut stdout, pkg, options.oneline_style())?;
+        }
+        if !options.quiet {
+            print_size_footer(&mut stdout, packit::dependencies::installed_size(graph))?;
         }
         Ok(())
     }
 }
 
+fn list_orphans<'a, G>(options: &args::Orphans, graph: G) -> std::io::Result<()>
+where
+    G: GraphRef
+        + GraphProp
+        + Data<EdgeWeight = DependencyEdge, NodeWeight = PackageNode<'a>>
+        + NodeCount
+        + NodeIndexable
+        + Visitable<NodeId = PackageNode<'a>>
+        + IntoNeighbors
+        + IntoNodeIdentifiers
+        + IntoEdgeReferences
+        + IntoNodeReferences,
+{
+    if options.group_roots {
+        #[allow(
+            clippy::mutable_key_type,
+            reason = "We do not mutate the package pointer while traversing the graph"
+        )]
+        let grouped = packit::groups::grouped_packages(&packit::graph::with_groups(graph));
+        let orphans = packit::dependencies::orphans_with_roots(&graph, |package| {
+            package.reason() == PackageReason::Explicit || grouped.contains(&package)
+        });
+        print_subgraph(&options.graph_options, &orphans)
+    } else {
+        let orphans = packit::dependencies::orphans(&graph);
+        print_subgraph(&options.graph_options, &orphans)
+    }
+}
+
 fn orphans_command(options: &args::Orphans, alpm: &Alpm) -> std::io::Result<()> {
     let localdb = alpm.localdb();
     let pkg_graph = packit::graph::build_graph_for_localdb(localdb);
-    if options.graph_options.ignore_optdepends {
-        list_orphans(
-            options,
-            &EdgeFiltered::from_fn(&pkg_graph, |edge| {
-                *edge.weight() == DependencyEdge::Required
-            }),
-        )
-    } else {
-        list_orphans(options, &pkg_graph)
-    }
+    with_graph!(
+        pkg_graph,
+        options.graph_options.ignore_optdepends,
+        |graph| list_orphans(options, graph)
+    )
 }
 
 fn list_dependents<'a, G>(
@@ -114,58 +199,897 @@ where
         + IntoNodeReferences
         + IntoEdgeReferences,
 {
-    let mut stdout = anstream::stdout().lock();
     let dependents = packit::dependencies::dependents(&pkg_graph, package);
-    if options.graph_options.dot {
-        print_package_graph(
-            &mut stdout,
-            &dependents,
-            options.graph_options.oneline_style(),
-        )
+    print_subgraph(&options.graph_options, &dependents)
+}
+
+fn dependents_command(options: &args::Dependents, alpm: &Alpm) -> std::io::Result<()> {
+    let localdb = alpm.localdb();
+    let source_pkg = localdb
+        .pkg(options.package.as_str())
+        .map_err(std::io::Error::other)?;
+    let pkg_graph = packit::graph::build_graph_for_localdb(localdb);
+
+    with_graph!(
+        pkg_graph,
+        options.graph_options.ignore_optdepends,
+        |graph| list_dependents(options, graph, source_pkg)
+    )
+}
+
+fn list_dependencies<'a, G>(
+    options: &args::Dependencies,
+    pkg_graph: G,
+    package: &'a Package,
+) -> std::io::Result<()>
+where
+    G: GraphRef
+        + GraphProp
+        + Data<EdgeWeight = DependencyEdge, NodeWeight = PackageNode<'a>>
+        + Visitable<NodeId = PackageNode<'a>>
+        + NodeIndexable
+        + IntoNeighbors
+        + IntoNodeIdentifiers
+        + IntoNodeReferences
+        + IntoEdgeReferences,
+{
+    let dependencies = packit::dependencies::dependencies(&pkg_graph, package);
+    print_subgraph(&options.graph_options, &dependencies)
+}
+
+fn dependencies_command(options: &args::Dependencies, alpm: &Alpm) -> std::io::Result<()> {
+    let localdb = alpm.localdb();
+    let source_pkg = localdb
+        .pkg(options.package.as_str())
+        .map_err(std::io::Error::other)?;
+    let pkg_graph = packit::graph::build_graph_for_localdb(localdb);
+
+    with_graph!(
+        pkg_graph,
+        options.graph_options.ignore_optdepends,
+        |graph| list_dependencies(options, graph, source_pkg)
+    )
+}
+
+fn alternatives_command(options: &args::Alternatives, alpm: &Alpm) -> std::io::Result<()> {
+    let localdb = alpm.localdb();
+    let package = localdb
+        .pkg(options.package.as_str())
+        .map_err(std::io::Error::other)?;
+    let pkg_graph = packit::graph::build_graph_for_localdb(localdb);
+    let alternatives = with_graph!(pkg_graph, options.ignore_optdepends, |graph| {
+        packit::upstream::alternatives(graph, localdb, alpm.syncdbs(), package)
+    });
+
+    let mut stdout = anstream::stdout().lock();
+    if options.json {
+        print_alternatives_json(&mut stdout, &alternatives)
     } else {
-        todo!()
+        print_alternatives(&mut stdout, &alternatives)
     }
 }
 
-fn dependents_command(options: &args::Dependents, alpm: &Alpm) -> std::io::Result<()> {
+fn audit_command(options: &args::Audit, alpm: &Alpm) -> std::io::Result<()> {
+    let file = std::fs::File::open(&options.advisories)?;
+    let advisories = packit::advisories::read_advisories(std::io::BufReader::new(file))?;
+    let pkg_graph = packit::graph::build_graph_for_localdb(alpm.localdb());
+    let findings = with_graph!(pkg_graph, options.ignore_optdepends, |graph| {
+        packit::advisories::audit(graph, &advisories)
+    });
+
+    let mut stdout = anstream::stdout().lock();
+    if options.json {
+        print_findings_json(&mut stdout, &findings)
+    } else {
+        print_findings(&mut stdout, &findings)
+    }
+}
+
+fn can_downgrade_command(
+    options: &args::CanDowngrade,
+    config: &Config,
+    alpm: &Alpm,
+) -> std::io::Result<()> {
+    let localdb = alpm.localdb();
+    let package = localdb
+        .pkg(options.package.as_str())
+        .map_err(std::io::Error::other)?;
+    let graph = packit::graph::build_graph_for_localdb(localdb);
+    let mut constraints = packit::graph::constraints_on(&graph, PackageNode::new(package));
+    if options.ignore_optdepends {
+        constraints.retain(|constraint| constraint.edge == DependencyEdge::Required);
+    }
+    let mut archives = packit::cache::scan_cache(&config.cache_dir);
+    // Newest versions first
+    archives.sort_by(|a, b| {
+        alpm::vercmp(b.package.version.as_str(), a.package.version.as_str())
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    let cached =
+        packit::downgrade::check_cached_versions(alpm, &constraints, package.name(), &archives);
+    let target = cached
+        .iter()
+        .find(|check| check.version == options.version)
+        .cloned()
+        .unwrap_or_else(|| VersionCheck::new(&constraints, package.name(), &options.version, None));
+
+    let mut stdout = anstream::stdout().lock();
+    if options.json {
+        print_version_checks_json(&mut stdout, package, &target, &cached)
+    } else {
+        print_version_checks(&mut stdout, package, &target, &cached)
+    }
+}
+
+fn constraints_command(options: &args::Constraints, alpm: &Alpm) -> std::io::Result<()> {
+    let localdb = alpm.localdb();
+    let package = localdb
+        .pkg(options.package.as_str())
+        .map_err(std::io::Error::other)?;
+    let graph = packit::graph::build_graph_for_localdb(localdb);
+    let mut constraints = packit::graph::constraints_on(&graph, PackageNode::new(package));
+    constraints.retain(|constraint| {
+        (!options.ignore_optdepends || constraint.edge == DependencyEdge::Required)
+            && (!options.versioned || constraint.dependency.version.is_some())
+    });
+    let mut stdout = anstream::stdout().lock();
+    if options.json {
+        print_constraints_json(&mut stdout, &constraints)
+    } else {
+        print_constraints(&mut stdout, &constraints)
+    }
+}
+
+fn check_command(options: &args::Check, alpm: &Alpm) -> std::io::Result<()> {
+    let localdb = alpm.localdb();
+    let additions = options
+        .additions
+        .iter()
+        .map(|name| alpm.syncdbs().pkg(name.as_str()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(std::io::Error::other)?;
+    // Additions replace installed packages of the same name
+    let mut packages = localdb
+        .pkgs()
+        .into_iter()
+        .filter(|package| {
+            !additions
+                .iter()
+                .any(|addition| addition.name() == package.name())
+        })
+        .collect::<Vec<_>>();
+    packages.extend(additions.iter().copied());
+    let mut conflicts = packit::conflicts::find_conflicts(&packages);
+    if !additions.is_empty() {
+        let additions = additions
+            .iter()
+            .copied()
+            .map(PackageNode::new)
+            .collect::<Vec<_>>();
+        conflicts.retain(|conflict| {
+            additions.contains(&conflict.package) || additions.contains(&conflict.conflicting)
+        });
+    }
+
+    let mut stdout = anstream::stdout().lock();
+    if options.dot {
+        let pkg_graph = packit::graph::build_graph_for_localdb(localdb);
+        print_conflict_graph(&mut stdout, &pkg_graph, &conflicts)
+    } else if options.json {
+        print_conflicts_json(&mut stdout, &conflicts)
+    } else {
+        print_conflicts(&mut stdout, &conflicts)
+    }
+}
+
+fn list_groups<'a, G>(options: &args::Groups, graph: G, alpm: &'a Alpm) -> std::io::Result<()>
+where
+    G: GraphRef
+        + Data<EdgeWeight = DependencyEdge>
+        + NodeCount
+        + Visitable<NodeId = PackageNode<'a>>
+        + IntoNeighbors
+        + IntoNodeIdentifiers
+        + IntoEdgeReferences,
+{
+    let mut groups = packit::groups::package_groups(graph, alpm.localdb(), alpm.syncdbs());
+    if options.installed {
+        groups.retain(|group| !group.installed.is_empty());
+    }
+
+    let mut stdout = anstream::stdout().lock();
+    if options.dot {
+        print_group_graph(&mut stdout, graph, &groups)
+    } else if options.json {
+        print_groups_json(&mut stdout, &groups)
+    } else {
+        print_groups(&mut stdout, &groups)
+    }
+}
+
+fn groups_command(options: &args::Groups, alpm: &Alpm) -> std::io::Result<()> {
+    let pkg_graph = packit::graph::build_graph_for_localdb(alpm.localdb());
+    with_graph!(pkg_graph, options.ignore_optdepends, |graph| {
+        list_groups(options, graph, alpm)
+    })
+}
+
+fn export_command(alpm: &Alpm) -> std::io::Result<()> {
+    let pkg_graph = packit::graph::build_graph_for_localdb(alpm.localdb());
+    let packages = packit::package_list::minimal_explicit_packages(&pkg_graph);
+    let list = PackageList::from_packages(
+        packages.into_iter().map(PackageNode::package),
+        alpm.syncdbs(),
+    );
+    write!(anstream::stdout().lock(), "{list}")
+}
+
+fn reconcile_command(options: &args::Reconcile, alpm: &Alpm) -> std::io::Result<()> {
+    let list = PackageList::read(BufReader::new(File::open(&options.list)?))?;
+    let localdb = alpm.localdb();
+    let pkg_graph = packit::graph::build_graph_for_localdb(localdb);
+    let reconciliation = with_graph!(pkg_graph, options.ignore_optdepends, |graph| {
+        packit::package_list::reconcile(graph, localdb, alpm.syncdbs(), &list)
+    });
+    print_reconciliation(&mut anstream::stdout().lock(), &reconciliation)
+}
+
+fn verify_command(options: &args::Verify, alpm: &Alpm) -> std::io::Result<()> {
+    let localdb = alpm.localdb();
+    let packages = if options.packages.is_empty() {
+        localdb.pkgs().into_iter().collect::<Vec<_>>()
+    } else {
+        options
+            .packages
+            .iter()
+            .map(|name| localdb.pkg(name.as_str()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(std::io::Error::other)?
+    };
+    let local_path = Path::new(alpm.dbpath()).join("local");
+    let packages = packages
+        .into_iter()
+        .map(|package| VerifyPackage {
+            name: package.name().to_owned(),
+            mtree: local_path
+                .join(format!("{}-{}", package.name(), package.version()))
+                .join("mtree"),
+            backup: package
+                .backup()
+                .iter()
+                .map(|backup| backup.name().to_owned())
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    let verifications = packit::verify::verify_packages(Path::new(alpm.root()), &packages);
+
+    let mut stdout = anstream::stdout().lock();
+    if options.json {
+        print_verifications_json(&mut stdout, &verifications)
+    } else {
+        print_verifications(&mut stdout, &verifications)
+    }
+}
+
+fn lostfiles_command(options: &args::Lostfiles, alpm: &Alpm) -> std::io::Result<()> {
+    let owned = packit::lostfiles::owned_files(alpm.localdb());
+    let ignore = options
+        .ignore
+        .iter()
+        .cloned()
+        .map(IgnorePattern::new)
+        .collect::<Vec<_>>();
+    let files = packit::lostfiles::lost_files(
+        Path::new(alpm.root()),
+        &options.directories,
+        &owned,
+        &ignore,
+    );
+
+    let mut stdout = anstream::stdout().lock();
+    if options.json {
+        print_lost_files_json(&mut stdout, &files)
+    } else {
+        print_lost_files(&mut stdout, &files)
+    }
+}
+
+fn cache_command(options: &args::Cache, config: &Config, alpm: &Alpm) -> std::io::Result<()> {
+    let localdb = alpm.localdb();
+    let pkg_graph = packit::graph::build_graph_for_localdb(localdb);
+    let orphans = with_graph!(pkg_graph, options.ignore_optdepends, |graph| {
+        packit::dependencies::orphans(graph)
+            .node_identifiers()
+            .map(|package| package.package().name())
+            .collect::<HashSet<_>>()
+    });
+    let archives = packit::cache::scan_cache(&config.cache_dir);
+    let packages = packit::cache::cached_packages(archives, localdb, &orphans);
+    let policy = RetentionPolicy {
+        keep: options.keep,
+        keep_removed: options.keep_removed,
+        remove_orphans: options.remove_orphans,
+    };
+
+    let mut stdout = anstream::stdout().lock();
+    if options.json {
+        print_cached_packages_json(&mut stdout, &packages, &policy)
+    } else if options.removable {
+        for archive in packages
+            .iter()
+            .flat_map(|package| policy.removable(package))
+        {
+            writeln!(stdout, "{}", archive.path.display())?;
+            if let Some(signature) = &archive.signature {
+                writeln!(stdout, "{}", signature.display())?;
+            }
+        }
+        Ok(())
+    } else {
+        print_cached_packages(&mut stdout, &packages, &policy)
+    }
+}
+
+fn pacnew_command(options: &args::Pacnew, alpm: &Alpm) -> std::io::Result<()> {
+    let localdb = alpm.localdb();
+    let packages = if options.packages.is_empty() {
+        localdb.pkgs().into_iter().collect::<Vec<_>>()
+    } else {
+        options
+            .packages
+            .iter()
+            .map(|name| localdb.pkg(name.as_str()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(std::io::Error::other)?
+    };
+    let root = Path::new(alpm.root());
+    let saved_files = packit::pacnew::find_saved_files(root, packages);
+    let unowned = if options.packages.is_empty() {
+        let backups = packit::pacnew::backup_files(localdb.pkgs());
+        packit::pacnew::find_unowned_saved_files(root, "/etc", &backups)
+    } else {
+        Vec::new()
+    };
+
+    let mut stdout = anstream::stdout().lock();
+    if options.json {
+        print_saved_files_json(&mut stdout, &saved_files, &unowned)
+    } else if options.diff {
+        let files = saved_files.iter().flat_map(|saved| &saved.files);
+        for file in files.chain(&unowned) {
+            match file.diff(root) {
+                Ok(diff) => print_diff(&mut stdout, &diff)?,
+                Err(error) => tracing::warn!("Failed to diff {}: {error}", file.saved_path()),
+            }
+        }
+        Ok(())
+    } else {
+        print_saved_files(&mut stdout, &saved_files, &unowned)
+    }
+}
+
+fn rebuild_order_command(options: &args::RebuildOrder, alpm: &Alpm) -> std::io::Result<()> {
     let localdb = alpm.localdb();
     let source_pkg = localdb
         .pkg(options.package.as_str())
         .map_err(std::io::Error::other)?;
     let pkg_graph = packit::graph::build_graph_for_localdb(localdb);
+    let mut batches = with_graph!(pkg_graph, options.ignore_optdepends, |graph| {
+        packit::dependencies::rebuild_order(graph, source_pkg)
+    });
+    if options.foreign {
+        let syncdbs = alpm.syncdbs();
+        for batch in &mut batches {
+            batch.retain(|node| packit::alpm::is_foreign(node, syncdbs));
+        }
+        batches.retain(|batch| !batch.is_empty());
+    }
+
+    let mut stdout = anstream::stdout().lock();
+    if options.json {
+        print_batches_json(&mut stdout, &batches)
+    } else {
+        print_batches(&mut stdout, &batches)
+    }
+}
+
+fn du_command(options: &args::Du, alpm: &Alpm) -> std::io::Result<()> {
+    let pkg_graph = packit::graph::build_graph_for_localdb(alpm.localdb());
+    let mut sizes = with_graph!(pkg_graph, options.ignore_optdepends, |graph| {
+        packit::dependencies::closure_sizes(graph)
+    });
+    // Sort by size, largest first
+    if options.exclusive {
+        sizes.sort_by_key(|size| std::cmp::Reverse(size.exclusive_size));
+    } else {
+        sizes.sort_by_key(|size| std::cmp::Reverse(size.total_size));
+    }
+
+    let mut stdout = anstream::stdout().lock();
+    if options.json {
+        print_closure_sizes_json(&mut stdout, &sizes)
+    } else {
+        print_closure_sizes(&mut stdout, &sizes)
+    }
+}
+
+fn foreign_command(options: &args::Foreign, alpm: &Alpm) -> std::io::Result<()> {
+    let pkg_graph = packit::graph::build_graph_for_localdb(alpm.localdb());
+    let packages = with_graph!(pkg_graph, options.ignore_optdepends, |graph| {
+        packit::dependencies::foreign_packages(graph, alpm.syncdbs())
+    });
+
+    let mut stdout = anstream::stdout().lock();
+    if options.json {
+        print_foreign_packages_json(&mut stdout, &packages)
+    } else {
+        print_foreign_packages(&mut stdout, &packages, options.quiet)
+    }
+}
+
+fn history_command(options: &args::History, config: &Config, alpm: &Alpm) -> std::io::Result<()> {
+    let log = BufReader::new(File::open(&config.log_file)?);
+    let transactions = packit::pacman_log::read_log(log)?;
+    let localdb = alpm.localdb();
+    let installed = localdb.pkg(options.package.as_str()).ok();
+    let pkg_graph = packit::graph::build_graph_for_localdb(localdb);
+    let history = with_graph!(pkg_graph, options.ignore_optdepends, |graph| {
+        packit::pacman_log::package_history(graph, &transactions, &options.package, installed)
+    });
+    if history.events.is_empty() && installed.is_none() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("No history for package {}", options.package),
+        ));
+    }
+
+    let mut stdout = anstream::stdout().lock();
+    if options.json {
+        print_package_history_json(&mut stdout, &history)
+    } else {
+        print_package_history(&mut stdout, &history)
+    }
+}
+
+/// Read the names of orphans recorded in `path`.
+///
+/// Return `None` if `path` does not exist.
+fn read_recorded_orphans(path: &Path) -> std::io::Result<Option<HashSet<String>>> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents.lines().map(ToOwned::to_owned).collect())),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Record the names of `orphans` in `path`.
+///
+/// Write to a temporary file first and rename it to `path` afterwards, to
+/// avoid leaving a truncated file behind.
+fn record_orphans(path: &Path, orphans: &[PackageNode]) -> std::io::Result<()> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+    let mut contents = String::new();
+    for orphan in orphans {
+        contents.push_str(orphan.name());
+        contents.push('\n');
+    }
+    let temp_path = path.with_extension("tmp");
+    std::fs::write(&temp_path, contents)?;
+    std::fs::rename(&temp_path, path)
+}
+
+fn report_new_orphans<'a, G>(options: &args::PostTransaction, graph: G) -> std::io::Result<()>
+where
+    G: GraphRef
+        + NodeCount
+        + NodeIndexable
+        + Visitable<NodeId = PackageNode<'a>>
+        + IntoNeighborsDirected
+        + IntoNodeIdentifiers,
+{
+    let orphan_graph = packit::dependencies::orphans(graph);
+    let mut orphans = orphan_graph.node_identifiers().collect::<Vec<_>>();
+    orphans.sort_by_key(|node| node.package().name());
+
+    let Some(recorded_orphans) = read_recorded_orphans(&options.state_file)? else {
+        tracing::info!(
+            "No orphans recorded in {} yet, recording current orphans",
+            options.state_file.display()
+        );
+        return record_orphans(&options.state_file, &orphans);
+    };
+    let new_orphans = orphans
+        .iter()
+        .filter(|node| !recorded_orphans.contains(node.name()))
+        .collect::<Vec<_>>();
+    if !new_orphans.is_empty() {
+        // pacman -Qtd only finds orphans without any dependents, so it misses
+        // orphans in dependency cycles, i.e. in strongly connected components
+        // of more than one orphan.
+        #[allow(
+            clippy::mutable_key_type,
+            reason = "We do not mutate the package pointer while traversing the graph"
+        )]
+        let in_cycles = tarjan_scc(&orphan_graph)
+            .into_iter()
+            .filter(|component| 1 < component.len())
+            .flatten()
+            .collect::<HashSet<_>>();
+        let mut stdout = anstream::stdout().lock();
+        writeln!(stdout, "New orphan packages:")?;
+        for node in new_orphans {
+            print_orphan(&mut stdout, node.package(), in_cycles.contains(node))?;
+        }
+    }
+
+    record_orphans(&options.state_file, &orphans)
+}
+
+fn hook_command(hook: &args::Hook, alpm: &Alpm) -> std::io::Result<()> {
+    match hook {
+        args::Hook::PostTransaction(options) => {
+            let pkg_graph = packit::graph::build_graph_for_localdb(alpm.localdb());
+            with_graph!(pkg_graph, options.ignore_optdepends, |graph| {
+                report_new_orphans(options, graph)
+            })
+        }
+    }
+}
+
+fn linkcheck_command(options: &args::Linkcheck, alpm: &Alpm) -> std::io::Result<()> {
+    let localdb = alpm.localdb();
+    let packages = if options.packages.is_empty() {
+        localdb.pkgs().into_iter().collect::<Vec<_>>()
+    } else {
+        options
+            .packages
+            .iter()
+            .map(|name| localdb.pkg(name.as_str()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(std::io::Error::other)?
+    };
+    let pkg_graph = packit::graph::build_graph_for_localdb(localdb);
+    // Libraries can only be required dependencies
+    let required_graph = EdgeFiltered::from_fn(&pkg_graph, |edge| {
+        *edge.weight() == DependencyEdge::Required
+    });
+    let owners = packit::linkcheck::LibraryOwners::from_db(localdb);
+    let root = Path::new(alpm.root());
+    let mut checks = packages
+        .into_iter()
+        .map(|package| {
+            let libraries = packit::linkcheck::linked_libraries(root, &owners, package);
+            packit::linkcheck::check_package(
+                &required_graph,
+                &owners,
+                PackageNode::new(package),
+                &libraries,
+                options.transitive,
+            )
+        })
+        .collect::<Vec<_>>();
+    checks.sort_by_key(|check| check.package.package().name());
+
+    let mut stdout = anstream::stdout().lock();
+    if options.json {
+        print_link_checks_json(&mut stdout, &checks)
+    } else {
+        print_link_checks(&mut stdout, &checks)
+    }
+}
+
+#[allow(
+    clippy::mutable_key_type,
+    reason = "We do not mutate the package pointer while traversing the graph"
+)]
+fn list_sources<'a, G>(
+    options: &args::Sources,
+    sources: &'a [SourcePackage],
+    graph: G,
+) -> std::io::Result<()>
+where
+    G: GraphProp
+        + Data<NodeWeight = &'a str, EdgeWeight = DependencyEdge>
+        + Visitable<NodeId = &'a str>
+        + NodeIndexable
+        + IntoEdges
+        + IntoNeighbors
+        + IntoNodeIdentifiers
+        + IntoNodeReferences
+        + IntoEdgeReferences,
+{
+    let included = match &options.needs {
+        Some(needs) => {
+            let base = sources
+                .iter()
+                .find(|source| source.base == *needs)
+                .ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("Package base {needs} not found"),
+                    )
+                })?;
+            Some(packit::srcinfo::build_closure(graph, &base.base))
+        }
+        None => None,
+    };
+    let is_included = |base: &str| {
+        included
+            .as_ref()
+            .is_none_or(|included| included.contains(base))
+    };
+    let graph = NodeFiltered::from_fn(graph, is_included);
+
+    let mut stdout = anstream::stdout().lock();
+    if options.order {
+        let batches = packit::srcinfo::build_order(&graph);
+        if options.json {
+            print_batches_json(&mut stdout, &batches)
+        } else {
+            print_batches(&mut stdout, &batches)
+        }
+    } else if options.dot {
+        print_source_graph(&mut stdout, &graph)
+    } else if options.json {
+        print_source_graph_json(&mut stdout, sources, &graph)
+    } else {
+        print_source_packages(&mut stdout, sources, &graph)
+    }
+}
 
-    if options.graph_options.ignore_optdepends {
-        list_dependents(
+fn sources_command(options: &args::Sources) -> std::io::Result<()> {
+    let sources =
+        packit::srcinfo::find_source_packages(&options.directory, std::env::consts::ARCH)?;
+    let graph = packit::srcinfo::build_source_graph(&sources);
+    if options.with_optdepends {
+        list_sources(options, &sources, &graph)
+    } else {
+        // Optional dependencies are not needed to build a package
+        list_sources(
             options,
-            &EdgeFiltered::from_fn(&pkg_graph, |edge| {
-                *edge.weight() == DependencyEdge::Required
-            }),
-            source_pkg,
+            &sources,
+            &EdgeFiltered::from_fn(&graph, |edge| *edge.weight() != DependencyEdge::Optional),
         )
+    }
+}
+
+fn stale_command(options: &args::Stale, alpm: &Alpm) -> std::io::Result<()> {
+    let pkg_graph = packit::graph::build_graph_for_localdb(alpm.localdb());
+    let (localdb, syncdbs) = (alpm.localdb(), alpm.syncdbs());
+    let packages = with_graph!(pkg_graph, options.ignore_optdepends, |graph| {
+        packit::upstream::stale_packages(graph, localdb, syncdbs)
+    });
+
+    let mut stdout = anstream::stdout().lock();
+    if options.json {
+        print_stale_packages_json(&mut stdout, &packages)
+    } else {
+        print_stale_packages(&mut stdout, &packages)
+    }
+}
+
+fn upgrade_preview_command(options: &args::UpgradePreview, alpm: &Alpm) -> std::io::Result<()> {
+    let localdb = alpm.localdb();
+    let preview = packit::upgrade::preview_upgrade(localdb, alpm.syncdbs());
+    let before = packit::graph::build_graph_for_localdb(localdb);
+    let mut stdout = anstream::stdout().lock();
+    with_graph!(preview.graph, options.ignore_optdepends, |after| {
+        if options.dot {
+            return print_package_graph(&mut stdout, after, PrintOneLine::WithVersion);
+        }
+        let new_orphans = with_graph!(before, options.ignore_optdepends, |installed| {
+            preview.new_orphans(installed, after)
+        });
+        if options.json {
+            print_upgrade_preview_json(&mut stdout, &preview, &new_orphans)
+        } else {
+            print_upgrade_preview(&mut stdout, &preview, &new_orphans)
+        }
+    })
+}
+
+fn risk_command(options: &args::Risk, alpm: &Alpm) -> std::io::Result<()> {
+    let pkg_graph = packit::graph::build_graph_for_localdb(alpm.localdb());
+    let mut radii = with_graph!(pkg_graph, options.ignore_optdepends, |graph| {
+        packit::dependencies::blast_radii(graph)
+    });
+    // Sort by the chosen metric, largest first, and then by name
+    radii.sort_by_key(|radius| radius.package.package().name());
+    match options.sort {
+        args::RiskOrder::Score => radii.sort_by_key(|radius| std::cmp::Reverse(radius.score())),
+        args::RiskOrder::Dependents => {
+            radii.sort_by_key(|radius| std::cmp::Reverse(radius.dependents));
+        }
+        args::RiskOrder::Size => {
+            radii.sort_by_key(|radius| std::cmp::Reverse(radius.dependents_size));
+        }
+        args::RiskOrder::Explicit => {
+            radii.sort_by_key(|radius| std::cmp::Reverse(radius.explicit));
+        }
+    }
+    if options.top != 0 {
+        radii.truncate(options.top);
+    }
+
+    let mut stdout = anstream::stdout().lock();
+    if options.json {
+        print_blast_radii_json(&mut stdout, &radii)
+    } else {
+        print_blast_radii(&mut stdout, &radii)
+    }
+}
+
+fn stats_command(options: &args::Stats, alpm: &Alpm) -> std::io::Result<()> {
+    let pkg_graph = packit::graph::build_graph_for_localdb(alpm.localdb());
+    let statistics = with_graph!(pkg_graph, options.ignore_optdepends, |graph| {
+        packit::stats::graph_statistics(graph, options.top)
+    });
+
+    let mut stdout = anstream::stdout().lock();
+    if options.json {
+        print_graph_statistics_json(&mut stdout, &statistics)
     } else {
-        list_dependents(options, &pkg_graph, source_pkg)
+        print_graph_statistics(&mut stdout, &statistics)
     }
 }
 
+/// Create a temporary database with packages from archives and repository databases.
+fn package_database(
+    source: &args::PackageSource,
+    config: &Config,
+) -> std::io::Result<TemporaryDatabase> {
+    let alpm = alpm_utils::alpm_with_conf(config).map_err(std::io::Error::other)?;
+    alpm.set_log_cb((), packit::alpm::tracing_log_cb);
+    let dbpath = Path::new(&config.db_path);
+    let mut database = TemporaryDatabase::create(dbpath)?;
+    for file in &source.repo_dbs {
+        database.add_repository(file)?;
+    }
+
+    let mut packages = BTreeMap::new();
+    if !database.repositories().is_empty() {
+        let repositories = Alpm::new(
+            config.root_dir.as_str(),
+            database.path().to_string_lossy().as_ref(),
+        )
+        .map_err(std::io::Error::other)?;
+        repositories.set_log_cb((), packit::alpm::tracing_log_cb);
+        for name in database.repositories() {
+            let db = repositories
+                .register_syncdb(name.as_str(), SigLevel::NONE)
+                .map_err(std::io::Error::other)?;
+            // Like pacman, prefer packages from earlier repositories
+            for package in db.pkgs() {
+                packages
+                    .entry(package.name().to_owned())
+                    .or_insert_with(|| PackageInfo::from_pkg(package));
+            }
+        }
+    }
+    if let Some(directory) = &source.archives {
+        tracing::warn!(
+            "Packages in {} have no file lists, only their metadata is analysed",
+            directory.display()
+        );
+        for package in packit::archives::read_packages(&alpm, directory)? {
+            packages.insert(package.name.clone(), package);
+        }
+    }
+
+    for package in packages.values() {
+        let reason = if source.explicit.is_empty() || source.explicit.contains(&package.name) {
+            PackageReason::Explicit
+        } else {
+            PackageReason::Depend
+        };
+        database.add_package(package, reason)?;
+    }
+    if source.with_localdb {
+        let names = packages.keys().map(String::as_str).collect::<HashSet<_>>();
+        database.copy_local_packages(alpm.localdb(), dbpath, &names)?;
+    }
+    Ok(database)
+}
+
 fn main() -> std::io::Result<()> {
-    use alpm_utils::{alpm_with_conf, config::Config};
+    use alpm_utils::alpm_with_conf;
 
     tracing_subscriber::fmt::init();
 
     let args = CliArgs::parse();
+    if !args.source.is_given() && (args.source.with_localdb || !args.source.explicit.is_empty()) {
+        CliArgs::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "--with-localdb and --explicit require --archives or --repo-db",
+            )
+            .exit();
+    }
+    if args.source.is_given() && args.command.inspects_files() {
+        CliArgs::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--archives and --repo-db only apply to commands which analyse the package graph",
+            )
+            .exit();
+    }
 
-    let config = Config::new().map_err(|error| match error.kind {
+    // Commands which do not need libalpm
+    match &args.command {
+        args::Command::Sources(sources) => return sources_command(sources),
+        #[cfg(feature = "completions")]
+        args::Command::Completions(completions) => {
+            completions.print();
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let mut config = Config::new().map_err(|error| match error.kind {
         alpm_utils::config::ErrorKind::Io(error) => error,
         _ => std::io::Error::new(std::io::ErrorKind::InvalidData, error),
     })?;
+    // Declare the database before the handle, to drop the handle first
+    let database = if args.source.is_given() {
+        Some(package_database(&args.source, &config)?)
+    } else {
+        None
+    };
+    if let Some(database) = &database {
+        config.db_path = database.path().to_string_lossy().into_owned();
+        // Repository databases shadow configured repositories of the same name
+        config
+            .repos
+            .retain(|repo| !database.repositories().contains(&repo.name));
+    }
     let alpm = alpm_with_conf(&config).map_err(std::io::Error::other)?;
     alpm.set_log_cb((), packit::alpm::tracing_log_cb);
+    for name in database.iter().flat_map(TemporaryDatabase::repositories) {
+        alpm.register_syncdb(name.as_str(), SigLevel::NONE)
+            .map_err(std::io::Error::other)?;
+    }
 
     match args.command {
         args::Command::Orphans(orphans) => orphans_command(&orphans, &alpm)?,
         args::Command::Dependents(dependents) => dependents_command(&dependents, &alpm)?,
+        args::Command::Dependencies(dependencies) => {
+            dependencies_command(&dependencies, &alpm)?;
+        }
+        args::Command::Alternatives(alternatives) => {
+            alternatives_command(&alternatives, &alpm)?;
+        }
+        args::Command::Audit(audit) => audit_command(&audit, &alpm)?,
+        args::Command::Cache(cache) => cache_command(&cache, &config, &alpm)?,
+        args::Command::CanDowngrade(can_downgrade) => {
+            can_downgrade_command(&can_downgrade, &config, &alpm)?;
+        }
+        args::Command::Check(check) => check_command(&check, &alpm)?,
+        args::Command::Constraints(constraints) => constraints_command(&constraints, &alpm)?,
+        args::Command::Du(du) => du_command(&du, &alpm)?,
+        args::Command::Export(_) => export_command(&alpm)?,
+        args::Command::RebuildOrder(rebuild_order) => {
+            rebuild_order_command(&rebuild_order, &alpm)?;
+        }
+        args::Command::Reconcile(reconcile) => reconcile_command(&reconcile, &alpm)?,
+        args::Command::Risk(risk) => risk_command(&risk, &alpm)?,
+        args::Command::Foreign(foreign) => foreign_command(&foreign, &alpm)?,
+        args::Command::Groups(groups) => groups_command(&groups, &alpm)?,
+        args::Command::History(history) => history_command(&history, &config, &alpm)?,
+        args::Command::Hook(hook) => hook_command(&hook, &alpm)?,
+        args::Command::Linkcheck(linkcheck) => linkcheck_command(&linkcheck, &alpm)?,
+        args::Command::Lostfiles(lostfiles) => lostfiles_command(&lostfiles, &alpm)?,
+        args::Command::Pacnew(pacnew) => pacnew_command(&pacnew, &alpm)?,
+        args::Command::Stale(stale) => stale_command(&stale, &alpm)?,
+        args::Command::Stats(stats) => stats_command(&stats, &alpm)?,
+        args::Command::UpgradePreview(upgrade_preview) => {
+            upgrade_preview_command(&upgrade_preview, &alpm)?;
+        }
+        args::Command::Verify(verify) => verify_command(&verify, &alpm)?,
+        args::Command::Sources(_) => unreachable!("sources does not need libalpm"),
         #[cfg(feature = "completions")]
-        args::Command::Completions(completions) => completions.print(),
+        args::Command::Completions(_) => unreachable!("completions do not need libalpm"),
     }
 
     Ok(())
@@ -0,0 +1,304 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL-1.2 OR GPL-3.0
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Parse mtree files of packages.
+//!
+//! Pacman stores an mtree file with the type, permissions, size, modification
+//! time and checksum of every file of a package in the local database.  This
+//! module parses the subset of the mtree format which pacman writes.
+
+use std::{collections::HashMap, io::BufRead};
+
+use tracing::trace;
+
+/// The type of an mtree entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    /// A regular file.
+    File,
+    /// A directory.
+    Directory,
+    /// A symbolic link.
+    Link,
+}
+
+/// An entry of an mtree file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MtreeEntry {
+    /// The path of the entry, relative to the root directory.
+    pub path: String,
+    /// The type of the entry.
+    pub kind: EntryKind,
+    /// The permission bits of the entry.
+    pub mode: Option<u32>,
+    /// The user ID of the owner.
+    pub uid: Option<u32>,
+    /// The group ID of the owner.
+    pub gid: Option<u32>,
+    /// The size of the entry in bytes.
+    pub size: Option<u64>,
+    /// The modification time of the entry, in whole seconds since the epoch.
+    pub mtime: Option<i64>,
+    /// The SHA-256 digest of the entry, as hex string.
+    pub sha256: Option<String>,
+    /// The target of a symbolic link.
+    pub link: Option<String>,
+}
+
+/// Unescape octal escapes like `\040` in mtree paths.
+fn unescape(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while let Some(byte) = bytes.get(index) {
+        let escape = bytes
+            .get(index + 1..index + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        if let (b'\\', Some(escaped)) = (byte, escape) {
+            unescaped.push(escaped);
+            index += 4;
+        } else {
+            unescaped.push(*byte);
+            index += 1;
+        }
+    }
+    String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+fn parse_entry(path: &str, keywords: &HashMap<String, String>) -> std::io::Result<MtreeEntry> {
+    let invalid = |keyword: &str| invalid_data(format!("Invalid {keyword} for {path} in mtree"));
+    let number = |keyword: &str, radix: u32| {
+        keywords
+            .get(keyword)
+            .map(|value| u32::from_str_radix(value, radix).map_err(|_| invalid(keyword)))
+            .transpose()
+    };
+    let kind = match keywords.get("type").map(String::as_str) {
+        None | Some("file") => EntryKind::File,
+        Some("dir") => EntryKind::Directory,
+        Some("link") => EntryKind::Link,
+        Some(_) => return Err(invalid("type")),
+    };
+    let size = keywords
+        .get("size")
+        .map(|value| value.parse().map_err(|_| invalid("size")))
+        .transpose()?;
+    let mtime = keywords
+        .get("time")
+        .map(|value| {
+            let seconds = value.split_once('.').map_or(value.as_str(), |(s, _)| s);
+            seconds.parse().map_err(|_| invalid("time"))
+        })
+        .transpose()?;
+    Ok(MtreeEntry {
+        path: path.to_owned(),
+        kind,
+        mode: number("mode", 8)?,
+        uid: number("uid", 10)?,
+        gid: number("gid", 10)?,
+        size,
+        mtime,
+        sha256: keywords.get("sha256digest").cloned(),
+        link: keywords.get("link").map(|link| unescape(link)),
+    })
+}
+
+/// Read all entries from an uncompressed mtree file.
+///
+/// Apply `/set` and `/unset` commands to subsequent entries, and make all
+/// paths relative to the root directory.  Skip package metadata files, such as
+/// `.PKGINFO` or `.BUILDINFO`.
+///
+/// # Errors
+///
+/// Return an error if reading from `reader` fails, or if an entry has invalid
+/// keywords.
+pub fn read_mtree<R: BufRead>(reader: R) -> std::io::Result<Vec<MtreeEntry>> {
+    let mut defaults = HashMap::new();
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let mut words = line.split_whitespace();
+        let Some(first) = words.next() else {
+            continue;
+        };
+        let keywords = words.filter_map(|word| word.split_once('='));
+        match first {
+            _ if first.starts_with('#') => {}
+            "/set" => {
+                defaults.extend(keywords.map(|(k, v)| (k.to_owned(), v.to_owned())));
+            }
+            "/unset" => {
+                for keyword in line.split_whitespace().skip(1) {
+                    if keyword == "all" {
+                        defaults.clear();
+                    } else {
+                        defaults.remove(keyword);
+                    }
+                }
+            }
+            _ => {
+                let path = unescape(first);
+                let path = path.strip_prefix("./").unwrap_or(&path);
+                if path.is_empty() || path.starts_with('.') {
+                    trace!("Skipping mtree entry {path}");
+                    continue;
+                }
+                let mut entry_keywords = defaults.clone();
+                entry_keywords.extend(keywords.map(|(k, v)| (k.to_owned(), v.to_owned())));
+                entries.push(parse_entry(path, &entry_keywords)?);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unescape_octal_escapes() {
+        assert_eq!(unescape("usr/share/foo\\040bar"), "usr/share/foo bar");
+        assert_eq!(unescape("\\043comment"), "#comment");
+        assert_eq!(unescape("caf\\303\\251"), "café");
+    }
+
+    #[test]
+    fn unescape_without_escapes() {
+        assert_eq!(unescape(""), "");
+        assert_eq!(unescape("usr/bin/foo"), "usr/bin/foo");
+    }
+
+    #[test]
+    fn unescape_invalid_escapes() {
+        assert_eq!(unescape("foo\\bar"), "foo\\bar");
+        assert_eq!(unescape("foo\\09"), "foo\\09");
+        assert_eq!(unescape("foo\\"), "foo\\");
+    }
+
+    #[test]
+    fn read_mtree_applies_set_defaults() {
+        let mtree = "\
+#mtree
+/set type=file uid=0 gid=0 mode=644
+./.BUILDINFO time=1700000000.0 size=4000 sha256digest=abcd
+./.PKGINFO time=1700000000.0 size=500 sha256digest=abcd
+./usr time=1700000000.0 mode=755 type=dir
+./usr/bin/foo\\040bar time=1700000000.123456789 mode=755 size=1234 sha256digest=0123
+./usr/lib/libfoo.so time=1700000000.0 type=link link=libfoo.so.1
+/set uid=1000
+/unset gid
+./usr/share/foo time=1700000001.0 size=0
+";
+        let entries = read_mtree(mtree.as_bytes()).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                MtreeEntry {
+                    path: "usr".to_owned(),
+                    kind: EntryKind::Directory,
+                    mode: Some(0o755),
+                    uid: Some(0),
+                    gid: Some(0),
+                    size: None,
+                    mtime: Some(1_700_000_000),
+                    sha256: None,
+                    link: None,
+                },
+                MtreeEntry {
+                    path: "usr/bin/foo bar".to_owned(),
+                    kind: EntryKind::File,
+                    mode: Some(0o755),
+                    uid: Some(0),
+                    gid: Some(0),
+                    size: Some(1234),
+                    mtime: Some(1_700_000_000),
+                    sha256: Some("0123".to_owned()),
+                    link: None,
+                },
+                MtreeEntry {
+                    path: "usr/lib/libfoo.so".to_owned(),
+                    kind: EntryKind::Link,
+                    mode: Some(0o644),
+                    uid: Some(0),
+                    gid: Some(0),
+                    size: None,
+                    mtime: Some(1_700_000_000),
+                    sha256: None,
+                    link: Some("libfoo.so.1".to_owned()),
+                },
+                MtreeEntry {
+                    path: "usr/share/foo".to_owned(),
+                    kind: EntryKind::File,
+                    mode: Some(0o644),
+                    uid: Some(1000),
+                    gid: None,
+                    size: Some(0),
+                    mtime: Some(1_700_000_001),
+                    sha256: None,
+                    link: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn read_mtree_unset_all() {
+        let mtree = "\
+/set type=dir mode=755
+/unset all
+./etc/foo.conf size=10
+";
+        let entries = read_mtree(mtree.as_bytes()).unwrap();
+        assert_eq!(
+            entries,
+            vec![MtreeEntry {
+                path: "etc/foo.conf".to_owned(),
+                kind: EntryKind::File,
+                mode: None,
+                uid: None,
+                gid: None,
+                size: Some(10),
+                mtime: None,
+                sha256: None,
+                link: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn read_mtree_escaped_link_target() {
+        let mtree = "./usr/bin/foo type=link link=foo\\040bar\n";
+        let entries = read_mtree(mtree.as_bytes()).unwrap();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| entry.link.as_deref())
+                .collect::<Vec<_>>(),
+            vec![Some("foo bar")]
+        );
+    }
+
+    #[test]
+    fn read_mtree_invalid_keywords() {
+        for mtree in [
+            "./foo type=fifo\n",
+            "./foo mode=999\n",
+            "./foo size=big\n",
+            "./foo time=yesterday\n",
+            "./foo uid=-1\n",
+        ] {
+            let error = read_mtree(mtree.as_bytes()).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData, "{mtree}");
+        }
+    }
+}
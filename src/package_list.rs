@@ -0,0 +1,563 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL-1.2 OR GPL-3.0
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Declarative lists of explicitly installed packages.
+//!
+//! A package list names packages one per line, in sections for every
+//! repository, e.g. `[core]`, and a final `[foreign]` section for foreign
+//! packages.  Empty lines and lines starting with `#` are ignored.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt::Display,
+    hash::Hash,
+    io::BufRead,
+};
+
+use alpm::{AlpmList, Db, Package, PackageReason};
+use alpm_utils::{DbListExt as _, depends::satisfies_dep};
+use petgraph::visit::{
+    Bfs, Data, EdgeFiltered, EdgeRef, GraphBase, GraphRef, IntoEdges, IntoNeighbors,
+    IntoNodeIdentifiers, NodeCount, Visitable, Walker,
+};
+use tracing::debug;
+
+use crate::{
+    dependencies::orphans_with_roots,
+    graph::{DependencyEdge, PackageNode},
+};
+
+/// The name of the section for foreign packages.
+pub const FOREIGN_SECTION: &str = "foreign";
+
+/// A list of packages, by repository.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackageList {
+    /// Packages from sync databases, by repository, in order of repositories.
+    pub repositories: Vec<(String, Vec<String>)>,
+    /// Foreign packages.
+    pub foreign: Vec<String>,
+}
+
+impl PackageList {
+    /// Create a list of `packages`, grouped by their repository in `syncdbs`.
+    ///
+    /// Group repositories in the order of `syncdbs`, and list all packages not
+    /// contained in any sync database as foreign.
+    pub fn from_packages<'a>(
+        packages: impl IntoIterator<Item = &'a Package>,
+        syncdbs: AlpmList<&Db>,
+    ) -> Self {
+        let mut repositories = syncdbs
+            .iter()
+            .map(|db| (db.name().to_owned(), Vec::new()))
+            .collect::<Vec<_>>();
+        let mut foreign = Vec::new();
+        for package in packages {
+            let repository = syncdbs
+                .pkg(package.name())
+                .ok()
+                .and_then(|sync_package| sync_package.db())
+                .and_then(|db| repositories.iter_mut().find(|(name, _)| name == db.name()));
+            match repository {
+                Some((_, packages)) => packages.push(package.name().to_owned()),
+                None => foreign.push(package.name().to_owned()),
+            }
+        }
+        repositories.retain(|(_, packages)| !packages.is_empty());
+        for (_, packages) in &mut repositories {
+            packages.sort();
+        }
+        foreign.sort();
+        Self {
+            repositories,
+            foreign,
+        }
+    }
+
+    /// Read a package list.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if reading from `reader` fails, or if a package appears
+    /// before the first section.
+    pub fn read<R: BufRead>(reader: R) -> std::io::Result<Self> {
+        let mut list = Self::default();
+        let mut section = None;
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+                section = Some(name.to_owned());
+                continue;
+            }
+            match section.as_deref() {
+                None => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!(
+                            "Package {line} outside of any section in line {}",
+                            index + 1
+                        ),
+                    ));
+                }
+                Some(FOREIGN_SECTION) => list.foreign.push(line.to_owned()),
+                Some(repository) => {
+                    match list
+                        .repositories
+                        .iter_mut()
+                        .find(|(name, _)| name == repository)
+                    {
+                        Some((_, packages)) => packages.push(line.to_owned()),
+                        None => list
+                            .repositories
+                            .push((repository.to_owned(), vec![line.to_owned()])),
+                    }
+                }
+            }
+        }
+        Ok(list)
+    }
+
+    /// All packages in this list, regardless of their repository.
+    pub fn packages(&self) -> impl Iterator<Item = &str> {
+        self.repositories
+            .iter()
+            .flat_map(|(_, packages)| packages)
+            .chain(&self.foreign)
+            .map(String::as_str)
+    }
+}
+
+impl Display for PackageList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sections = self
+            .repositories
+            .iter()
+            .map(|(name, packages)| (name.as_str(), packages))
+            .chain((!self.foreign.is_empty()).then_some((FOREIGN_SECTION, &self.foreign)));
+        for (index, (name, packages)) in sections.enumerate() {
+            if 0 < index {
+                writeln!(f)?;
+            }
+            writeln!(f, "[{name}]")?;
+            for package in packages {
+                writeln!(f, "{package}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Find the minimal subset of `roots` from which all `roots` are reachable.
+///
+/// Return all `roots` which no other root reaches in `graph`, either directly
+/// or transitively.  Of roots which reach each other, e.g. in a cycle, keep
+/// the root with the smallest `key`; keys must be distinct among `roots`.
+///
+/// Return roots sorted by `key`.
+pub fn minimal_roots<G, F, K>(graph: G, roots: &[G::NodeId], key: F) -> Vec<G::NodeId>
+where
+    G: IntoNeighbors + Visitable,
+    G::NodeId: Hash + Eq,
+    F: Fn(G::NodeId) -> K,
+    K: Ord,
+{
+    let reachable = roots
+        .iter()
+        .map(|root| {
+            let reachable = Bfs::new(graph, *root)
+                .iter(graph)
+                .filter(|other| other != root)
+                .collect::<HashSet<_>>();
+            (*root, reachable)
+        })
+        .collect::<Vec<_>>();
+    let reaches = |from: G::NodeId, to: &G::NodeId| {
+        reachable
+            .iter()
+            .any(|(node, reachable)| *node == from && reachable.contains(to))
+    };
+    let mut minimal = roots
+        .iter()
+        .copied()
+        .filter(|root| {
+            !reachable.iter().any(|(other, reachable)| {
+                reachable.contains(root) && !(reaches(*root, other) && key(*root) < key(*other))
+            })
+        })
+        .collect::<Vec<_>>();
+    minimal.sort_by_key(|node| key(*node));
+    minimal
+}
+
+/// Get the subgraph of required dependencies which name the package directly.
+///
+/// Leave out dependencies on provisions, because pacman may choose a different
+/// provider for these.
+fn named_dependencies<'a, G>(graph: G) -> EdgeFiltered<G, impl Fn(G::EdgeRef) -> bool>
+where
+    G: GraphBase<NodeId = PackageNode<'a>> + Data<EdgeWeight = DependencyEdge> + IntoEdges,
+{
+    EdgeFiltered::from_fn(graph, |edge: G::EdgeRef| {
+        *edge.weight() == DependencyEdge::Required
+            && edge
+                .source()
+                .depends()
+                .iter()
+                .any(|dep| satisfies_dep(dep, edge.target().name(), edge.target().version()))
+    })
+}
+
+/// Find all roots which a list does not imply.
+///
+/// Return all `roots` which are neither in `listed` nor reachable from any
+/// node in `listed` in `graph`, in order of `roots`.
+pub fn unlisted_roots<G>(graph: G, roots: &[G::NodeId], listed: &[G::NodeId]) -> Vec<G::NodeId>
+where
+    G: IntoNeighbors + Visitable,
+    G::NodeId: Hash + Eq,
+{
+    let mut implied = HashSet::new();
+    for node in listed {
+        if !implied.contains(node) {
+            implied.extend(Bfs::new(graph, *node).iter(graph));
+        }
+    }
+    roots
+        .iter()
+        .copied()
+        .filter(|root| !implied.contains(root))
+        .collect()
+}
+
+/// Find the minimal set of explicitly installed packages.
+///
+/// Return all explicitly installed packages in `graph` which no other
+/// explicitly installed package requires by name, either directly or
+/// transitively.  Of explicitly installed packages which require each other,
+/// keep the package whose name comes first.
+///
+/// Only consider required dependencies which name the package directly, because
+/// pacman may choose a different provider for a dependency on a provision.
+///
+/// Return packages sorted by name.
+pub fn minimal_explicit_packages<'a, G>(graph: G) -> Vec<PackageNode<'a>>
+where
+    G: GraphRef
+        + Data<EdgeWeight = DependencyEdge>
+        + Visitable<NodeId = PackageNode<'a>>
+        + IntoEdges
+        + IntoNodeIdentifiers,
+{
+    let explicit = graph
+        .node_identifiers()
+        .filter(|node| node.reason() == PackageReason::Explicit)
+        .collect::<Vec<_>>();
+    minimal_roots(&named_dependencies(graph), &explicit, |node| {
+        node.package().name()
+    })
+}
+
+/// Changes which converge the installed packages with a package list.
+#[derive(Debug)]
+pub struct Reconciliation<'a> {
+    /// Packages from sync databases to install, sorted by name.
+    pub install: Vec<&'a Package>,
+    /// Listed packages which are neither installed nor in any sync database.
+    pub unavailable: Vec<String>,
+    /// Listed packages which are installed as dependencies, sorted by name.
+    pub mark_explicit: Vec<PackageNode<'a>>,
+    /// Explicitly installed packages which are not listed, sorted by name.
+    pub mark_dependency: Vec<PackageNode<'a>>,
+    /// Installed packages which neither listed packages nor packages to install
+    /// require, sorted by name.
+    pub remove: Vec<PackageNode<'a>>,
+}
+
+/// Compare the installed packages in `graph` to a package `list`.
+///
+/// Install all listed packages which are not installed, mark all listed packages
+/// as explicitly installed, and mark all other explicitly installed packages as
+/// dependencies.  Remove all packages which are orphans afterwards, but keep
+/// installed packages which packages to install require.
+///
+/// Keep explicitly installed packages which listed packages require by name,
+/// like [`minimal_explicit_packages`] does, so that reconciling with an
+/// exported list changes nothing.
+pub fn reconcile<'a, G>(
+    graph: G,
+    localdb: &'a Db,
+    syncdbs: AlpmList<&'a Db>,
+    list: &PackageList,
+) -> Reconciliation<'a>
+where
+    G: GraphRef
+        + NodeCount
+        + Data<EdgeWeight = DependencyEdge>
+        + Visitable<NodeId = PackageNode<'a>>
+        + IntoNeighbors
+        + IntoEdges
+        + IntoNodeIdentifiers,
+{
+    let listed = list.packages().collect::<HashSet<_>>();
+    let mut install = Vec::new();
+    let mut unavailable = Vec::new();
+    let mut mark_explicit = Vec::new();
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let mut roots = HashSet::new();
+    let mut installed = Vec::new();
+    for name in &listed {
+        if let Ok(package) = localdb.pkg(*name) {
+            roots.insert(PackageNode::new(package));
+            installed.push(PackageNode::new(package));
+            if package.reason() == PackageReason::Depend {
+                mark_explicit.push(PackageNode::new(package));
+            }
+        } else if let Ok(package) = syncdbs.pkg(*name) {
+            install.push(package);
+        } else {
+            unavailable.push((*name).to_owned());
+        }
+    }
+
+    // Keep installed packages which satisfy dependencies of packages to install.
+    let mut queue = install.iter().copied().collect::<VecDeque<_>>();
+    let mut visited = install
+        .iter()
+        .map(|package| package.name())
+        .collect::<HashSet<_>>();
+    while let Some(package) = queue.pop_front() {
+        for dep in package.depends() {
+            if let Some(installed) = localdb.pkgs().find_satisfier(dep.to_string()) {
+                debug!(
+                    package = package.name(),
+                    "Keeping {} for {}",
+                    installed.name(),
+                    package.name()
+                );
+                roots.insert(PackageNode::new(installed));
+            } else if let Some(dependency) = syncdbs.find_satisfier(dep.to_string())
+                && visited.insert(dependency.name())
+            {
+                queue.push_back(dependency);
+            }
+        }
+    }
+
+    let explicit = graph
+        .node_identifiers()
+        .filter(|node| node.reason() == PackageReason::Explicit)
+        .collect::<Vec<_>>();
+    let mut mark_dependency = unlisted_roots(&named_dependencies(graph), &explicit, &installed);
+    let mut remove = orphans_with_roots(graph, |node| roots.contains(&node))
+        .node_identifiers()
+        .collect::<Vec<_>>();
+
+    install.sort_by_key(|package| package.name());
+    unavailable.sort();
+    mark_explicit.sort_by_key(|node| node.package().name());
+    mark_dependency.sort_by_key(|node| node.package().name());
+    remove.sort_by_key(|node| node.package().name());
+    Reconciliation {
+        install,
+        unavailable,
+        mark_explicit,
+        mark_dependency,
+        remove,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use petgraph::prelude::DiGraphMap;
+
+    use super::*;
+
+    fn minimal<'a>(edges: &[(&'a str, &'a str)], roots: &[&'a str]) -> Vec<&'a str> {
+        let mut graph = DiGraphMap::<&str, ()>::from_edges(edges);
+        for root in roots {
+            graph.add_node(root);
+        }
+        minimal_roots(&graph, roots, |node| node)
+    }
+
+    #[test]
+    fn minimal_roots_without_edges() {
+        assert_eq!(minimal(&[], &["b", "a", "c"]), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn minimal_roots_direct_dependency() {
+        assert_eq!(minimal(&[("a", "b")], &["a", "b"]), vec!["a"]);
+    }
+
+    #[test]
+    fn minimal_roots_transitive_dependency() {
+        assert_eq!(
+            minimal(&[("a", "x"), ("x", "b"), ("c", "x")], &["a", "b", "c"]),
+            vec!["a", "c"]
+        );
+    }
+
+    #[test]
+    fn minimal_roots_ignores_dependents_outside_roots() {
+        assert_eq!(
+            minimal(&[("x", "a"), ("x", "b")], &["a", "b"]),
+            vec!["a", "b"]
+        );
+    }
+
+    #[test]
+    fn minimal_roots_cycle_between_roots() {
+        assert_eq!(minimal(&[("b", "a"), ("a", "b")], &["b", "a"]), vec!["a"]);
+        assert_eq!(
+            minimal(&[("c", "b"), ("b", "a"), ("a", "c")], &["c", "b", "a"]),
+            vec!["a"]
+        );
+    }
+
+    #[test]
+    fn minimal_roots_cycle_through_other_packages() {
+        assert_eq!(
+            minimal(&[("b", "x"), ("x", "a"), ("a", "b")], &["a", "b"]),
+            vec!["a"]
+        );
+    }
+
+    #[test]
+    fn minimal_roots_cycle_required_by_other_root() {
+        assert_eq!(
+            minimal(&[("a", "b"), ("b", "a"), ("z", "a")], &["a", "b", "z"]),
+            vec!["z"]
+        );
+    }
+
+    #[test]
+    fn minimal_roots_self_loop() {
+        assert_eq!(minimal(&[("a", "a")], &["a"]), vec!["a"]);
+    }
+
+    #[test]
+    fn unlisted_roots_excludes_implied_roots() {
+        let graph = DiGraphMap::<&str, ()>::from_edges([("a", "x"), ("x", "b"), ("c", "d")]);
+        assert_eq!(
+            unlisted_roots(&graph, &["a", "b", "c", "d"], &["a"]),
+            vec!["c", "d"]
+        );
+        assert_eq!(
+            unlisted_roots(&graph, &["a", "b", "c", "d"], &["d"]),
+            vec!["a", "b", "c"]
+        );
+        assert!(unlisted_roots(&graph, &["a", "b"], &["a", "b"]).is_empty());
+    }
+
+    #[test]
+    fn reconcile_with_minimal_roots_changes_nothing() {
+        let graph = DiGraphMap::<&str, ()>::from_edges([
+            ("a", "x"),
+            ("x", "b"),
+            ("b", "y"),
+            ("c", "d"),
+            ("d", "c"),
+            ("d", "z"),
+            ("e", "z"),
+        ]);
+        let explicit = ["a", "b", "c", "d", "e"];
+        let listed = minimal_roots(&graph, &explicit, |node| node);
+        assert_eq!(listed, vec!["a", "c", "e"]);
+        assert!(unlisted_roots(&graph, &explicit, &listed).is_empty());
+        let remove = orphans_with_roots(&graph, |node| listed.contains(&node));
+        assert_eq!(remove.node_identifiers().count(), 0);
+    }
+
+    #[test]
+    fn read_package_list() {
+        let list = "\
+# Packages
+[core]
+base
+linux
+
+[extra]
+  firefox  
+[core]
+vim
+[foreign]
+paru
+";
+        assert_eq!(
+            PackageList::read(list.as_bytes()).unwrap(),
+            PackageList {
+                repositories: vec![
+                    (
+                        "core".to_owned(),
+                        vec!["base".to_owned(), "linux".to_owned(), "vim".to_owned()]
+                    ),
+                    ("extra".to_owned(), vec!["firefox".to_owned()]),
+                ],
+                foreign: vec!["paru".to_owned()],
+            }
+        );
+    }
+
+    #[test]
+    fn read_package_list_empty() {
+        assert_eq!(
+            PackageList::read("# Nothing\n\n".as_bytes()).unwrap(),
+            PackageList::default()
+        );
+    }
+
+    #[test]
+    fn read_package_list_outside_section() {
+        let error = PackageList::read("# Packages\nbase\n[core]\n".as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(
+            error.to_string(),
+            "Package base outside of any section in line 2"
+        );
+    }
+
+    #[test]
+    fn package_list_round_trip() {
+        let list = PackageList {
+            repositories: vec![
+                ("core".to_owned(), vec!["base".to_owned()]),
+                (
+                    "extra".to_owned(),
+                    vec!["firefox".to_owned(), "vim".to_owned()],
+                ),
+            ],
+            foreign: vec!["paru".to_owned()],
+        };
+        let text = list.to_string();
+        assert_eq!(
+            text,
+            "[core]\nbase\n\n[extra]\nfirefox\nvim\n\n[foreign]\nparu\n"
+        );
+        assert_eq!(PackageList::read(text.as_bytes()).unwrap(), list);
+        assert_eq!(
+            list.packages().collect::<Vec<_>>(),
+            vec!["base", "firefox", "vim", "paru"]
+        );
+    }
+
+    #[test]
+    fn package_list_without_foreign_packages() {
+        let list = PackageList {
+            repositories: vec![("core".to_owned(), vec!["base".to_owned()])],
+            foreign: Vec::new(),
+        };
+        assert_eq!(list.to_string(), "[core]\nbase\n");
+    }
+}
@@ -0,0 +1,502 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL-1.2 OR GPL-3.0
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Parse the pacman log.
+
+use std::{collections::HashSet, fmt::Display, io::BufRead};
+
+use alpm::Package;
+use petgraph::visit::{
+    GraphRef, IntoNeighbors, IntoNeighborsDirected, IntoNodeIdentifiers, NodeCount, Visitable,
+};
+use tracing::trace;
+
+use crate::{dependencies::GraphPosition, graph::PackageNode};
+
+/// What happened to a package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackageAction {
+    /// The package was installed at the given version.
+    Installed(String),
+    /// The package was reinstalled at the given version.
+    Reinstalled(String),
+    /// The package was upgraded.
+    Upgraded {
+        /// The version before the upgrade.
+        from: String,
+        /// The version after the upgrade.
+        to: String,
+    },
+    /// The package was downgraded.
+    Downgraded {
+        /// The version before the downgrade.
+        from: String,
+        /// The version after the downgrade.
+        to: String,
+    },
+    /// The package was removed at the given version.
+    Removed(String),
+}
+
+impl PackageAction {
+    /// The name of this action, as in the pacman log.
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            PackageAction::Installed(_) => "installed",
+            PackageAction::Reinstalled(_) => "reinstalled",
+            PackageAction::Upgraded { .. } => "upgraded",
+            PackageAction::Downgraded { .. } => "downgraded",
+            PackageAction::Removed(_) => "removed",
+        }
+    }
+}
+
+impl Display for PackageAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PackageAction::Installed(version)
+            | PackageAction::Reinstalled(version)
+            | PackageAction::Removed(version) => write!(f, "{} {version}", self.name()),
+            PackageAction::Upgraded { from, to } | PackageAction::Downgraded { from, to } => {
+                write!(f, "{} {from} -> {to}", self.name())
+            }
+        }
+    }
+}
+
+/// An event of a single package in the pacman log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageEvent {
+    /// The timestamp of the event, as written in the log.
+    pub timestamp: String,
+    /// The name of the package.
+    pub package: String,
+    /// What happened to the package.
+    pub action: PackageAction,
+}
+
+/// A pacman invocation in the log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    /// The timestamp of the pacman invocation, as written in the log.
+    pub timestamp: String,
+    /// The pacman command line, if known.
+    ///
+    /// This is `None` for package events before the first command line in the
+    /// log.
+    pub command: Option<String>,
+    /// All package events of this invocation, in order.
+    pub events: Vec<PackageEvent>,
+}
+
+/// Split a log line into timestamp, source, and message.
+fn split_line(line: &str) -> Option<(&str, &str, &str)> {
+    let (timestamp, rest) = line.strip_prefix('[')?.split_once("] ")?;
+    let (source, message) = rest.strip_prefix('[')?.split_once("] ")?;
+    Some((timestamp, source, message))
+}
+
+/// Parse a package event message, e.g. `upgraded foo (1.0-1 -> 1.1-1)`.
+fn parse_action(message: &str) -> Option<(&str, PackageAction)> {
+    let (action, rest) = message.split_once(' ')?;
+    let (package, versions) = rest.split_once(' ')?;
+    let versions = versions.strip_prefix('(')?.strip_suffix(')')?;
+    let action = match action {
+        "installed" => PackageAction::Installed(versions.to_owned()),
+        "reinstalled" => PackageAction::Reinstalled(versions.to_owned()),
+        "removed" => PackageAction::Removed(versions.to_owned()),
+        "upgraded" | "downgraded" => {
+            let (from, to) = versions.split_once(" -> ")?;
+            let (from, to) = (from.to_owned(), to.to_owned());
+            if action == "upgraded" {
+                PackageAction::Upgraded { from, to }
+            } else {
+                PackageAction::Downgraded { from, to }
+            }
+        }
+        _ => return None,
+    };
+    Some((package, action))
+}
+
+/// Read all transactions from a pacman log.
+///
+/// Group package events by the pacman command line preceding them.  Ignore
+/// all lines which are neither pacman command lines nor package events, e.g.
+/// output of install scriptlets or hooks.
+///
+/// # Errors
+///
+/// Return an error if reading from `reader` fails.
+pub fn read_log<R: BufRead>(mut reader: R) -> std::io::Result<Vec<Transaction>> {
+    let mut transactions: Vec<Transaction> = Vec::new();
+    let mut buffer = Vec::new();
+    while reader.read_until(b'\n', &mut buffer)? != 0 {
+        // The log may contain arbitrary output of scriptlets, so don't insist
+        // on valid UTF-8.
+        let line = String::from_utf8_lossy(&buffer);
+        let line = line.trim_end();
+        if let Some((timestamp, source, message)) = split_line(line) {
+            if source == "PACMAN"
+                && let Some(command) = message
+                    .strip_prefix("Running '")
+                    .and_then(|command| command.strip_suffix('\''))
+            {
+                transactions.push(Transaction {
+                    timestamp: timestamp.to_owned(),
+                    command: Some(command.to_owned()),
+                    events: Vec::new(),
+                });
+            } else if source == "ALPM"
+                && let Some((package, action)) = parse_action(message)
+            {
+                trace!(package, "{timestamp}: {action}");
+                let event = PackageEvent {
+                    timestamp: timestamp.to_owned(),
+                    package: package.to_owned(),
+                    action,
+                };
+                match transactions.last_mut() {
+                    Some(transaction) => transaction.events.push(event),
+                    None => transactions.push(Transaction {
+                        timestamp: timestamp.to_owned(),
+                        command: None,
+                        events: vec![event],
+                    }),
+                }
+            }
+        }
+        buffer.clear();
+    }
+    Ok(transactions)
+}
+
+/// Get all events of a package.
+///
+/// Return all events of the package named `package` in `transactions`, in
+/// order, along with the transaction of each event.
+#[must_use]
+pub fn package_events<'a>(
+    transactions: &'a [Transaction],
+    package: &str,
+) -> Vec<(&'a Transaction, &'a PackageEvent)> {
+    transactions
+        .iter()
+        .flat_map(|transaction| {
+            transaction
+                .events
+                .iter()
+                .filter(|event| event.package == package)
+                .map(move |event| (transaction, event))
+        })
+        .collect()
+}
+
+/// The transaction which installed a package.
+#[derive(Debug)]
+pub struct InstalledBy<'a> {
+    /// The transaction.
+    pub transaction: &'a Transaction,
+    /// Packages installed in the same transaction which depend on the package, sorted by name.
+    pub dependents: Vec<PackageNode<'a>>,
+}
+
+/// The history of a package.
+#[derive(Debug)]
+pub struct PackageHistory<'a> {
+    /// The name of the package.
+    pub name: &'a str,
+    /// The position of the package in the dependency graph, if it's installed.
+    pub position: Option<GraphPosition>,
+    /// All events of the package, in order.
+    pub events: Vec<(&'a Transaction, &'a PackageEvent)>,
+    /// The transaction which installed the package, if it's installed.
+    pub installed_by: Option<InstalledBy<'a>>,
+}
+
+/// Get the history of a package.
+///
+/// Collect all events of the package named `name` in `transactions`.  If the
+/// package is `installed`, also determine its position in `graph`, and find
+/// the transaction which last installed it, along with all packages installed
+/// in the same transaction which depend on it.
+pub fn package_history<'a, G>(
+    graph: G,
+    transactions: &'a [Transaction],
+    name: &'a str,
+    installed: Option<&'a Package>,
+) -> PackageHistory<'a>
+where
+    G: GraphRef
+        + NodeCount
+        + Visitable<NodeId = PackageNode<'a>>
+        + IntoNeighbors
+        + IntoNeighborsDirected
+        + IntoNodeIdentifiers,
+{
+    let events = package_events(transactions, name);
+    let Some(package) = installed else {
+        return PackageHistory {
+            name,
+            position: None,
+            events,
+            installed_by: None,
+        };
+    };
+
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let orphans = crate::dependencies::orphans(graph)
+        .node_identifiers()
+        .collect::<HashSet<_>>();
+    let installed_by = events
+        .iter()
+        .rev()
+        .find(|(_, event)| matches!(event.action, PackageAction::Installed(_)))
+        .map(|(transaction, _)| {
+            let installed_along = transaction
+                .events
+                .iter()
+                .filter(|event| matches!(event.action, PackageAction::Installed(_)))
+                .map(|event| event.package.as_str())
+                .collect::<HashSet<_>>();
+            let mut dependents = crate::dependencies::dependents(graph, package)
+                .node_identifiers()
+                .filter(|node| {
+                    *node != PackageNode::new(package) && installed_along.contains(node.name())
+                })
+                .collect::<Vec<_>>();
+            dependents.sort_by_key(|node| node.package().name());
+            InstalledBy {
+                transaction,
+                dependents,
+            }
+        });
+    PackageHistory {
+        name,
+        position: Some(GraphPosition::of(PackageNode::new(package), &orphans)),
+        events,
+        installed_by,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_action_installed() {
+        assert_eq!(
+            parse_action("installed foo (1.0-1)"),
+            Some(("foo", PackageAction::Installed("1.0-1".to_owned())))
+        );
+    }
+
+    #[test]
+    fn parse_action_reinstalled() {
+        assert_eq!(
+            parse_action("reinstalled foo (1:1.0-1)"),
+            Some(("foo", PackageAction::Reinstalled("1:1.0-1".to_owned())))
+        );
+    }
+
+    #[test]
+    fn parse_action_removed() {
+        assert_eq!(
+            parse_action("removed foo-bar (1.0-1)"),
+            Some(("foo-bar", PackageAction::Removed("1.0-1".to_owned())))
+        );
+    }
+
+    #[test]
+    fn parse_action_upgraded() {
+        assert_eq!(
+            parse_action("upgraded foo (1.0-1 -> 1.1-1)"),
+            Some((
+                "foo",
+                PackageAction::Upgraded {
+                    from: "1.0-1".to_owned(),
+                    to: "1.1-1".to_owned()
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_action_downgraded() {
+        assert_eq!(
+            parse_action("downgraded foo (1.1-1 -> 1.0-1)"),
+            Some((
+                "foo",
+                PackageAction::Downgraded {
+                    from: "1.1-1".to_owned(),
+                    to: "1.0-1".to_owned()
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_action_malformed() {
+        assert_eq!(parse_action(""), None);
+        assert_eq!(parse_action("installed"), None);
+        assert_eq!(parse_action("installed foo"), None);
+        assert_eq!(parse_action("installed foo 1.0-1"), None);
+        assert_eq!(parse_action("installed foo (1.0-1"), None);
+        assert_eq!(parse_action("upgraded foo (1.0-1)"), None);
+        assert_eq!(parse_action("frobnicated foo (1.0-1)"), None);
+        assert_eq!(parse_action("transaction started"), None);
+    }
+
+    #[test]
+    fn split_line_timestamp_formats() {
+        assert_eq!(
+            split_line("[2019-01-01 12:00] [ALPM] installed foo (1.0-1)"),
+            Some(("2019-01-01 12:00", "ALPM", "installed foo (1.0-1)"))
+        );
+        assert_eq!(
+            split_line("[2024-01-01T12:00:00+0100] [PACMAN] Running 'pacman -S foo'"),
+            Some((
+                "2024-01-01T12:00:00+0100",
+                "PACMAN",
+                "Running 'pacman -S foo'"
+            ))
+        );
+    }
+
+    #[test]
+    fn split_line_malformed() {
+        assert_eq!(split_line(""), None);
+        assert_eq!(split_line("installed foo (1.0-1)"), None);
+        assert_eq!(split_line("[2019-01-01 12:00] installed foo (1.0-1)"), None);
+        assert_eq!(split_line("[2019-01-01 12:00 [ALPM] installed foo"), None);
+    }
+
+    #[test]
+    fn read_log_groups_events_by_command() {
+        let log = "\
+[2019-01-01 12:00] [ALPM] installed foo (1.0-1)
+[2019-01-01 12:00] [ALPM] installed bar (2.0-1)
+[2024-01-01T12:00:00+0100] [PACMAN] Running 'pacman -S foo'
+[2024-01-01T12:00:00+0100] [ALPM] transaction started
+[2024-01-01T12:00:01+0100] [ALPM] reinstalled foo (1.0-1)
+[2024-01-01T12:00:01+0100] [ALPM-SCRIPTLET] installed baz (1.0-1)
+[2024-01-01T12:00:02+0100] [ALPM] transaction completed
+garbage without timestamp
+[2024-01-02T12:00:00+0100] [PACMAN] Running 'pacman -Syu'
+[2024-01-02T12:00:01+0100] [ALPM] upgraded foo (1.0-1 -> 1.1-1)
+[2024-01-02T12:00:01+0100] [ALPM] downgraded bar (2.0-1 -> 1.9-1)
+[2024-01-02T12:00:02+0100] [ALPM] running 'update-desktop-database.hook'...
+[2024-01-03T12:00:00+0100] [PACMAN] Running 'pacman -R bar'
+[2024-01-03T12:00:01+0100] [ALPM] removed bar (1.9-1)
+[2024-01-03T12:00:01+0100] [ALPM] removed bar
+";
+        let transactions = read_log(log.as_bytes()).unwrap();
+        assert_eq!(
+            transactions,
+            vec![
+                Transaction {
+                    timestamp: "2019-01-01 12:00".to_owned(),
+                    command: None,
+                    events: vec![
+                        PackageEvent {
+                            timestamp: "2019-01-01 12:00".to_owned(),
+                            package: "foo".to_owned(),
+                            action: PackageAction::Installed("1.0-1".to_owned()),
+                        },
+                        PackageEvent {
+                            timestamp: "2019-01-01 12:00".to_owned(),
+                            package: "bar".to_owned(),
+                            action: PackageAction::Installed("2.0-1".to_owned()),
+                        },
+                    ],
+                },
+                Transaction {
+                    timestamp: "2024-01-01T12:00:00+0100".to_owned(),
+                    command: Some("pacman -S foo".to_owned()),
+                    events: vec![PackageEvent {
+                        timestamp: "2024-01-01T12:00:01+0100".to_owned(),
+                        package: "foo".to_owned(),
+                        action: PackageAction::Reinstalled("1.0-1".to_owned()),
+                    }],
+                },
+                Transaction {
+                    timestamp: "2024-01-02T12:00:00+0100".to_owned(),
+                    command: Some("pacman -Syu".to_owned()),
+                    events: vec![
+                        PackageEvent {
+                            timestamp: "2024-01-02T12:00:01+0100".to_owned(),
+                            package: "foo".to_owned(),
+                            action: PackageAction::Upgraded {
+                                from: "1.0-1".to_owned(),
+                                to: "1.1-1".to_owned()
+                            },
+                        },
+                        PackageEvent {
+                            timestamp: "2024-01-02T12:00:01+0100".to_owned(),
+                            package: "bar".to_owned(),
+                            action: PackageAction::Downgraded {
+                                from: "2.0-1".to_owned(),
+                                to: "1.9-1".to_owned()
+                            },
+                        },
+                    ],
+                },
+                Transaction {
+                    timestamp: "2024-01-03T12:00:00+0100".to_owned(),
+                    command: Some("pacman -R bar".to_owned()),
+                    events: vec![PackageEvent {
+                        timestamp: "2024-01-03T12:00:01+0100".to_owned(),
+                        package: "bar".to_owned(),
+                        action: PackageAction::Removed("1.9-1".to_owned()),
+                    }],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn read_log_invalid_utf8() {
+        let log = b"[2024-01-01T12:00:00+0100] [ALPM-SCRIPTLET] \xff\xfe\n\
+[2024-01-01T12:00:01+0100] [ALPM] installed foo (1.0-1)\n";
+        let transactions = read_log(&log[..]).unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(
+            package_events(&transactions, "foo")
+                .into_iter()
+                .map(|(_, event)| event.action.clone())
+                .collect::<Vec<_>>(),
+            vec![PackageAction::Installed("1.0-1".to_owned())]
+        );
+    }
+
+    #[test]
+    fn package_events_of_package() {
+        let log = "\
+[2024-01-01T12:00:00+0100] [PACMAN] Running 'pacman -S foo bar'
+[2024-01-01T12:00:01+0100] [ALPM] installed bar (1.0-1)
+[2024-01-01T12:00:01+0100] [ALPM] installed foo (1.0-1)
+[2024-01-02T12:00:00+0100] [PACMAN] Running 'pacman -R foo'
+[2024-01-02T12:00:01+0100] [ALPM] removed foo (1.0-1)
+";
+        let transactions = read_log(log.as_bytes()).unwrap();
+        let events = package_events(&transactions, "foo")
+            .into_iter()
+            .map(|(transaction, event)| (transaction.command.as_deref(), event.action.name()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                (Some("pacman -S foo bar"), "installed"),
+                (Some("pacman -R foo"), "removed")
+            ]
+        );
+        assert!(package_events(&transactions, "baz").is_empty());
+    }
+}
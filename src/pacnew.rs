@@ -0,0 +1,311 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL-1.2 OR GPL-3.0
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Find `.pacnew` and `.pacsave` files of backup files.
+
+use std::{collections::HashSet, fmt::Display, hash::BuildHasher, path::Path};
+
+use alpm::Package;
+use tracing::{debug, warn};
+
+use crate::diff::unified_diff;
+
+/// The kind of a file pacman saved next to a backup file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SavedFileKind {
+    /// A new version of the file from an upgrade, which pacman did not install
+    /// because the live file was modified.
+    Pacnew,
+    /// A modified file which pacman saved when removing or replacing a package.
+    Pacsave,
+}
+
+impl SavedFileKind {
+    /// All kinds of saved files.
+    pub const ALL: [SavedFileKind; 2] = [SavedFileKind::Pacnew, SavedFileKind::Pacsave];
+
+    /// The extension pacman appends to the path of the backup file.
+    #[must_use]
+    pub fn extension(self) -> &'static str {
+        match self {
+            SavedFileKind::Pacnew => "pacnew",
+            SavedFileKind::Pacsave => "pacsave",
+        }
+    }
+}
+
+impl Display for SavedFileKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+/// The state of a live backup file, compared to the packaged original.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LiveFileState {
+    /// The live file does not exist.
+    Missing,
+    /// The live file is identical to the packaged original.
+    Unmodified,
+    /// The live file differs from the packaged original.
+    Modified,
+    /// The live file could not be compared, e.g. because it is not readable or
+    /// the database has no hash for it.
+    Unknown,
+}
+
+impl Display for LiveFileState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            LiveFileState::Missing => write!(f, "missing"),
+            LiveFileState::Unmodified => write!(f, "unmodified"),
+            LiveFileState::Modified => write!(f, "modified"),
+            LiveFileState::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// A `.pacnew` or `.pacsave` file next to a backup file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SavedFile {
+    /// The absolute path of the live backup file, as seen from within the root
+    /// directory.
+    pub path: String,
+    /// The kind of the saved file.
+    pub kind: SavedFileKind,
+    /// The state of the live file.
+    pub live: LiveFileState,
+}
+
+impl SavedFile {
+    /// The absolute path of the saved file, as seen from within the root directory.
+    #[must_use]
+    pub fn saved_path(&self) -> String {
+        format!("{}.{}", self.path, self.kind.extension())
+    }
+
+    /// Create a unified diff between the live file and the saved file under `root`.
+    ///
+    /// Diff from the live file to a `.pacnew` file, and from a `.pacsave` file
+    /// to the live file, i.e. always from the older to the newer file.  Treat
+    /// a missing live file as empty, and replace invalid UTF-8.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if the saved file or the existing live file cannot be read.
+    pub fn diff(&self, root: &Path) -> std::io::Result<String> {
+        let read = |path: &str| std::fs::read(root.join(path.trim_start_matches('/')));
+        let live = match read(&self.path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(error) => return Err(error),
+        };
+        let live = String::from_utf8_lossy(&live);
+        let saved_path = self.saved_path();
+        let saved = read(&saved_path)?;
+        let saved = String::from_utf8_lossy(&saved);
+        Ok(match self.kind {
+            SavedFileKind::Pacnew => unified_diff(&live, &saved, &self.path, &saved_path),
+            SavedFileKind::Pacsave => unified_diff(&saved, &live, &saved_path, &self.path),
+        })
+    }
+}
+
+/// All saved files of a package.
+#[derive(Debug)]
+pub struct PackageSavedFiles<'a> {
+    /// The package.
+    pub package: &'a Package,
+    /// Saved files next to backup files of the package, sorted by path.
+    pub files: Vec<SavedFile>,
+}
+
+/// Compare a live backup file to the `hash` of its packaged original.
+fn live_file_state(path: &Path, hash: &str) -> LiveFileState {
+    if !path.exists() {
+        return LiveFileState::Missing;
+    }
+    if hash.is_empty() {
+        return LiveFileState::Unknown;
+    }
+    match alpm::compute_md5sum(path.as_os_str().as_encoded_bytes()) {
+        Ok(actual) if actual == hash => LiveFileState::Unmodified,
+        Ok(_) => LiveFileState::Modified,
+        Err(error) => {
+            warn!("Failed to compute checksum of {}: {error}", path.display());
+            LiveFileState::Unknown
+        }
+    }
+}
+
+/// Find `.pacnew` and `.pacsave` files for all backup files of `packages`.
+///
+/// Look for saved files next to every backup file of every package under
+/// `root`, and compare the live file to the hash of the packaged original in
+/// the database, to tell whether the live file has local modifications.
+///
+/// Return only packages with saved files, sorted by name.
+pub fn find_saved_files<'a, I>(root: &Path, packages: I) -> Vec<PackageSavedFiles<'a>>
+where
+    I: IntoIterator<Item = &'a Package>,
+{
+    let mut result = packages
+        .into_iter()
+        .filter_map(|package| {
+            let mut files = Vec::new();
+            for backup in package.backup() {
+                let live_path = root.join(backup.name());
+                for kind in SavedFileKind::ALL {
+                    let mut saved_path = live_path.clone().into_os_string();
+                    saved_path.push(".");
+                    saved_path.push(kind.extension());
+                    if Path::new(&saved_path).exists() {
+                        files.push(SavedFile {
+                            path: format!("/{}", backup.name()),
+                            kind,
+                            live: live_file_state(&live_path, backup.hash()),
+                        });
+                    }
+                }
+            }
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+            (!files.is_empty()).then_some(PackageSavedFiles { package, files })
+        })
+        .collect::<Vec<_>>();
+    result.sort_by_key(|saved| saved.package.name());
+    result
+}
+
+/// Get the absolute paths of all backup files of `packages`.
+///
+/// Return paths as seen from within the root directory.
+pub fn backup_files<'a, I>(packages: I) -> HashSet<String>
+where
+    I: IntoIterator<Item = &'a Package>,
+{
+    packages
+        .into_iter()
+        .flat_map(|package| package.backup())
+        .map(|backup| format!("/{}", backup.name()))
+        .collect()
+}
+
+fn walk_saved_files<S: BuildHasher>(
+    root: &Path,
+    directory: &str,
+    backups: &HashSet<String, S>,
+    saved: &mut Vec<SavedFile>,
+) {
+    let path = root.join(directory.trim_start_matches('/'));
+    let entries = match std::fs::read_dir(&path) {
+        Ok(entries) => entries,
+        Err(error) => {
+            warn!("Failed to read directory {}: {error}", path.display());
+            return;
+        }
+    };
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                warn!("Failed to read directory {}: {error}", path.display());
+                continue;
+            }
+        };
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        let file_path = format!("{}/{file_name}", directory.trim_end_matches('/'));
+        // Do not follow symlinks to directories
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            walk_saved_files(root, &file_path, backups, saved);
+            continue;
+        }
+        for kind in SavedFileKind::ALL {
+            let Some(live_path) = file_path
+                .strip_suffix(kind.extension())
+                .and_then(|path| path.strip_suffix('.'))
+            else {
+                continue;
+            };
+            if backups.contains(live_path) {
+                continue;
+            }
+            debug!("{file_path} belongs to no backup file of an installed package");
+            let live = if root.join(live_path.trim_start_matches('/')).exists() {
+                LiveFileState::Unknown
+            } else {
+                LiveFileState::Missing
+            };
+            saved.push(SavedFile {
+                path: live_path.to_owned(),
+                kind,
+                live,
+            });
+        }
+    }
+}
+
+/// Find `.pacnew` and `.pacsave` files in `directory` which belong to no backup file.
+///
+/// Recursively walk `directory`, an absolute path as seen from within `root`,
+/// and find all saved files whose live file is not in `backups`, see
+/// [`backup_files`].  These are mostly `.pacsave` files which pacman left
+/// behind when removing a package.  The database has no hash for these files,
+/// so the state of their live file is either missing or unknown.
+///
+/// Log and skip directories which cannot be read.
+///
+/// Return saved files sorted by path.
+#[must_use]
+pub fn find_unowned_saved_files<S: BuildHasher>(
+    root: &Path,
+    directory: &str,
+    backups: &HashSet<String, S>,
+) -> Vec<SavedFile> {
+    let mut saved = Vec::new();
+    walk_saved_files(root, directory, backups, &mut saved);
+    saved.sort_by(|a, b| (&a.path, a.kind.extension()).cmp(&(&b.path, b.kind.extension())));
+    saved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_unowned_saved_files_in_directory() {
+        let root = std::env::temp_dir().join(format!("packit-pacnew-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("etc/foo")).unwrap();
+        for file in [
+            "etc/owned.conf",
+            "etc/owned.conf.pacsave",
+            "etc/foo/removed.conf.pacsave",
+            "etc/live.conf",
+            "etc/live.conf.pacnew",
+            "etc/pacsave",
+        ] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+        let backups = HashSet::from(["/etc/owned.conf".to_owned()]);
+        let saved = find_unowned_saved_files(&root, "/etc", &backups);
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            saved,
+            [
+                SavedFile {
+                    path: "/etc/foo/removed.conf".to_owned(),
+                    kind: SavedFileKind::Pacsave,
+                    live: LiveFileState::Missing,
+                },
+                SavedFile {
+                    path: "/etc/live.conf".to_owned(),
+                    kind: SavedFileKind::Pacnew,
+                    live: LiveFileState::Unknown,
+                },
+            ]
+        );
+    }
+}
@@ -6,16 +6,40 @@
 
 //! Utilities for printing packages.
 
-use std::io::prelude::*;
+use std::{borrow::Cow, fmt::Display, io::prelude::*, path::Path};
 
+use alpm::{PackageReason, Pkg};
 use anstyle::{AnsiColor, Reset, Style};
-use packit::graph::{DependencyEdge, PackageNode};
+use packit::{
+    advisories::Finding,
+    cache::{CacheStatus, CachedPackage, RetentionPolicy},
+    conflicts::Conflict,
+    dependencies::{BlastRadius, ClosureSize, ForeignPackage, GraphPosition},
+    downgrade::{Satisfaction, VersionCheck},
+    graph::{
+        Constraint, DependencyEdge, PackageNode, ProvisionGraphMap, ProvisionGraphNode,
+        edge_constraints,
+    },
+    groups::{GroupStatus, PackageGroup},
+    linkcheck::LinkCheck,
+    lostfiles::LostFile,
+    package_list::Reconciliation,
+    pacman_log::{PackageAction, PackageEvent, PackageHistory, Transaction},
+    pacnew::{LiveFileState, PackageSavedFiles, SavedFile},
+    srcinfo::SourcePackage,
+    stats::GraphStatistics,
+    upgrade::UpgradePreview,
+    upstream::{Alternative, StalePackage},
+    verify::{FileProblem, FileState, PackageVerification},
+};
 use petgraph::{
     dot::{Config, Dot, RankDir},
     visit::{
-        Data, EdgeRef, GraphProp, IntoEdgeReferences, IntoNodeReferences, NodeIndexable, NodeRef,
+        Data, EdgeRef, GraphBase, GraphProp, IntoEdgeReferences, IntoEdges, IntoNodeIdentifiers,
+        IntoNodeReferences, NodeIndexable, NodeRef,
     },
 };
+use serde::Serialize;
 
 /// How to print a package.
 #[derive(Debug, Copy, Clone)]
@@ -24,6 +48,29 @@ pub enum PrintOneLine {
     NameOnly,
     /// Print with version.
     WithVersion,
+    /// Print with version and installed size.
+    WithSize,
+}
+
+/// Format a size in bytes with binary units.
+pub fn format_size(bytes: i64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+    // Compute on the magnitude in u128, to avoid overflows for large sizes
+    let magnitude = u128::from(bytes.unsigned_abs());
+    let mut unit = 0;
+    let mut divisor = 1;
+    while unit + 1 < UNITS.len() && 1024 * divisor <= magnitude {
+        unit += 1;
+        divisor *= 1024;
+    }
+    let hundredths = magnitude * 100 / divisor;
+    let sign = if bytes < 0 { "-" } else { "" };
+    format!(
+        "{sign}{}.{:02} {}",
+        hundredths / 100,
+        hundredths % 100,
+        UNITS.get(unit).unwrap_or(&"B")
+    )
 }
 
 /// Print a package on one single line.
@@ -44,9 +91,110 @@ pub fn print_package_one_line<W: Write>(
                 package.version()
             )
         }
+        PrintOneLine::WithSize => {
+            let bold = Style::new().bold();
+            let green = bold.fg_color(Some(AnsiColor::Green.into()));
+            let cyan = Style::new().fg_color(Some(AnsiColor::Cyan.into()));
+            writeln!(
+                write,
+                "{bold}{} {green}{}{Reset} {cyan}{}{Reset}",
+                package.name(),
+                package.version(),
+                format_size(package.isize())
+            )
+        }
+    }
+}
+
+/// Print an orphan package, and whether it's part of a dependency cycle.
+pub fn print_orphan<W: Write>(
+    write: &mut W,
+    package: &alpm::Package,
+    in_cycle: bool,
+) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    let green = bold.fg_color(Some(AnsiColor::Green.into()));
+    let yellow = Style::new().fg_color(Some(AnsiColor::Yellow.into()));
+    write!(
+        write,
+        "{bold}{} {green}{}{Reset}",
+        package.name(),
+        package.version()
+    )?;
+    if in_cycle {
+        write!(write, " {yellow}(dependency cycle){Reset}")?;
+    }
+    writeln!(write)
+}
+
+/// Print the total installed size of a set of packages.
+pub fn print_size_footer<W: Write>(write: &mut W, total_size: i64) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    writeln!(
+        write,
+        "\n{bold}Total installed size:{Reset} {}",
+        format_size(total_size)
+    )
+}
+
+/// The dot label of a package node.
+fn package_label(package: &Pkg, format: PrintOneLine) -> String {
+    match format {
+        PrintOneLine::NameOnly => format!(
+            "label = <<FONT FACE=\"sans-serif\">{}</FONT>>",
+            package.name()
+        ),
+        PrintOneLine::WithVersion => format!(
+            "label = <<FONT FACE=\"sans-serif\"><B>{name} <FONT COLOR=\"green\">{version}</FONT></B></FONT>>",
+            name = package.name(),
+            version = package.version()
+        ),
+        PrintOneLine::WithSize => format!(
+            "label = <<FONT FACE=\"sans-serif\"><B>{name} <FONT COLOR=\"green\">{version}</FONT></B><BR/>{size}</FONT>>",
+            name = package.name(),
+            version = package.version(),
+            size = format_size(package.isize())
+        ),
     }
 }
 
+/// The dot style of a dependency edge.
+fn edge_style(edge: DependencyEdge) -> String {
+    match edge {
+        DependencyEdge::Required => "style = solid".to_string(),
+        DependencyEdge::Optional => "style = dashed".to_string(),
+        DependencyEdge::Make => "style = solid, color = blue".to_string(),
+        DependencyEdge::Check => "style = dotted, color = blue".to_string(),
+    }
+}
+
+/// The dot attributes of a dependency edge between two packages.
+///
+/// Style the edge according to its kind, and label it with all dependencies
+/// which restrict the target package beyond its name, e.g. `foo>=2.3` or `sh`.
+/// With `by_name` only label dependencies which the target package satisfies
+/// by its name.
+fn package_edge_attributes(
+    dependent: PackageNode<'_>,
+    provider: PackageNode<'_>,
+    edge: DependencyEdge,
+    by_name: bool,
+) -> String {
+    let mut attributes = edge_style(edge);
+    let label = edge_constraints(dependent, provider, edge)
+        .iter()
+        .filter(|constraint| constraint.is_restrictive())
+        .filter(|constraint| !by_name || constraint.provision.is_none())
+        .map(|constraint| escape_html(&constraint.dependency.to_string()))
+        .collect::<Vec<_>>();
+    if !label.is_empty() {
+        attributes.push_str(", label = <<FONT FACE=\"sans-serif\">");
+        attributes.push_str(&label.join("<BR/>"));
+        attributes.push_str("</FONT>>");
+    }
+    attributes
+}
+
 /// Print a package graph as dot.
 pub fn print_package_graph<'a, G, W: Write>(
     write: &mut W,
@@ -55,24 +203,150 @@ pub fn print_package_graph<'a, G, W: Write>(
 ) -> std::io::Result<()>
 where
     G: GraphProp
+        + GraphBase<NodeId = PackageNode<'a>>
         + Data<NodeWeight = PackageNode<'a>, EdgeWeight = DependencyEdge>
         + IntoEdgeReferences
         + IntoNodeReferences
         + NodeIndexable,
 {
-    let get_node_attributes = |_graph, node: G::NodeRef| {
-        let package = node.weight();
-        match format {
-            PrintOneLine::NameOnly => format!(
-                "label = <<FONT FACE=\"sans-serif\">{}</FONT>>",
-                package.name()
-            ),
-            PrintOneLine::WithVersion => format!(
-                "label = <<FONT FACE=\"sans-serif\"><B>{name} <FONT COLOR=\"green\">{version}</FONT></B></FONT>>",
-                name = package.name(),
-                version = package.version()
-            ),
+    let get_node_attributes = |_graph, node: G::NodeRef| package_label(node.weight(), format);
+    let dot = Dot::with_attr_getters(
+        graph,
+        &[
+            Config::EdgeNoLabel,
+            Config::NodeNoLabel,
+            Config::RankDir(RankDir::TB),
+        ],
+        &|_graph, edge| {
+            package_edge_attributes(edge.source(), edge.target(), *edge.weight(), false)
+        },
+        &get_node_attributes,
+    );
+    writeln!(write, "{dot}")
+}
+
+#[derive(Debug, Serialize)]
+struct JsonPackage<'a> {
+    name: &'a str,
+    version: &'a str,
+    reason: &'static str,
+    installed_size: i64,
+}
+
+impl<'a> From<PackageNode<'a>> for JsonPackage<'a> {
+    fn from(node: PackageNode<'a>) -> Self {
+        let package = node.package();
+        Self {
+            name: package.name(),
+            version: package.version().as_str(),
+            reason: match package.reason() {
+                PackageReason::Explicit => "explicit",
+                PackageReason::Depend => "dependency",
+            },
+            installed_size: package.isize(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct JsonConstraint<'a> {
+    dependency: String,
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    operator: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provision: Option<String>,
+}
+
+impl<'a> From<&Constraint<'a>> for JsonConstraint<'a> {
+    fn from(constraint: &Constraint<'a>) -> Self {
+        let dependency = constraint.dependency;
+        Self {
+            dependency: dependency.to_string(),
+            name: dependency.name,
+            operator: dependency.version.map(|_| dependency.operator()),
+            version: dependency.version,
+            provision: constraint.provision.map(|provision| provision.to_string()),
         }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct JsonEdge<'a> {
+    from: Cow<'a, str>,
+    to: Cow<'a, str>,
+    kind: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    constraints: Vec<JsonConstraint<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonGraph<'a> {
+    packages: Vec<JsonPackage<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    provisions: Vec<String>,
+    dependencies: Vec<JsonEdge<'a>>,
+    installed_size: i64,
+}
+
+/// Print a package graph as JSON.
+///
+/// Print an object with all `packages` in the graph, sorted by name, all
+/// `dependencies` between these packages, and the total `installed_size` of
+/// all packages.
+pub fn print_package_graph_json<'a, G, W: Write>(write: &mut W, graph: G) -> std::io::Result<()>
+where
+    G: GraphBase<NodeId = PackageNode<'a>>
+        + Data<NodeWeight = PackageNode<'a>, EdgeWeight = DependencyEdge>
+        + IntoEdgeReferences
+        + IntoNodeReferences,
+{
+    let mut packages = graph
+        .node_references()
+        .map(|node| JsonPackage::from(*node.weight()))
+        .collect::<Vec<_>>();
+    packages.sort_by_key(|package| package.name);
+    let mut dependencies = graph
+        .edge_references()
+        .map(|edge| JsonEdge {
+            from: Cow::Borrowed(edge.source().package().name()),
+            to: Cow::Borrowed(edge.target().package().name()),
+            kind: edge.weight().to_string(),
+            constraints: edge_constraints(edge.source(), edge.target(), *edge.weight())
+                .iter()
+                .map(JsonConstraint::from)
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    dependencies.sort_by(|a, b| (&a.from, &a.to).cmp(&(&b.from, &b.to)));
+    let installed_size = packages.iter().map(|package| package.installed_size).sum();
+    let graph = JsonGraph {
+        packages,
+        provisions: Vec::new(),
+        dependencies,
+        installed_size,
+    };
+    serde_json::to_writer_pretty(&mut *write, &graph)?;
+    writeln!(write)
+}
+
+/// Print a package graph with provisions as dot.
+///
+/// Print packages like [`print_package_graph`], and provisions as ellipses in
+/// italics.
+pub fn print_provision_graph<W: Write>(
+    write: &mut W,
+    graph: &ProvisionGraphMap,
+    format: PrintOneLine,
+) -> std::io::Result<()> {
+    let get_node_attributes = |_graph, (node, _)| match node {
+        ProvisionGraphNode::Package(package) => package_label(&package, format),
+        ProvisionGraphNode::Provision(provision) => format!(
+            "shape = ellipse, label = <<FONT FACE=\"sans-serif\"><I>{}</I></FONT>>",
+            escape_html(&provision.to_string())
+        ),
     };
     let dot = Dot::with_attr_getters(
         graph,
@@ -81,11 +355,2015 @@ where
             Config::NodeNoLabel,
             Config::RankDir(RankDir::TB),
         ],
-        &|_graph, edge| match *edge.weight() {
-            DependencyEdge::Required => "style = solid".to_string(),
-            DependencyEdge::Optional => "style = dashed".to_string(),
+        &|_graph, (dependent, provider, edge)| match (dependent, provider) {
+            (ProvisionGraphNode::Package(dependent), ProvisionGraphNode::Package(provider)) => {
+                package_edge_attributes(dependent, provider, *edge, true)
+            }
+            _ => edge_style(*edge),
         },
         &get_node_attributes,
     );
     writeln!(write, "{dot}")
 }
+
+/// Escape text for HTML labels in dot.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// The name of a node in a graph with provisions.
+fn provision_node_name(node: ProvisionGraphNode<'_>) -> Cow<'_, str> {
+    match node {
+        ProvisionGraphNode::Package(package) => Cow::Borrowed(package.package().name()),
+        ProvisionGraphNode::Provision(provision) => Cow::Owned(provision.to_string()),
+    }
+}
+
+/// Print a package graph with provisions as JSON.
+///
+/// Print an object like [`print_package_graph_json`], with an additional list
+/// of all `provisions` in the graph.  Dependencies refer to provisions by their
+/// name and version constraint, e.g. `libfoo.so=1-64`.
+pub fn print_provision_graph_json<W: Write>(
+    write: &mut W,
+    graph: &ProvisionGraphMap,
+) -> std::io::Result<()> {
+    let mut packages = Vec::new();
+    let mut provisions = Vec::new();
+    for node in graph.nodes() {
+        match node {
+            ProvisionGraphNode::Package(package) => packages.push(JsonPackage::from(package)),
+            ProvisionGraphNode::Provision(provision) => provisions.push(provision.to_string()),
+        }
+    }
+    packages.sort_by_key(|package| package.name);
+    provisions.sort();
+    let mut dependencies = graph
+        .all_edges()
+        .map(|(from, to, edge)| JsonEdge {
+            from: provision_node_name(from),
+            to: provision_node_name(to),
+            kind: edge.to_string(),
+            constraints: match (from, to) {
+                (ProvisionGraphNode::Package(from), ProvisionGraphNode::Package(to)) => {
+                    edge_constraints(from, to, *edge)
+                        .iter()
+                        .filter(|constraint| constraint.provision.is_none())
+                        .map(JsonConstraint::from)
+                        .collect()
+                }
+                _ => Vec::new(),
+            },
+        })
+        .collect::<Vec<_>>();
+    dependencies.sort_by(|a, b| (&a.from, &a.to).cmp(&(&b.from, &b.to)));
+    let installed_size = packages.iter().map(|package| package.installed_size).sum();
+    let graph = JsonGraph {
+        packages,
+        provisions,
+        dependencies,
+        installed_size,
+    };
+    serde_json::to_writer_pretty(&mut *write, &graph)?;
+    writeln!(write)
+}
+
+/// Print closure sizes of packages as a table.
+pub fn print_closure_sizes<W: Write>(write: &mut W, sizes: &[ClosureSize]) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    let green = bold.fg_color(Some(AnsiColor::Green.into()));
+    for size in sizes {
+        writeln!(
+            write,
+            "{:>12} {:>12}  {bold}{} {green}{}{Reset}",
+            format_size(size.total_size),
+            format_size(size.exclusive_size),
+            size.package.name(),
+            size.package.version()
+        )?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct JsonClosureSize<'a> {
+    #[serde(flatten)]
+    package: JsonPackage<'a>,
+    closure_packages: usize,
+    closure_size: i64,
+    exclusive_size: i64,
+}
+
+/// Print closure sizes of packages as JSON.
+pub fn print_closure_sizes_json<W: Write>(
+    write: &mut W,
+    sizes: &[ClosureSize],
+) -> std::io::Result<()> {
+    let sizes = sizes
+        .iter()
+        .map(|size| JsonClosureSize {
+            package: JsonPackage::from(size.package),
+            closure_packages: size.packages,
+            closure_size: size.total_size,
+            exclusive_size: size.exclusive_size,
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_writer_pretty(&mut *write, &sizes)?;
+    writeln!(write)
+}
+
+/// Print blast radii of packages as a table.
+pub fn print_blast_radii<W: Write>(write: &mut W, radii: &[BlastRadius]) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    let green = bold.fg_color(Some(AnsiColor::Green.into()));
+    writeln!(
+        write,
+        "{bold}{:>8} {:>10} {:>8} {:>12}  Package{Reset}",
+        "Score", "Dependents", "Explicit", "Size"
+    )?;
+    for radius in radii {
+        writeln!(
+            write,
+            "{:>8} {:>10} {:>8} {:>12}  {bold}{} {green}{}{Reset}",
+            radius.score(),
+            radius.dependents,
+            radius.explicit,
+            format_size(radius.dependents_size),
+            radius.package.name(),
+            radius.package.version()
+        )?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct JsonBlastRadius<'a> {
+    #[serde(flatten)]
+    package: JsonPackage<'a>,
+    score: usize,
+    dependents: usize,
+    dependents_size: i64,
+    explicit: usize,
+}
+
+/// Print blast radii of packages as JSON.
+pub fn print_blast_radii_json<W: Write>(
+    write: &mut W,
+    radii: &[BlastRadius],
+) -> std::io::Result<()> {
+    let radii = radii
+        .iter()
+        .map(|radius| JsonBlastRadius {
+            package: JsonPackage::from(radius.package),
+            score: radius.score(),
+            dependents: radius.dependents,
+            dependents_size: radius.dependents_size,
+            explicit: radius.explicit,
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_writer_pretty(&mut *write, &radii)?;
+    writeln!(write)
+}
+
+/// Format `part` as percentage of `total`, with one decimal.
+fn format_percentage(part: usize, total: usize) -> String {
+    let permille = (part * 1000).checked_div(total).unwrap_or_default();
+    format!("{}.{}%", permille / 10, permille % 10)
+}
+
+/// Print graph statistics as text.
+pub fn print_graph_statistics<W: Write>(
+    write: &mut W,
+    statistics: &GraphStatistics,
+) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    let dependency_packages = statistics.packages - statistics.explicit;
+    writeln!(
+        write,
+        "{bold}Packages:{Reset}        {}",
+        statistics.packages
+    )?;
+    writeln!(
+        write,
+        "{bold}Explicit:{Reset}        {} ({})",
+        statistics.explicit,
+        format_percentage(statistics.explicit, statistics.packages)
+    )?;
+    writeln!(
+        write,
+        "{bold}As dependency:{Reset}   {} ({})",
+        dependency_packages,
+        format_percentage(dependency_packages, statistics.packages)
+    )?;
+    writeln!(
+        write,
+        "{bold}Dependencies:{Reset}    {}",
+        statistics.dependencies
+    )?;
+    writeln!(
+        write,
+        "{bold}Components:{Reset}      {} ({} cyclic)",
+        statistics.components, statistics.cycles
+    )?;
+    writeln!(
+        write,
+        "{bold}Longest chain:{Reset}   {} packages",
+        statistics.longest_chain.len()
+    )?;
+    if !statistics.longest_chain.is_empty() {
+        let chain = statistics
+            .longest_chain
+            .iter()
+            .map(|node| node.name())
+            .collect::<Vec<_>>();
+        writeln!(write, "    {}", chain.join(" -> "))?;
+    }
+
+    writeln!(write, "\n{bold}Most direct dependents:{Reset}")?;
+    for (node, count) in &statistics.most_required {
+        writeln!(write, "{count:>8} {}", node.name())?;
+    }
+    writeln!(write, "\n{bold}Most transitive dependents:{Reset}")?;
+    for (node, count) in &statistics.most_required_transitively {
+        writeln!(write, "{count:>8} {}", node.name())?;
+    }
+    writeln!(write, "\n{bold}Highest betweenness:{Reset}")?;
+    for (node, centrality) in &statistics.highest_betweenness {
+        writeln!(write, "{centrality:>8.0} {}", node.name())?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct JsonRankedPackage<'a, T> {
+    name: &'a str,
+    value: T,
+}
+
+impl<'a, T: Copy> From<&(PackageNode<'a>, T)> for JsonRankedPackage<'a, T> {
+    fn from((node, value): &(PackageNode<'a>, T)) -> Self {
+        Self {
+            name: node.package().name(),
+            value: *value,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct JsonGraphStatistics<'a> {
+    packages: usize,
+    explicit: usize,
+    dependencies: usize,
+    components: usize,
+    cycles: usize,
+    longest_chain: Vec<&'a str>,
+    most_required: Vec<JsonRankedPackage<'a, usize>>,
+    most_required_transitively: Vec<JsonRankedPackage<'a, usize>>,
+    highest_betweenness: Vec<JsonRankedPackage<'a, f64>>,
+}
+
+/// Print graph statistics as JSON.
+pub fn print_graph_statistics_json<W: Write>(
+    write: &mut W,
+    statistics: &GraphStatistics,
+) -> std::io::Result<()> {
+    let statistics = JsonGraphStatistics {
+        packages: statistics.packages,
+        explicit: statistics.explicit,
+        dependencies: statistics.dependencies,
+        components: statistics.components,
+        cycles: statistics.cycles,
+        longest_chain: statistics
+            .longest_chain
+            .iter()
+            .map(|node| node.package().name())
+            .collect(),
+        most_required: statistics.most_required.iter().map(Into::into).collect(),
+        most_required_transitively: statistics
+            .most_required_transitively
+            .iter()
+            .map(Into::into)
+            .collect(),
+        highest_betweenness: statistics
+            .highest_betweenness
+            .iter()
+            .map(Into::into)
+            .collect(),
+    };
+    serde_json::to_writer_pretty(&mut *write, &statistics)?;
+    writeln!(write)
+}
+
+fn print_package_names<W: Write>(
+    write: &mut W,
+    label: &str,
+    packages: &[PackageNode],
+) -> std::io::Result<()> {
+    if !packages.is_empty() {
+        let names = packages.iter().map(|node| node.name()).collect::<Vec<_>>();
+        writeln!(write, "    {label}: {}", names.join(", "))?;
+    }
+    Ok(())
+}
+
+/// Print foreign packages as text.
+pub fn print_foreign_packages<W: Write>(
+    write: &mut W,
+    packages: &[ForeignPackage],
+    quiet: bool,
+) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    let green = bold.fg_color(Some(AnsiColor::Green.into()));
+    let yellow = Style::new().fg_color(Some(AnsiColor::Yellow.into()));
+    for package in packages {
+        if quiet {
+            writeln!(write, "{}", package.package.name())?;
+            continue;
+        }
+        write!(
+            write,
+            "{bold}{} {green}{}{Reset}",
+            package.package.name(),
+            package.package.version()
+        )?;
+        if package.orphan {
+            write!(write, " {yellow}(orphan){Reset}")?;
+        }
+        writeln!(write)?;
+        print_package_names(write, "Keeps installed", &package.exclusive_dependencies)?;
+        print_package_names(
+            write,
+            "Requires from repositories",
+            &package.repo_dependencies,
+        )?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct JsonForeignPackage<'a> {
+    #[serde(flatten)]
+    package: JsonPackage<'a>,
+    orphan: bool,
+    exclusive_dependencies: Vec<&'a str>,
+    repo_dependencies: Vec<&'a str>,
+}
+
+/// Print foreign packages as JSON.
+pub fn print_foreign_packages_json<W: Write>(
+    write: &mut W,
+    packages: &[ForeignPackage],
+) -> std::io::Result<()> {
+    let packages = packages
+        .iter()
+        .map(|package| JsonForeignPackage {
+            package: JsonPackage::from(package.package),
+            orphan: package.orphan,
+            exclusive_dependencies: package
+                .exclusive_dependencies
+                .iter()
+                .map(|node| node.package().name())
+                .collect(),
+            repo_dependencies: package
+                .repo_dependencies
+                .iter()
+                .map(|node| node.package().name())
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_writer_pretty(&mut *write, &packages)?;
+    writeln!(write)
+}
+
+/// Print stale packages as text.
+pub fn print_stale_packages<W: Write>(
+    write: &mut W,
+    packages: &[StalePackage],
+) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    let green = bold.fg_color(Some(AnsiColor::Green.into()));
+    let yellow = Style::new().fg_color(Some(AnsiColor::Yellow.into()));
+    for package in packages {
+        write!(
+            write,
+            "{bold}{} {green}{}{Reset} ",
+            package.package.name(),
+            package.package.version()
+        )?;
+        if package.replaced_by.is_empty() {
+            write!(write, "dropped")?;
+        } else {
+            write!(write, "replaced by ")?;
+            for (index, replacement) in package.replaced_by.iter().enumerate() {
+                let separator = if index == 0 { "" } else { ", " };
+                write!(
+                    write,
+                    "{separator}{bold}{} {green}{}{Reset}",
+                    replacement.name(),
+                    replacement.version()
+                )?;
+            }
+        }
+        writeln!(write, " {yellow}({}){Reset}", package.position.as_str())?;
+        print_package_names(write, "Required by", &package.required_by)?;
+        print_package_names(write, "Dependents", &package.dependents)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct JsonReplacement<'a> {
+    name: &'a str,
+    version: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonStalePackage<'a> {
+    #[serde(flatten)]
+    package: JsonPackage<'a>,
+    position: &'static str,
+    replaced_by: Vec<JsonReplacement<'a>>,
+    required_by: Vec<&'a str>,
+    dependents: Vec<&'a str>,
+}
+
+/// Print stale packages as JSON.
+pub fn print_stale_packages_json<W: Write>(
+    write: &mut W,
+    packages: &[StalePackage],
+) -> std::io::Result<()> {
+    let packages = packages
+        .iter()
+        .map(|package| JsonStalePackage {
+            package: JsonPackage::from(package.package),
+            position: package.position.as_str(),
+            replaced_by: package
+                .replaced_by
+                .iter()
+                .map(|replacement| JsonReplacement {
+                    name: replacement.name(),
+                    version: replacement.version().as_str(),
+                })
+                .collect(),
+            required_by: package_names(&package.required_by),
+            dependents: package_names(&package.dependents),
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_writer_pretty(&mut *write, &packages)?;
+    writeln!(write)
+}
+
+/// Print the history of a package as text.
+pub fn print_package_history<W: Write>(
+    write: &mut W,
+    history: &PackageHistory,
+) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    let yellow = Style::new().fg_color(Some(AnsiColor::Yellow.into()));
+    let dimmed = Style::new().dimmed();
+    write!(write, "{bold}{}{Reset}", history.name)?;
+    match history.position {
+        Some(position) => writeln!(write, " {yellow}({}){Reset}", position.as_str())?,
+        None => writeln!(write, " {yellow}(not installed){Reset}")?,
+    }
+    for (transaction, event) in &history.events {
+        write!(write, "{} {}", event.timestamp, event.action)?;
+        if let Some(command) = &transaction.command {
+            write!(write, " {dimmed}[{command}]{Reset}")?;
+        }
+        writeln!(write)?;
+    }
+    if let Some(installed_by) = &history.installed_by {
+        let transaction = installed_by.transaction;
+        write!(write, "\n{bold}Installed by{Reset} ")?;
+        match &transaction.command {
+            Some(command) => write!(write, "'{command}'")?,
+            None => write!(write, "unknown command")?,
+        }
+        write!(write, " on {}", transaction.timestamp)?;
+        if !installed_by.dependents.is_empty() {
+            let names = installed_by
+                .dependents
+                .iter()
+                .map(|node| node.name())
+                .collect::<Vec<_>>();
+            write!(write, " as dependency of {}", names.join(", "))?;
+        }
+        writeln!(write)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct JsonPackageEvent<'a> {
+    timestamp: &'a str,
+    action: &'static str,
+    from_version: Option<&'a str>,
+    version: &'a str,
+    command: Option<&'a str>,
+}
+
+impl<'a> From<(&'a Transaction, &'a PackageEvent)> for JsonPackageEvent<'a> {
+    fn from((transaction, event): (&'a Transaction, &'a PackageEvent)) -> Self {
+        let (from_version, version) = match &event.action {
+            PackageAction::Installed(version)
+            | PackageAction::Reinstalled(version)
+            | PackageAction::Removed(version) => (None, version),
+            PackageAction::Upgraded { from, to } | PackageAction::Downgraded { from, to } => {
+                (Some(from.as_str()), to)
+            }
+        };
+        Self {
+            timestamp: &event.timestamp,
+            action: event.action.name(),
+            from_version,
+            version,
+            command: transaction.command.as_deref(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct JsonInstalledBy<'a> {
+    timestamp: &'a str,
+    command: Option<&'a str>,
+    dependents: Vec<&'a str>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonPackageHistory<'a> {
+    name: &'a str,
+    position: Option<&'static str>,
+    events: Vec<JsonPackageEvent<'a>>,
+    installed_by: Option<JsonInstalledBy<'a>>,
+}
+
+/// Print the history of a package as JSON.
+pub fn print_package_history_json<W: Write>(
+    write: &mut W,
+    history: &PackageHistory,
+) -> std::io::Result<()> {
+    let history = JsonPackageHistory {
+        name: history.name,
+        position: history.position.map(GraphPosition::as_str),
+        events: history.events.iter().copied().map(Into::into).collect(),
+        installed_by: history
+            .installed_by
+            .as_ref()
+            .map(|installed_by| JsonInstalledBy {
+                timestamp: &installed_by.transaction.timestamp,
+                command: installed_by.transaction.command.as_deref(),
+                dependents: installed_by
+                    .dependents
+                    .iter()
+                    .map(|node| node.package().name())
+                    .collect(),
+            }),
+    };
+    serde_json::to_writer_pretty(&mut *write, &history)?;
+    writeln!(write)
+}
+
+/// Print results of link checks as text.
+///
+/// Omit packages without any findings.
+pub fn print_link_checks<W: Write>(write: &mut W, checks: &[LinkCheck]) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    let green = bold.fg_color(Some(AnsiColor::Green.into()));
+    let yellow = Style::new().fg_color(Some(AnsiColor::Yellow.into()));
+    let red = Style::new().fg_color(Some(AnsiColor::Red.into()));
+    for check in checks {
+        if check.undeclared.is_empty() && check.unresolved.is_empty() && check.unused.is_empty() {
+            continue;
+        }
+        writeln!(
+            write,
+            "{bold}{} {green}{}{Reset}",
+            check.package.name(),
+            check.package.version()
+        )?;
+        for library in &check.undeclared {
+            let owners = library
+                .owners
+                .iter()
+                .map(|owner| owner.name())
+                .collect::<Vec<_>>();
+            writeln!(
+                write,
+                "    {red}undeclared{Reset} {} ({})",
+                library.soname,
+                owners.join(", ")
+            )?;
+        }
+        for soname in &check.unresolved {
+            writeln!(write, "    {red}unresolved{Reset} {soname}")?;
+        }
+        for dependency in &check.unused {
+            writeln!(write, "    {yellow}unused{Reset} {}", dependency.name())?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct JsonUndeclaredLibrary<'a> {
+    soname: &'a str,
+    owners: Vec<&'a str>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonLinkCheck<'a> {
+    #[serde(flatten)]
+    package: JsonPackage<'a>,
+    undeclared: Vec<JsonUndeclaredLibrary<'a>>,
+    unresolved: &'a [String],
+    unused: Vec<&'a str>,
+}
+
+/// Print results of link checks as JSON.
+pub fn print_link_checks_json<W: Write>(
+    write: &mut W,
+    checks: &[LinkCheck],
+) -> std::io::Result<()> {
+    let checks = checks
+        .iter()
+        .map(|check| JsonLinkCheck {
+            package: JsonPackage::from(check.package),
+            undeclared: check
+                .undeclared
+                .iter()
+                .map(|library| JsonUndeclaredLibrary {
+                    soname: &library.soname,
+                    owners: library.owners.iter().map(|owner| owner.name()).collect(),
+                })
+                .collect(),
+            unresolved: &check.unresolved,
+            unused: check
+                .unused
+                .iter()
+                .map(|node| node.package().name())
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_writer_pretty(&mut *write, &checks)?;
+    writeln!(write)
+}
+
+/// Print batches of packages as numbered list.
+pub fn print_batches<N: Display, W: Write>(
+    write: &mut W,
+    batches: &[Vec<N>],
+) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    let yellow = Style::new().fg_color(Some(AnsiColor::Yellow.into()));
+    for (index, batch) in batches.iter().enumerate() {
+        let names = batch.iter().map(ToString::to_string).collect::<Vec<_>>();
+        write!(write, "{bold}{:>4}.{Reset} {}", index + 1, names.join(" "))?;
+        if batch.len() > 1 {
+            write!(write, " {yellow}(cycle){Reset}")?;
+        }
+        writeln!(write)?;
+    }
+    Ok(())
+}
+
+/// Print batches of packages as JSON.
+pub fn print_batches_json<N: Display, W: Write>(
+    write: &mut W,
+    batches: &[Vec<N>],
+) -> std::io::Result<()> {
+    let batches = batches
+        .iter()
+        .map(|batch| batch.iter().map(ToString::to_string).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    serde_json::to_writer_pretty(&mut *write, &batches)?;
+    writeln!(write)
+}
+
+/// Print alternatives for a package as text.
+pub fn print_alternatives<W: Write>(
+    write: &mut W,
+    alternatives: &[Alternative],
+) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    let green = bold.fg_color(Some(AnsiColor::Green.into()));
+    let yellow = Style::new().fg_color(Some(AnsiColor::Yellow.into()));
+    for alternative in alternatives {
+        let candidate = alternative.candidate;
+        write!(
+            write,
+            "{bold}{} {green}{}{Reset}",
+            candidate.name(),
+            candidate.version()
+        )?;
+        if let Some(db) = candidate.db() {
+            write!(write, " {yellow}({}){Reset}", db.name())?;
+        }
+        if alternative.installed {
+            write!(write, " {yellow}[installed]{Reset}")?;
+        }
+        writeln!(write)?;
+        print_package_names(write, "Satisfies", &alternative.satisfied)?;
+        print_package_names(write, "Does not satisfy", &alternative.unsatisfied)?;
+        print_package_names(write, "Conflicts with", &alternative.conflicts)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct JsonAlternative<'a> {
+    name: &'a str,
+    version: &'a str,
+    repository: Option<&'a str>,
+    installed: bool,
+    satisfies: Vec<&'a str>,
+    does_not_satisfy: Vec<&'a str>,
+    conflicts_with: Vec<&'a str>,
+}
+
+fn package_names<'a>(packages: &[PackageNode<'a>]) -> Vec<&'a str> {
+    packages.iter().map(|node| node.package().name()).collect()
+}
+
+/// Print alternatives for a package as JSON.
+pub fn print_alternatives_json<W: Write>(
+    write: &mut W,
+    alternatives: &[Alternative],
+) -> std::io::Result<()> {
+    let alternatives = alternatives
+        .iter()
+        .map(|alternative| JsonAlternative {
+            name: alternative.candidate.name(),
+            version: alternative.candidate.version().as_str(),
+            repository: alternative.candidate.db().map(alpm::Db::name),
+            installed: alternative.installed,
+            satisfies: package_names(&alternative.satisfied),
+            does_not_satisfy: package_names(&alternative.unsatisfied),
+            conflicts_with: package_names(&alternative.conflicts),
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_writer_pretty(&mut *write, &alternatives)?;
+    writeln!(write)
+}
+
+/// Print a single constraint on a package on one line.
+fn print_constraint<W: Write>(
+    write: &mut W,
+    constraint: &Constraint,
+    indent: &str,
+    note: Option<&str>,
+) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    let green = bold.fg_color(Some(AnsiColor::Green.into()));
+    let cyan = Style::new().fg_color(Some(AnsiColor::Cyan.into()));
+    let yellow = Style::new().fg_color(Some(AnsiColor::Yellow.into()));
+    let dependent = constraint.dependent.package();
+    write!(
+        write,
+        "{indent}{bold}{} {green}{}{Reset} requires {cyan}{}{Reset}",
+        dependent.name(),
+        dependent.version(),
+        constraint.dependency
+    )?;
+    if let Some(provision) = constraint.provision {
+        write!(write, " {yellow}(via {provision}){Reset}")?;
+    }
+    if constraint.edge == DependencyEdge::Optional {
+        write!(write, " {yellow}[optional]{Reset}")?;
+    }
+    if let Some(note) = note {
+        write!(write, " {yellow}({note}){Reset}")?;
+    }
+    writeln!(write)
+}
+
+/// Print constraints on a package as text.
+pub fn print_constraints<W: Write>(
+    write: &mut W,
+    constraints: &[Constraint],
+) -> std::io::Result<()> {
+    for constraint in constraints {
+        print_constraint(write, constraint, "", None)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct JsonPackageConstraint<'a> {
+    dependent: &'a str,
+    version: &'a str,
+    kind: String,
+    #[serde(flatten)]
+    constraint: JsonConstraint<'a>,
+}
+
+impl<'a> From<&Constraint<'a>> for JsonPackageConstraint<'a> {
+    fn from(constraint: &Constraint<'a>) -> Self {
+        let dependent = constraint.dependent.package();
+        Self {
+            dependent: dependent.name(),
+            version: dependent.version().as_str(),
+            kind: constraint.edge.to_string(),
+            constraint: JsonConstraint::from(constraint),
+        }
+    }
+}
+
+/// Print constraints on a package as JSON.
+pub fn print_constraints_json<W: Write>(
+    write: &mut W,
+    constraints: &[Constraint],
+) -> std::io::Result<()> {
+    let constraints = constraints
+        .iter()
+        .map(JsonPackageConstraint::from)
+        .collect::<Vec<_>>();
+    serde_json::to_writer_pretty(&mut *write, &constraints)?;
+    writeln!(write)
+}
+
+/// Print whether versions of a package satisfy its dependents as text.
+///
+/// Print whether the `target` version satisfies all dependents of `package`,
+/// and list all unsatisfied constraints otherwise.  Then list all `cached`
+/// versions which satisfy all dependents.
+pub fn print_version_checks<W: Write>(
+    write: &mut W,
+    package: &Pkg,
+    target: &VersionCheck,
+    cached: &[VersionCheck],
+) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    let green = bold.fg_color(Some(AnsiColor::Green.into()));
+    let yellow = Style::new().fg_color(Some(AnsiColor::Yellow.into()));
+    let red = Style::new().fg_color(Some(AnsiColor::Red.into()));
+    write!(
+        write,
+        "{bold}{} {green}{}{Reset}",
+        package.name(),
+        target.version
+    )?;
+    if target.archives.is_empty() {
+        write!(write, " {yellow}(not cached){Reset}")?;
+    }
+    match target.satisfaction() {
+        Satisfaction::Satisfied => writeln!(write, " satisfies all dependents")?,
+        Satisfaction::Unsatisfied => writeln!(write, " {red}does not satisfy{Reset}")?,
+        Satisfaction::Unknown => writeln!(write, " {yellow}may not satisfy{Reset}")?,
+    }
+    for constraint in &target.unsatisfied {
+        print_constraint(write, constraint, "    ", None)?;
+    }
+    let unknown = Satisfaction::Unknown.to_string();
+    for constraint in &target.unverifiable {
+        print_constraint(write, constraint, "    ", Some(&unknown))?;
+    }
+
+    writeln!(
+        write,
+        "\n{bold}Cached versions which satisfy all dependents:{Reset}"
+    )?;
+    let mut satisfied = cached
+        .iter()
+        .filter(|check| check.satisfaction() != Satisfaction::Unsatisfied)
+        .peekable();
+    if satisfied.peek().is_none() {
+        writeln!(write, "    {yellow}none{Reset}")?;
+    }
+    for check in satisfied {
+        write!(write, "    {green}{}{Reset}", check.version)?;
+        if check.satisfaction() == Satisfaction::Unknown {
+            write!(write, " {yellow}({unknown}){Reset}")?;
+        }
+        writeln!(write)?;
+        for archive in &check.archives {
+            writeln!(write, "        {}", archive.display())?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct JsonVersionCheck<'a> {
+    version: &'a str,
+    archives: Vec<Cow<'a, str>>,
+    satisfaction: &'static str,
+    unsatisfied: Vec<JsonPackageConstraint<'a>>,
+    unverifiable: Vec<JsonPackageConstraint<'a>>,
+}
+
+impl<'a> From<&'a VersionCheck<'a>> for JsonVersionCheck<'a> {
+    fn from(check: &'a VersionCheck<'a>) -> Self {
+        Self {
+            version: &check.version,
+            archives: check
+                .archives
+                .iter()
+                .map(|archive| archive.to_string_lossy())
+                .collect(),
+            satisfaction: check.satisfaction().as_str(),
+            unsatisfied: check
+                .unsatisfied
+                .iter()
+                .map(JsonPackageConstraint::from)
+                .collect(),
+            unverifiable: check
+                .unverifiable
+                .iter()
+                .map(JsonPackageConstraint::from)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct JsonVersionChecks<'a> {
+    package: &'a str,
+    installed_version: &'a str,
+    target: JsonVersionCheck<'a>,
+    cached: Vec<JsonVersionCheck<'a>>,
+}
+
+/// Print whether versions of a package satisfy its dependents as JSON.
+pub fn print_version_checks_json<W: Write>(
+    write: &mut W,
+    package: &Pkg,
+    target: &VersionCheck,
+    cached: &[VersionCheck],
+) -> std::io::Result<()> {
+    let checks = JsonVersionChecks {
+        package: package.name(),
+        installed_version: package.version().as_str(),
+        target: JsonVersionCheck::from(target),
+        cached: cached.iter().map(JsonVersionCheck::from).collect(),
+    };
+    serde_json::to_writer_pretty(&mut *write, &checks)?;
+    writeln!(write)
+}
+
+/// Print findings of security advisories as text.
+pub fn print_findings<W: Write>(write: &mut W, findings: &[Finding]) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    let green = bold.fg_color(Some(AnsiColor::Green.into()));
+    let red = Style::new().fg_color(Some(AnsiColor::Red.into()));
+    let yellow = Style::new().fg_color(Some(AnsiColor::Yellow.into()));
+    for finding in findings {
+        let advisory = finding.advisory;
+        write!(
+            write,
+            "{bold}{} {green}{}{Reset} {red}{} {}{Reset} {}",
+            finding.package.name(),
+            finding.package.version(),
+            advisory.name,
+            advisory.severity,
+            advisory.kind
+        )?;
+        match &advisory.fixed {
+            Some(fixed) => writeln!(write, " {yellow}(fixed in {fixed}){Reset}")?,
+            None => writeln!(write, " {yellow}(not fixed){Reset}")?,
+        }
+        if !advisory.issues.is_empty() {
+            writeln!(write, "    Issues: {}", advisory.issues.join(", "))?;
+        }
+        print_package_names(write, "Dependents", &finding.dependents)?;
+        print_package_names(write, "Explicit roots", &finding.explicit_roots)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct JsonFinding<'a> {
+    #[serde(flatten)]
+    package: JsonPackage<'a>,
+    advisory: &'a str,
+    status: &'a str,
+    severity: &'a str,
+    #[serde(rename = "type")]
+    kind: &'a str,
+    affected: &'a str,
+    fixed: Option<&'a str>,
+    issues: &'a [String],
+    advisories: &'a [String],
+    dependents: Vec<&'a str>,
+    explicit_roots: Vec<&'a str>,
+}
+
+/// Print findings of security advisories as JSON.
+pub fn print_findings_json<W: Write>(write: &mut W, findings: &[Finding]) -> std::io::Result<()> {
+    let findings = findings
+        .iter()
+        .map(|finding| JsonFinding {
+            package: JsonPackage::from(finding.package),
+            advisory: &finding.advisory.name,
+            status: &finding.advisory.status,
+            severity: &finding.advisory.severity,
+            kind: &finding.advisory.kind,
+            affected: &finding.advisory.affected,
+            fixed: finding.advisory.fixed.as_deref(),
+            issues: &finding.advisory.issues,
+            advisories: &finding.advisory.advisories,
+            dependents: package_names(&finding.dependents),
+            explicit_roots: package_names(&finding.explicit_roots),
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_writer_pretty(&mut *write, &findings)?;
+    writeln!(write)
+}
+
+/// Print a single conflict, and the declared conflict if the conflicting package
+/// only satisfies it through a provision.
+fn print_conflict<W: Write>(write: &mut W, conflict: &Conflict) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    let green = bold.fg_color(Some(AnsiColor::Green.into()));
+    let yellow = Style::new().fg_color(Some(AnsiColor::Yellow.into()));
+    write!(
+        write,
+        "{bold}{} {green}{}{Reset} conflicts with {bold}{} {green}{}{Reset}",
+        conflict.package.name(),
+        conflict.package.version(),
+        conflict.conflicting.name(),
+        conflict.conflicting.version(),
+    )?;
+    if conflict.conflict.name() == conflict.conflicting.name() {
+        writeln!(write)
+    } else {
+        writeln!(write, " {yellow}(via {}){Reset}", conflict.conflict)
+    }
+}
+
+/// Print conflicts as text.
+pub fn print_conflicts<W: Write>(write: &mut W, conflicts: &[Conflict]) -> std::io::Result<()> {
+    for conflict in conflicts {
+        print_conflict(write, conflict)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct JsonConflict<'a> {
+    package: &'a str,
+    conflicting: &'a str,
+    conflict: String,
+}
+
+impl<'a> From<&Conflict<'a>> for JsonConflict<'a> {
+    fn from(conflict: &Conflict<'a>) -> Self {
+        Self {
+            package: conflict.package.package().name(),
+            conflicting: conflict.conflicting.package().name(),
+            conflict: conflict.conflict.to_string(),
+        }
+    }
+}
+
+/// Print conflicts as JSON.
+pub fn print_conflicts_json<W: Write>(
+    write: &mut W,
+    conflicts: &[Conflict],
+) -> std::io::Result<()> {
+    let conflicts = conflicts.iter().map(JsonConflict::from).collect::<Vec<_>>();
+    serde_json::to_writer_pretty(&mut *write, &conflicts)?;
+    writeln!(write)
+}
+
+/// Print conflicts as dot.
+///
+/// Print all packages involved in `conflicts` and the dependency edges in
+/// `graph` between these packages, and an undirected bold red edge from the
+/// declaring to the conflicting package of every conflict.
+pub fn print_conflict_graph<'a, G, W: Write>(
+    write: &mut W,
+    graph: G,
+    conflicts: &[Conflict<'a>],
+) -> std::io::Result<()>
+where
+    G: GraphBase<NodeId = PackageNode<'a>> + Data<EdgeWeight = DependencyEdge> + IntoEdgeReferences,
+{
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let mut nodes = std::collections::HashSet::new();
+    writeln!(write, "digraph {{")?;
+    writeln!(write, "    rankdir=\"TB\"")?;
+    for node in conflicts
+        .iter()
+        .flat_map(|conflict| [conflict.package, conflict.conflicting])
+    {
+        if nodes.insert(node) {
+            writeln!(
+                write,
+                "    \"{}\" [ {} ]",
+                node.name(),
+                package_label(&node, PrintOneLine::WithVersion)
+            )?;
+        }
+    }
+    for edge in graph.edge_references() {
+        if nodes.contains(&edge.source()) && nodes.contains(&edge.target()) {
+            writeln!(
+                write,
+                "    \"{}\" -> \"{}\" [ {} ]",
+                edge.source().name(),
+                edge.target().name(),
+                package_edge_attributes(edge.source(), edge.target(), *edge.weight(), false)
+            )?;
+        }
+    }
+    for conflict in conflicts {
+        writeln!(
+            write,
+            "    \"{}\" -> \"{}\" [ style = bold, color = red, dir = none ]",
+            conflict.package.name(),
+            conflict.conflicting.name()
+        )?;
+    }
+    writeln!(write, "}}")
+}
+
+/// Print a package with its version, and the repository it comes from, if any.
+fn print_repository_package<W: Write>(
+    write: &mut W,
+    package: &alpm::Package,
+) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    let green = bold.fg_color(Some(AnsiColor::Green.into()));
+    let yellow = Style::new().fg_color(Some(AnsiColor::Yellow.into()));
+    write!(
+        write,
+        "{bold}{} {green}{}{Reset}",
+        package.name(),
+        package.version()
+    )?;
+    match package.db() {
+        Some(db) if db.name() != "local" => writeln!(write, " {yellow}({}){Reset}", db.name()),
+        _ => writeln!(write),
+    }
+}
+
+/// Print an upgrade preview as text.
+///
+/// Print all upgrades and replacements, new packages, `new_orphans` and new
+/// conflicts, and omit empty sections.
+pub fn print_upgrade_preview<W: Write>(
+    write: &mut W,
+    preview: &UpgradePreview,
+    new_orphans: &[PackageNode],
+) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    let green = bold.fg_color(Some(AnsiColor::Green.into()));
+    let yellow = Style::new().fg_color(Some(AnsiColor::Yellow.into()));
+    if preview.upgrades.is_empty() && preview.replacements.is_empty() {
+        return writeln!(write, "Nothing to upgrade");
+    }
+    let mut sections = 0;
+    let mut header = |write: &mut W, title: &str| {
+        let separator = if sections == 0 { "" } else { "\n" };
+        sections += 1;
+        writeln!(write, "{separator}{bold}{title}:{Reset}")
+    };
+    if !preview.upgrades.is_empty() {
+        header(write, "Upgrades")?;
+        for upgrade in &preview.upgrades {
+            write!(
+                write,
+                "    {bold}{} {green}{}{Reset} -> {green}{}{Reset}",
+                upgrade.installed.name(),
+                upgrade.installed.version(),
+                upgrade.upgrade.version()
+            )?;
+            match upgrade.upgrade.db() {
+                Some(db) => writeln!(write, " {yellow}({}){Reset}", db.name())?,
+                None => writeln!(write)?,
+            }
+        }
+    }
+    if !preview.replacements.is_empty() {
+        header(write, "Replacements")?;
+        for replacement in &preview.replacements {
+            write!(
+                write,
+                "    {bold}{} {green}{}{Reset} replaced by ",
+                replacement.installed.name(),
+                replacement.installed.version(),
+            )?;
+            print_repository_package(write, replacement.replacement)?;
+        }
+    }
+    if !preview.new_packages.is_empty() {
+        header(write, "New packages")?;
+        for package in &preview.new_packages {
+            write!(write, "    ")?;
+            print_repository_package(write, package)?;
+        }
+    }
+    if !new_orphans.is_empty() {
+        header(write, "New orphans")?;
+        for node in new_orphans {
+            write!(write, "    ")?;
+            print_repository_package(write, node.package())?;
+        }
+    }
+    if !preview.new_conflicts.is_empty() {
+        header(write, "New conflicts")?;
+        for conflict in &preview.new_conflicts {
+            write!(write, "    ")?;
+            print_conflict(write, conflict)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct JsonRepositoryPackage<'a> {
+    name: &'a str,
+    version: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    repository: Option<&'a str>,
+}
+
+impl<'a> From<&'a alpm::Package> for JsonRepositoryPackage<'a> {
+    fn from(package: &'a alpm::Package) -> Self {
+        Self {
+            name: package.name(),
+            version: package.version().as_str(),
+            repository: package
+                .db()
+                .map(alpm::Db::name)
+                .filter(|name| *name != "local"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct JsonUpgrade<'a> {
+    name: &'a str,
+    installed_version: &'a str,
+    version: &'a str,
+    repository: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonUpgradeReplacement<'a> {
+    name: &'a str,
+    installed_version: &'a str,
+    replaced_by: JsonRepositoryPackage<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonUpgradePreview<'a> {
+    upgrades: Vec<JsonUpgrade<'a>>,
+    replacements: Vec<JsonUpgradeReplacement<'a>>,
+    new_packages: Vec<JsonRepositoryPackage<'a>>,
+    new_orphans: Vec<JsonRepositoryPackage<'a>>,
+    new_conflicts: Vec<JsonConflict<'a>>,
+}
+
+/// Print an upgrade preview as JSON.
+pub fn print_upgrade_preview_json<W: Write>(
+    write: &mut W,
+    preview: &UpgradePreview,
+    new_orphans: &[PackageNode],
+) -> std::io::Result<()> {
+    let preview = JsonUpgradePreview {
+        upgrades: preview
+            .upgrades
+            .iter()
+            .map(|upgrade| JsonUpgrade {
+                name: upgrade.installed.name(),
+                installed_version: upgrade.installed.version().as_str(),
+                version: upgrade.upgrade.version().as_str(),
+                repository: upgrade.upgrade.db().map(alpm::Db::name),
+            })
+            .collect(),
+        replacements: preview
+            .replacements
+            .iter()
+            .map(|replacement| JsonUpgradeReplacement {
+                name: replacement.installed.name(),
+                installed_version: replacement.installed.version().as_str(),
+                replaced_by: JsonRepositoryPackage::from(replacement.replacement),
+            })
+            .collect(),
+        new_packages: preview
+            .new_packages
+            .iter()
+            .map(|package| JsonRepositoryPackage::from(*package))
+            .collect(),
+        new_orphans: new_orphans
+            .iter()
+            .map(|node| JsonRepositoryPackage::from(node.package()))
+            .collect(),
+        new_conflicts: preview
+            .new_conflicts
+            .iter()
+            .map(JsonConflict::from)
+            .collect(),
+    };
+    serde_json::to_writer_pretty(&mut *write, &preview)?;
+    writeln!(write)
+}
+
+/// Print package groups as text.
+pub fn print_groups<W: Write>(write: &mut W, groups: &[PackageGroup]) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    let yellow = Style::new().fg_color(Some(AnsiColor::Yellow.into()));
+    for group in groups {
+        writeln!(
+            write,
+            "{bold}{}{Reset} {yellow}({}){Reset}",
+            group.name,
+            group.status()
+        )?;
+        if group.status() == GroupStatus::PartiallyInstalled {
+            print_package_names(write, "Missing", &group.missing)?;
+        }
+        print_package_names(write, "Orphans", &group.orphans)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct JsonGroup<'a> {
+    name: &'a str,
+    status: String,
+    installed: Vec<&'a str>,
+    missing: Vec<&'a str>,
+    orphans: Vec<&'a str>,
+}
+
+/// Print package groups as JSON.
+pub fn print_groups_json<W: Write>(write: &mut W, groups: &[PackageGroup]) -> std::io::Result<()> {
+    let groups = groups
+        .iter()
+        .map(|group| JsonGroup {
+            name: group.name,
+            status: group.status().to_string(),
+            installed: package_names(&group.installed),
+            missing: package_names(&group.missing),
+            orphans: package_names(&group.orphans),
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_writer_pretty(&mut *write, &groups)?;
+    writeln!(write)
+}
+
+/// Print installed members of package groups as dot.
+///
+/// Render every group with installed members as a cluster, along with all
+/// dependencies in `graph` between installed members.  Orphans have a dashed
+/// outline.  A package which belongs to multiple groups appears in the first
+/// group only.
+pub fn print_group_graph<'a, G, W: Write>(
+    write: &mut W,
+    graph: G,
+    groups: &[PackageGroup<'a>],
+) -> std::io::Result<()>
+where
+    G: GraphBase<NodeId = PackageNode<'a>> + Data<EdgeWeight = DependencyEdge> + IntoEdgeReferences,
+{
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let mut nodes = std::collections::HashSet::new();
+    writeln!(write, "digraph {{")?;
+    writeln!(write, "    rankdir=\"TB\"")?;
+    for group in groups.iter().filter(|group| !group.installed.is_empty()) {
+        writeln!(write, "    subgraph \"cluster_{}\" {{", group.name)?;
+        writeln!(
+            write,
+            "        label = <<FONT FACE=\"sans-serif\"><B>{}</B></FONT>>",
+            group.name
+        )?;
+        for node in &group.installed {
+            if nodes.insert(*node) {
+                let style = if group.orphans.contains(node) {
+                    ", style = dashed"
+                } else {
+                    ""
+                };
+                writeln!(
+                    write,
+                    "        \"{}\" [ {}{style} ]",
+                    node.name(),
+                    package_label(node, PrintOneLine::WithVersion)
+                )?;
+            }
+        }
+        writeln!(write, "    }}")?;
+    }
+    for edge in graph.edge_references() {
+        if nodes.contains(&edge.source()) && nodes.contains(&edge.target()) {
+            writeln!(
+                write,
+                "    \"{}\" -> \"{}\" [ {} ]",
+                edge.source().name(),
+                edge.target().name(),
+                package_edge_attributes(edge.source(), edge.target(), *edge.weight(), false)
+            )?;
+        }
+    }
+    writeln!(write, "}}")
+}
+
+/// Print pacman commands for a reconciliation.
+///
+/// Print a comment for packages which are neither installed nor available in
+/// any sync database.
+pub fn print_reconciliation<W: Write>(
+    write: &mut W,
+    reconciliation: &Reconciliation,
+) -> std::io::Result<()> {
+    if !reconciliation.unavailable.is_empty() {
+        writeln!(
+            write,
+            "# Not available in any sync database: {}",
+            reconciliation.unavailable.join(" ")
+        )?;
+    }
+    if !reconciliation.install.is_empty() {
+        let names = reconciliation
+            .install
+            .iter()
+            .map(|package| package.name())
+            .collect::<Vec<_>>();
+        writeln!(write, "pacman -S --needed {}", names.join(" "))?;
+    }
+    let commands = [
+        ("pacman -D --asexplicit", &reconciliation.mark_explicit),
+        ("pacman -D --asdeps", &reconciliation.mark_dependency),
+        ("pacman -Rn", &reconciliation.remove),
+    ];
+    for (command, packages) in commands {
+        if !packages.is_empty() {
+            writeln!(write, "{command} {}", package_names(packages).join(" "))?;
+        }
+    }
+    Ok(())
+}
+
+/// Group file problems into missing, modified, and files with changed permissions.
+fn classify_problems(
+    problems: &[FileProblem],
+) -> (Vec<&FileProblem>, Vec<&FileProblem>, Vec<&FileProblem>) {
+    let mut missing = Vec::new();
+    let mut modified = Vec::new();
+    let mut permissions = Vec::new();
+    for problem in problems {
+        match &problem.state {
+            FileState::Missing => missing.push(problem),
+            FileState::Changed(mismatches) if mismatches.iter().all(|m| m.is_permissions()) => {
+                permissions.push(problem);
+            }
+            FileState::Changed(_) => modified.push(problem),
+        }
+    }
+    (missing, modified, permissions)
+}
+
+fn format_mismatches(problem: &FileProblem) -> String {
+    match &problem.state {
+        FileState::Missing => String::new(),
+        FileState::Changed(mismatches) => mismatches
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+/// Print verification results as text.
+///
+/// Only print packages with problems.
+pub fn print_verifications<W: Write>(
+    write: &mut W,
+    verifications: &[PackageVerification],
+) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    let red = Style::new().fg_color(Some(AnsiColor::Red.into()));
+    let yellow = Style::new().fg_color(Some(AnsiColor::Yellow.into()));
+    for verification in verifications {
+        let problems = match &verification.problems {
+            Ok(problems) if problems.is_empty() => continue,
+            Ok(problems) => problems,
+            Err(error) => {
+                writeln!(
+                    write,
+                    "{bold}{}{Reset} {red}failed to read mtree: {error}{Reset}",
+                    verification.name
+                )?;
+                continue;
+            }
+        };
+        writeln!(write, "{bold}{}{Reset}", verification.name)?;
+        let (missing, modified, permissions) = classify_problems(problems);
+        for problem in missing {
+            writeln!(write, "    {red}missing{Reset} /{}", problem.path)?;
+        }
+        for (label, problems) in [("modified", modified), ("permissions", permissions)] {
+            for problem in problems {
+                writeln!(
+                    write,
+                    "    {yellow}{label}{Reset} /{} ({})",
+                    problem.path,
+                    format_mismatches(problem)
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct JsonChangedFile<'a> {
+    path: &'a str,
+    mismatches: Vec<String>,
+}
+
+impl<'a> From<&'a FileProblem> for JsonChangedFile<'a> {
+    fn from(problem: &'a FileProblem) -> Self {
+        let mismatches = match &problem.state {
+            FileState::Missing => Vec::new(),
+            FileState::Changed(mismatches) => mismatches.iter().map(ToString::to_string).collect(),
+        };
+        Self {
+            path: &problem.path,
+            mismatches,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct JsonVerification<'a> {
+    name: &'a str,
+    error: Option<String>,
+    missing: Vec<&'a str>,
+    modified: Vec<JsonChangedFile<'a>>,
+    permissions: Vec<JsonChangedFile<'a>>,
+}
+
+/// Print verification results as JSON.
+///
+/// Only print packages with problems.
+pub fn print_verifications_json<W: Write>(
+    write: &mut W,
+    verifications: &[PackageVerification],
+) -> std::io::Result<()> {
+    let verifications = verifications
+        .iter()
+        .filter_map(|verification| match &verification.problems {
+            Ok(problems) if problems.is_empty() => None,
+            Ok(problems) => {
+                let (missing, modified, permissions) = classify_problems(problems);
+                Some(JsonVerification {
+                    name: &verification.name,
+                    error: None,
+                    missing: missing
+                        .iter()
+                        .map(|problem| problem.path.as_str())
+                        .collect(),
+                    modified: modified.into_iter().map(JsonChangedFile::from).collect(),
+                    permissions: permissions.into_iter().map(JsonChangedFile::from).collect(),
+                })
+            }
+            Err(error) => Some(JsonVerification {
+                name: &verification.name,
+                error: Some(error.to_string()),
+                missing: Vec::new(),
+                modified: Vec::new(),
+                permissions: Vec::new(),
+            }),
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_writer_pretty(&mut *write, &verifications)?;
+    writeln!(write)
+}
+
+/// Print lost files as text.
+///
+/// Append a slash to directories.
+pub fn print_lost_files<W: Write>(write: &mut W, files: &[LostFile]) -> std::io::Result<()> {
+    for file in files {
+        let suffix = if file.is_directory { "/" } else { "" };
+        writeln!(write, "{}{suffix}", file.path)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct JsonLostFile<'a> {
+    path: &'a str,
+    directory: bool,
+}
+
+/// Print lost files as JSON.
+pub fn print_lost_files_json<W: Write>(write: &mut W, files: &[LostFile]) -> std::io::Result<()> {
+    let files = files
+        .iter()
+        .map(|file| JsonLostFile {
+            path: &file.path,
+            directory: file.is_directory,
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_writer_pretty(&mut *write, &files)?;
+    writeln!(write)
+}
+
+/// Print a saved file as text.
+fn print_saved_file<W: Write>(write: &mut W, file: &SavedFile) -> std::io::Result<()> {
+    let style = match file.live {
+        LiveFileState::Unmodified => Style::new().fg_color(Some(AnsiColor::Green.into())),
+        _ => Style::new().fg_color(Some(AnsiColor::Yellow.into())),
+    };
+    writeln!(
+        write,
+        "    {} ({style}live file {}{Reset})",
+        file.saved_path(),
+        file.live
+    )
+}
+
+/// Print saved files of packages as text.
+///
+/// Print `unowned` saved files, which belong to no backup file of any package,
+/// in a separate section at the end.
+pub fn print_saved_files<W: Write>(
+    write: &mut W,
+    packages: &[PackageSavedFiles],
+    unowned: &[SavedFile],
+) -> std::io::Result<()> {
+    for saved in packages {
+        print_package_one_line(write, saved.package, PrintOneLine::WithVersion)?;
+        for file in &saved.files {
+            print_saved_file(write, file)?;
+        }
+    }
+    if !unowned.is_empty() {
+        let bold = Style::new().bold();
+        let separator = if packages.is_empty() { "" } else { "\n" };
+        writeln!(write, "{separator}{bold}Not owned by any package:{Reset}")?;
+        for file in unowned {
+            print_saved_file(write, file)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct JsonSavedFile<'a> {
+    path: &'a str,
+    saved_path: String,
+    kind: String,
+    live: String,
+}
+
+impl<'a> From<&'a SavedFile> for JsonSavedFile<'a> {
+    fn from(file: &'a SavedFile) -> Self {
+        Self {
+            path: &file.path,
+            saved_path: file.saved_path(),
+            kind: file.kind.to_string(),
+            live: file.live.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct JsonPackageSavedFiles<'a> {
+    name: &'a str,
+    version: &'a str,
+    files: Vec<JsonSavedFile<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonSavedFiles<'a> {
+    packages: Vec<JsonPackageSavedFiles<'a>>,
+    unowned: Vec<JsonSavedFile<'a>>,
+}
+
+/// Print saved files of packages, and `unowned` saved files, as JSON.
+pub fn print_saved_files_json<W: Write>(
+    write: &mut W,
+    packages: &[PackageSavedFiles],
+    unowned: &[SavedFile],
+) -> std::io::Result<()> {
+    let saved_files = JsonSavedFiles {
+        packages: packages
+            .iter()
+            .map(|saved| JsonPackageSavedFiles {
+                name: saved.package.name(),
+                version: saved.package.version().as_str(),
+                files: saved.files.iter().map(JsonSavedFile::from).collect(),
+            })
+            .collect(),
+        unowned: unowned.iter().map(JsonSavedFile::from).collect(),
+    };
+    serde_json::to_writer_pretty(&mut *write, &saved_files)?;
+    writeln!(write)
+}
+
+/// Print a unified diff with colours.
+pub fn print_diff<W: Write>(write: &mut W, diff: &str) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    let red = Style::new().fg_color(Some(AnsiColor::Red.into()));
+    let green = Style::new().fg_color(Some(AnsiColor::Green.into()));
+    let cyan = Style::new().fg_color(Some(AnsiColor::Cyan.into()));
+    for line in diff.lines() {
+        let style = if line.starts_with("---") || line.starts_with("+++") {
+            bold
+        } else if line.starts_with("@@") {
+            cyan
+        } else if line.starts_with('-') {
+            red
+        } else if line.starts_with('+') {
+            green
+        } else {
+            Style::new()
+        };
+        writeln!(write, "{style}{line}{Reset}")?;
+    }
+    Ok(())
+}
+
+/// Print cached packages as text.
+///
+/// Mark archives which `policy` removes, and print the total size of all
+/// archives and of removable archives at the end.
+pub fn print_cached_packages<W: Write>(
+    write: &mut W,
+    packages: &[CachedPackage],
+    policy: &RetentionPolicy,
+) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    let green = Style::new().fg_color(Some(AnsiColor::Green.into()));
+    let yellow = Style::new().fg_color(Some(AnsiColor::Yellow.into()));
+    let red = Style::new().fg_color(Some(AnsiColor::Red.into()));
+    let mut total_size = 0;
+    let mut removable_size = 0;
+    for package in packages {
+        let status_style = match package.status {
+            CacheStatus::Installed => green,
+            CacheStatus::Orphan => yellow,
+            CacheStatus::Removed => red,
+        };
+        let installed = package
+            .installed_version
+            .as_deref()
+            .map(|version| format!(" {version}"))
+            .unwrap_or_default();
+        writeln!(
+            write,
+            "{bold}{}{Reset} ({status_style}{}{installed}{Reset}), {}",
+            package.name,
+            package.status,
+            format_size(package.size())
+        )?;
+        let removable = policy.removable(package);
+        for archive in &package.archives {
+            let remove = removable.contains(&archive);
+            let annotation = if remove {
+                format!(" {red}remove{Reset}")
+            } else {
+                String::new()
+            };
+            writeln!(
+                write,
+                "    {} {} ({}){annotation}",
+                archive.package.version,
+                archive.path.display(),
+                format_size(archive.size)
+            )?;
+            total_size += archive.size;
+            if remove {
+                removable_size += archive.size;
+            }
+        }
+    }
+    writeln!(
+        write,
+        "\n{bold}Total cache size:{Reset} {}\n{bold}Removable:{Reset} {}",
+        format_size(total_size),
+        format_size(removable_size)
+    )
+}
+
+#[derive(Debug, Serialize)]
+struct JsonCachedArchive<'a> {
+    path: Cow<'a, str>,
+    signature: Option<Cow<'a, str>>,
+    version: &'a str,
+    arch: &'a str,
+    size: i64,
+    remove: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonCachedPackage<'a> {
+    name: &'a str,
+    status: String,
+    installed_version: Option<&'a str>,
+    size: i64,
+    archives: Vec<JsonCachedArchive<'a>>,
+}
+
+/// Print cached packages as JSON.
+pub fn print_cached_packages_json<W: Write>(
+    write: &mut W,
+    packages: &[CachedPackage],
+    policy: &RetentionPolicy,
+) -> std::io::Result<()> {
+    let packages = packages
+        .iter()
+        .map(|package| {
+            let removable = policy.removable(package);
+            JsonCachedPackage {
+                name: &package.name,
+                status: package.status.to_string(),
+                installed_version: package.installed_version.as_deref(),
+                size: package.size(),
+                archives: package
+                    .archives
+                    .iter()
+                    .map(|archive| JsonCachedArchive {
+                        path: archive.path.to_string_lossy(),
+                        signature: archive.signature.as_deref().map(Path::to_string_lossy),
+                        version: &archive.package.version,
+                        arch: &archive.package.arch,
+                        size: archive.size,
+                        remove: removable.contains(&archive),
+                    })
+                    .collect(),
+            }
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_writer_pretty(&mut *write, &packages)?;
+    writeln!(write)
+}
+
+/// Print a graph of source packages as dot.
+pub fn print_source_graph<'a, G, W: Write>(write: &mut W, graph: G) -> std::io::Result<()>
+where
+    G: GraphProp
+        + Data<NodeWeight = &'a str, EdgeWeight = DependencyEdge>
+        + IntoEdgeReferences
+        + IntoNodeReferences
+        + NodeIndexable,
+{
+    let get_node_attributes = |_graph, node: G::NodeRef| {
+        format!(
+            "label = <<FONT FACE=\"sans-serif\">{}</FONT>>",
+            escape_html(node.weight())
+        )
+    };
+    let dot = Dot::with_attr_getters(
+        graph,
+        &[
+            Config::EdgeNoLabel,
+            Config::NodeNoLabel,
+            Config::RankDir(RankDir::TB),
+        ],
+        &|_graph, edge| edge_style(*edge.weight()),
+        &get_node_attributes,
+    );
+    writeln!(write, "{dot}")
+}
+
+/// Print source packages in a graph with their dependencies as text.
+///
+/// Only print source packages which are in `graph`.
+pub fn print_source_packages<'a, G, W: Write>(
+    write: &mut W,
+    sources: &'a [SourcePackage],
+    graph: G,
+) -> std::io::Result<()>
+where
+    G: IntoEdges
+        + IntoNodeIdentifiers
+        + Data<EdgeWeight = DependencyEdge>
+        + GraphBase<NodeId = &'a str>,
+{
+    let bold = Style::new().bold();
+    let green = bold.fg_color(Some(AnsiColor::Green.into()));
+    let included = graph.node_identifiers().collect::<Vec<_>>();
+    for source in sources {
+        if !included.contains(&source.base.as_str()) {
+            continue;
+        }
+        writeln!(
+            write,
+            "{bold}{} {green}{}{Reset}",
+            source.base, source.version
+        )?;
+        for (label, kind) in [
+            ("Depends", DependencyEdge::Required),
+            ("Make", DependencyEdge::Make),
+            ("Check", DependencyEdge::Check),
+            ("Optional", DependencyEdge::Optional),
+        ] {
+            let mut bases = graph
+                .edges(source.base.as_str())
+                .filter(|edge| *edge.weight() == kind)
+                .map(|edge| edge.target())
+                .collect::<Vec<_>>();
+            if !bases.is_empty() {
+                bases.sort_unstable();
+                writeln!(write, "    {label}: {}", bases.join(", "))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct JsonSourcePackage<'a> {
+    base: &'a str,
+    version: &'a str,
+    path: Cow<'a, str>,
+    packages: Vec<&'a str>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonSourceGraph<'a> {
+    sources: Vec<JsonSourcePackage<'a>>,
+    dependencies: Vec<JsonEdge<'a>>,
+}
+
+/// Print a graph of source packages as JSON.
+///
+/// Print an object with all `sources` in the graph, sorted by package base,
+/// and all `dependencies` between these source packages.
+pub fn print_source_graph_json<'a, G, W: Write>(
+    write: &mut W,
+    sources: &'a [SourcePackage],
+    graph: G,
+) -> std::io::Result<()>
+where
+    G: IntoEdgeReferences
+        + IntoNodeIdentifiers
+        + Data<EdgeWeight = DependencyEdge>
+        + GraphBase<NodeId = &'a str>,
+{
+    let included = graph.node_identifiers().collect::<Vec<_>>();
+    let graph = JsonSourceGraph {
+        sources: sources
+            .iter()
+            .filter(|source| included.contains(&source.base.as_str()))
+            .map(|source| JsonSourcePackage {
+                base: &source.base,
+                version: &source.version,
+                path: source.path.to_string_lossy(),
+                packages: source
+                    .packages
+                    .iter()
+                    .map(|package| package.name.as_str())
+                    .collect(),
+            })
+            .collect(),
+        dependencies: graph
+            .edge_references()
+            .map(|edge| JsonEdge {
+                from: Cow::Borrowed(edge.source()),
+                to: Cow::Borrowed(edge.target()),
+                kind: edge.weight().to_string(),
+                constraints: Vec::new(),
+            })
+            .collect(),
+    };
+    serde_json::to_writer_pretty(&mut *write, &graph)?;
+    writeln!(write)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_size_unit_boundaries() {
+        assert_eq!(format_size(0), "0.00 B");
+        assert_eq!(format_size(1023), "1023.00 B");
+        assert_eq!(format_size(1024), "1.00 KiB");
+        assert_eq!(format_size(1536), "1.50 KiB");
+        assert_eq!(format_size(1024 * 1024 - 1), "1023.99 KiB");
+        assert_eq!(format_size(1024 * 1024), "1.00 MiB");
+        assert_eq!(format_size(1 << 30), "1.00 GiB");
+        assert_eq!(format_size(1 << 40), "1.00 TiB");
+        assert_eq!(format_size(1 << 50), "1.00 PiB");
+        assert_eq!(format_size(1 << 60), "1024.00 PiB");
+    }
+
+    #[test]
+    fn format_size_negative() {
+        assert_eq!(format_size(-1), "-1.00 B");
+        assert_eq!(format_size(-1536), "-1.50 KiB");
+        assert_eq!(format_size(-(1 << 30)), "-1.00 GiB");
+    }
+
+    #[test]
+    fn format_size_extremes() {
+        assert_eq!(format_size(i64::MAX), "8191.99 PiB");
+        assert_eq!(format_size(i64::MIN), "-8192.00 PiB");
+    }
+}
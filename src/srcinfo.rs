@@ -0,0 +1,439 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL-1.2 OR GPL-3.0
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Build dependency graphs of source packages from `.SRCINFO` files.
+//!
+//! Local databases do not record build dependencies, so this module builds
+//! graphs from `.SRCINFO` files instead.  Nodes of these graphs are package
+//! bases, i.e. the unit which makepkg builds, and edges include build-time
+//! dependencies.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::BufRead,
+    path::{Path, PathBuf},
+};
+
+use petgraph::{
+    algo::tarjan_scc,
+    prelude::DiGraphMap,
+    visit::{Bfs, IntoNeighbors, IntoNodeIdentifiers, NodeIndexable, Visitable, Walker},
+};
+use tracing::{debug, warn};
+
+use crate::graph::DependencyEdge;
+
+/// A package built from a source package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitPackage {
+    /// The name of the package.
+    pub name: String,
+    /// Runtime dependencies.
+    pub depends: Vec<String>,
+    /// Optional runtime dependencies, with descriptions.
+    pub optdepends: Vec<String>,
+    /// Provisions.
+    pub provides: Vec<String>,
+    /// Conflicts.
+    pub conflicts: Vec<String>,
+}
+
+/// A source package, as described by a `.SRCINFO` file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourcePackage {
+    /// The name of the package base.
+    pub base: String,
+    /// The full version, including epoch and release.
+    pub version: String,
+    /// The path of the `.SRCINFO` file.
+    pub path: PathBuf,
+    /// Dependencies required to build the package.
+    pub makedepends: Vec<String>,
+    /// Dependencies required to run the tests of the package.
+    pub checkdepends: Vec<String>,
+    /// All packages built from this source package.
+    pub packages: Vec<SplitPackage>,
+}
+
+/// The values of a section of a `.SRCINFO` file.
+type Section = HashMap<String, Vec<String>>;
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Get the name of a dependency, provision or conflict, without version and description.
+fn dependency_name(dependency: &str) -> &str {
+    dependency
+        .split(['<', '>', '=', ':'])
+        .next()
+        .unwrap_or(dependency)
+        .trim()
+}
+
+/// Read a `.SRCINFO` file.
+///
+/// Let split packages inherit fields from the package base unless they
+/// override them, and merge architecture specific fields for `arch`, e.g.
+/// `depends_x86_64`, into the generic fields.  Ignore fields for other
+/// architectures.
+///
+/// # Errors
+///
+/// Return an error if reading fails, or if the file has no `pkgbase` or
+/// `pkgver`.
+pub fn read_srcinfo<R: BufRead>(
+    reader: R,
+    path: &Path,
+    arch: &str,
+) -> std::io::Result<SourcePackage> {
+    let mut base_name = None;
+    let mut base = Section::new();
+    let mut packages: Vec<(String, Section)> = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+        match key {
+            "pkgbase" => base_name = Some(value.to_owned()),
+            "pkgname" => packages.push((value.to_owned(), Section::new())),
+            _ => {
+                let section = packages
+                    .last_mut()
+                    .map_or(&mut base, |(_, section)| section);
+                let values = section.entry(key.to_owned()).or_default();
+                // An empty value overrides the inherited value with nothing
+                if !value.is_empty() {
+                    values.push(value.to_owned());
+                }
+            }
+        }
+    }
+
+    let base_name =
+        base_name.ok_or_else(|| invalid_data(format!("Missing pkgbase in {}", path.display())))?;
+    let single = |key: &str| base.get(key).and_then(|values| values.first());
+    let pkgver = single("pkgver")
+        .ok_or_else(|| invalid_data(format!("Missing pkgver in {}", path.display())))?;
+    let pkgrel = single("pkgrel").map_or("1", String::as_str);
+    let version = match single("epoch") {
+        Some(epoch) => format!("{epoch}:{pkgver}-{pkgrel}"),
+        None => format!("{pkgver}-{pkgrel}"),
+    };
+    // Split packages override generic and architecture specific fields separately
+    let field = |section: &Section, key: &str| {
+        [key.to_owned(), format!("{key}_{arch}")]
+            .iter()
+            .filter_map(|key| section.get(key).or_else(|| base.get(key)))
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    let packages = packages
+        .iter()
+        .map(|(name, section)| SplitPackage {
+            name: name.clone(),
+            depends: field(section, "depends"),
+            optdepends: field(section, "optdepends"),
+            provides: field(section, "provides"),
+            conflicts: field(section, "conflicts"),
+        })
+        .collect();
+    Ok(SourcePackage {
+        makedepends: field(&base, "makedepends"),
+        checkdepends: field(&base, "checkdepends"),
+        base: base_name,
+        version,
+        path: path.to_owned(),
+        packages,
+    })
+}
+
+fn walk(directory: &Path, arch: &str, sources: &mut Vec<SourcePackage>) -> std::io::Result<()> {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(error) => {
+            warn!("Failed to read directory {}: {error}", directory.display());
+            return Ok(());
+        }
+    };
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        let file_name = entry.file_name();
+        if file_name == ".SRCINFO" {
+            debug!("Reading {}", path.display());
+            let reader = std::io::BufReader::new(std::fs::File::open(&path)?);
+            sources.push(read_srcinfo(reader, &path, arch)?);
+        } else if entry.file_type()?.is_dir() && !file_name.to_string_lossy().starts_with('.') {
+            walk(&path, arch, sources)?;
+        }
+    }
+    Ok(())
+}
+
+/// Find and read all `.SRCINFO` files under `directory`.
+///
+/// Recursively search `directory`, but skip hidden directories such as `.git`.
+/// Log and skip directories which cannot be read.  See [`read_srcinfo`] for
+/// `arch`.
+///
+/// Return source packages sorted by package base.
+///
+/// # Errors
+///
+/// Return an error if a `.SRCINFO` file cannot be read or is invalid.
+pub fn find_source_packages(directory: &Path, arch: &str) -> std::io::Result<Vec<SourcePackage>> {
+    let mut sources = Vec::new();
+    walk(directory, arch, &mut sources)?;
+    sources.sort_by(|a, b| a.base.cmp(&b.base));
+    Ok(sources)
+}
+
+/// A dependency graph of package bases.
+pub type SourceGraphMap<'a> = DiGraphMap<&'a str, DependencyEdge>;
+
+/// How strongly an edge binds two package bases; lower is stronger.
+fn edge_rank(edge: DependencyEdge) -> u8 {
+    match edge {
+        DependencyEdge::Required => 0,
+        DependencyEdge::Make => 1,
+        DependencyEdge::Check => 2,
+        DependencyEdge::Optional => 3,
+    }
+}
+
+/// Build a dependency graph of `sources`.
+///
+/// Add a node for every package base, and an edge from a package base to every
+/// other package base whose packages satisfy a dependency of the former by
+/// name or provision.  Ignore version constraints, and dependencies which no
+/// source package satisfies, e.g. packages from official repositories.
+///
+/// Only keep the strongest edge between two package bases, where required
+/// edges are stronger than [`DependencyEdge::Make`] edges, which are stronger
+/// than [`DependencyEdge::Check`] edges, which are stronger than optional
+/// edges.
+pub fn build_source_graph(sources: &[SourcePackage]) -> SourceGraphMap<'_> {
+    let mut providers = HashMap::<&str, &str>::new();
+    for source in sources {
+        for package in &source.packages {
+            let names = std::iter::once(package.name.as_str())
+                .chain(package.provides.iter().map(|p| dependency_name(p)));
+            for name in names {
+                if let Some(other) = providers.insert(name, &source.base)
+                    && other != source.base
+                {
+                    debug!("{name} is provided by {other} and {}", source.base);
+                }
+            }
+        }
+    }
+
+    let mut graph = SourceGraphMap::new();
+    for source in sources {
+        graph.add_node(&source.base);
+        let runtime = source.packages.iter().flat_map(|package| {
+            let depends = package
+                .depends
+                .iter()
+                .map(|dep| (dep, DependencyEdge::Required));
+            let optdepends = package
+                .optdepends
+                .iter()
+                .map(|dep| (dep, DependencyEdge::Optional));
+            depends.chain(optdepends)
+        });
+        let build = source
+            .makedepends
+            .iter()
+            .map(|dep| (dep, DependencyEdge::Make))
+            .chain(
+                source
+                    .checkdepends
+                    .iter()
+                    .map(|dep| (dep, DependencyEdge::Check)),
+            );
+        for (dependency, edge) in runtime.chain(build) {
+            let Some(provider) = providers.get(dependency_name(dependency)).copied() else {
+                continue;
+            };
+            if provider == source.base {
+                continue;
+            }
+            let stronger = graph
+                .edge_weight(&source.base, provider)
+                .is_none_or(|existing| edge_rank(edge) < edge_rank(*existing));
+            if stronger {
+                graph.add_edge(&source.base, provider, edge);
+            }
+        }
+    }
+    graph
+}
+
+/// Get all package bases which `base` transitively needs, including `base`.
+pub fn build_closure<'a, G>(graph: G, base: &'a str) -> HashSet<&'a str>
+where
+    G: IntoNeighbors + Visitable<NodeId = &'a str>,
+{
+    Bfs::new(graph, base).iter(graph).collect()
+}
+
+/// Get the order in which to build all package bases in `graph`.
+///
+/// Return batches of package bases, such that every batch only depends on
+/// packages in earlier batches or in the same batch.  A batch with multiple
+/// package bases is a dependency cycle.  Sort package bases in each batch by
+/// name.
+pub fn build_order<'a, G>(graph: G) -> Vec<Vec<&'a str>>
+where
+    G: IntoNodeIdentifiers + IntoNeighbors + NodeIndexable<NodeId = &'a str>,
+{
+    // Tarjan's algorithm returns components in reverse topological order,
+    // i.e. dependencies before dependents.
+    let mut batches = tarjan_scc(graph);
+    for batch in &mut batches {
+        batch.sort_unstable();
+    }
+    batches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(srcinfo: &str, arch: &str) -> SourcePackage {
+        read_srcinfo(srcinfo.as_bytes(), Path::new("foo/.SRCINFO"), arch).unwrap()
+    }
+
+    #[test]
+    fn read_srcinfo_with_split_packages() {
+        let source = read(
+            "# Generated by makepkg
+pkgbase = foo
+\tpkgver = 1.2
+\tpkgrel = 3
+\tmakedepends = cmake
+\tcheckdepends = python-pytest
+\tdepends = glibc
+\toptdepends = bash: for scripts
+\tprovides = libfoo.so=1-64
+
+pkgname = foo
+
+pkgname = foo-docs
+\tdepends = foo>=1.2
+\tprovides = foo-doc
+",
+            "x86_64",
+        );
+        assert_eq!(source.base, "foo");
+        assert_eq!(source.version, "1.2-3");
+        assert_eq!(source.path, Path::new("foo/.SRCINFO"));
+        assert_eq!(source.makedepends, ["cmake"]);
+        assert_eq!(source.checkdepends, ["python-pytest"]);
+        assert_eq!(
+            source.packages,
+            [
+                SplitPackage {
+                    name: "foo".to_owned(),
+                    depends: vec!["glibc".to_owned()],
+                    optdepends: vec!["bash: for scripts".to_owned()],
+                    provides: vec!["libfoo.so=1-64".to_owned()],
+                    conflicts: Vec::new(),
+                },
+                SplitPackage {
+                    name: "foo-docs".to_owned(),
+                    depends: vec!["foo>=1.2".to_owned()],
+                    optdepends: vec!["bash: for scripts".to_owned()],
+                    provides: vec!["foo-doc".to_owned()],
+                    conflicts: Vec::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn read_srcinfo_with_empty_override() {
+        let source = read(
+            "pkgbase = foo
+\tpkgver = 1.0
+\tdepends = glibc
+\tdepends = zlib
+
+pkgname = foo
+
+pkgname = foo-data
+\tdepends =
+",
+            "x86_64",
+        );
+        let depends = source
+            .packages
+            .iter()
+            .map(|package| (package.name.as_str(), package.depends.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            depends,
+            [
+                ("foo", vec!["glibc".to_owned(), "zlib".to_owned()]),
+                ("foo-data", Vec::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_srcinfo_merges_arch_fields() {
+        let srcinfo = "pkgbase = foo
+\tpkgver = 1.0
+\tmakedepends = cmake
+\tmakedepends_x86_64 = nasm
+\tmakedepends_aarch64 = gcc-aarch64
+\tdepends = glibc
+\tdepends_x86_64 = lib32-glibc
+
+pkgname = foo
+\tdepends_x86_64 = libfoo-x86
+";
+        let source = read(srcinfo, "x86_64");
+        assert_eq!(source.makedepends, ["cmake", "nasm"]);
+        let package = source.packages.first().unwrap();
+        // The split package overrides only the architecture specific field
+        assert_eq!(package.depends, ["glibc", "libfoo-x86"]);
+
+        let source = read(srcinfo, "aarch64");
+        assert_eq!(source.makedepends, ["cmake", "gcc-aarch64"]);
+        assert_eq!(source.packages.first().unwrap().depends, ["glibc"]);
+    }
+
+    #[test]
+    fn read_srcinfo_versions() {
+        let source = read(
+            "pkgbase = foo\npkgver = 1.0\npkgrel = 2\nepoch = 1\n",
+            "any",
+        );
+        assert_eq!(source.version, "1:1.0-2");
+        let source = read("pkgbase = foo\npkgver = 1.0\n", "any");
+        assert_eq!(source.version, "1.0-1");
+        assert!(source.packages.is_empty());
+    }
+
+    #[test]
+    fn read_srcinfo_without_pkgbase_or_pkgver() {
+        let error = read_srcinfo(&b"pkgver = 1.0\n"[..], Path::new(".SRCINFO"), "any").unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        let error =
+            read_srcinfo(&b"pkgbase = foo\n"[..], Path::new(".SRCINFO"), "any").unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
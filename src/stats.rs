@@ -0,0 +1,365 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL-1.2 OR GPL-3.0
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Statistics about dependency graphs.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+};
+
+use alpm::PackageReason;
+use petgraph::{
+    algo::tarjan_scc,
+    visit::{
+        Bfs, Data, GraphRef, IntoEdgeReferences, IntoNeighbors, IntoNeighborsDirected,
+        IntoNodeIdentifiers, NodeIndexable, Reversed, Visitable, Walker,
+    },
+};
+
+use crate::graph::{DependencyEdge, PackageNode};
+
+/// Count the number of direct dependents of every node in `graph`.
+///
+/// Return a map of every node to the number of its incoming edges.
+pub fn in_degrees<G>(graph: G) -> HashMap<G::NodeId, usize>
+where
+    G: IntoNeighborsDirected + IntoNodeIdentifiers,
+    G::NodeId: Hash + Eq,
+{
+    graph
+        .node_identifiers()
+        .map(|node| {
+            let degree = graph
+                .neighbors_directed(node, petgraph::Direction::Incoming)
+                .count();
+            (node, degree)
+        })
+        .collect()
+}
+
+/// Count the number of transitive dependents of every node in `graph`.
+///
+/// Return a map of every node to the number of other nodes it is reachable
+/// from.
+pub fn transitive_in_degrees<G>(graph: G) -> HashMap<G::NodeId, usize>
+where
+    G: GraphRef + Visitable + IntoNeighborsDirected + IntoNodeIdentifiers,
+    G::NodeId: Hash + Eq,
+{
+    let reversed = Reversed(graph);
+    graph
+        .node_identifiers()
+        .map(|node| {
+            // Do not count the node itself
+            let reachable = Bfs::new(reversed, node).iter(reversed).skip(1).count();
+            (node, reachable)
+        })
+        .collect()
+}
+
+/// The longest chain starting in a strongly connected component.
+struct Chain<N> {
+    /// The number of components on the chain.
+    length: usize,
+    /// The index of the next component on the chain, and the node in that
+    /// component.
+    next: Option<(usize, N)>,
+}
+
+/// Find the longest dependency chain in `graph`.
+///
+/// Collapse all strongly connected components of `graph`, i.e. dependency
+/// cycles, into single nodes, and return the longest path through the
+/// resulting acyclic graph.  The returned chain contains one node per
+/// strongly connected component on the path, each of which has an edge from
+/// some node in the previous component.
+///
+/// Return an empty chain if the graph is empty.
+pub fn longest_chain<G>(graph: G) -> Vec<G::NodeId>
+where
+    G: IntoNeighbors + IntoNodeIdentifiers + NodeIndexable,
+    G::NodeId: Hash + Eq,
+{
+    // Tarjan's algorithm returns components in reverse topological order, so
+    // every component only has edges to components which come before it.
+    let components = tarjan_scc(graph);
+    let component_of = components
+        .iter()
+        .enumerate()
+        .flat_map(|(index, component)| component.iter().map(move |node| (*node, index)))
+        .collect::<HashMap<_, _>>();
+
+    // For every component the length of the longest chain starting in the
+    // component, and the next component and node on that chain.
+    let mut chains = Vec::<Chain<G::NodeId>>::with_capacity(components.len());
+    for (index, component) in components.iter().enumerate() {
+        let mut longest = Chain {
+            length: 1,
+            next: None,
+        };
+        for node in component {
+            for target in graph.neighbors(*node) {
+                let Some(target_index) = component_of.get(&target).copied() else {
+                    continue;
+                };
+                if target_index == index {
+                    continue;
+                }
+                if let Some(chain) = chains.get(target_index)
+                    && longest.length < chain.length + 1
+                {
+                    longest = Chain {
+                        length: chain.length + 1,
+                        next: Some((target_index, target)),
+                    };
+                }
+            }
+        }
+        chains.push(longest);
+    }
+
+    let start = chains
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, chain)| chain.length)
+        .map(|(index, _)| index);
+    let mut chain = Vec::new();
+    if let Some(node) = start.and_then(|index| components.get(index)?.first()) {
+        chain.push(*node);
+    }
+    let mut next = start.and_then(|index| chains.get(index)?.next);
+    while let Some((index, node)) = next {
+        chain.push(node);
+        next = chains.get(index).and_then(|chain| chain.next);
+    }
+    chain
+}
+
+/// Compute the betweenness centrality of every node in `graph`.
+///
+/// The betweenness centrality of a node is the sum of the fractions of all
+/// shortest paths between all pairs of other nodes which pass through the node.
+/// Nodes with a high betweenness connect many packages with their dependencies,
+/// e.g. central libraries or frameworks.
+///
+/// This uses Brandes' algorithm, and runs in `O(nodes * edges)`.
+///
+/// See <https://doi.org/10.1080/0022250X.2001.9990249>.
+pub fn betweenness_centrality<G>(graph: G) -> HashMap<G::NodeId, f64>
+where
+    G: IntoNeighbors + IntoNodeIdentifiers,
+    G::NodeId: Hash + Eq,
+{
+    let mut centrality = graph
+        .node_identifiers()
+        .map(|node| (node, 0.0))
+        .collect::<HashMap<_, _>>();
+    for source in graph.node_identifiers() {
+        // Nodes in order of non-decreasing distance from source
+        let mut stack = Vec::new();
+        let mut predecessors = HashMap::<_, Vec<_>>::new();
+        let mut path_counts = HashMap::from([(source, 1.0)]);
+        let mut distances = HashMap::from([(source, 0_usize)]);
+        let mut queue = VecDeque::from([source]);
+        while let Some(node) = queue.pop_front() {
+            stack.push(node);
+            let distance = distances.get(&node).copied().unwrap_or_default();
+            let path_count = path_counts.get(&node).copied().unwrap_or_default();
+            for target in graph.neighbors(node) {
+                let target_distance = *distances.entry(target).or_insert_with(|| {
+                    queue.push_back(target);
+                    distance + 1
+                });
+                if target_distance == distance + 1 {
+                    *path_counts.entry(target).or_default() += path_count;
+                    predecessors.entry(target).or_default().push(node);
+                }
+            }
+        }
+
+        let mut deltas = HashMap::<_, f64>::new();
+        while let Some(node) = stack.pop() {
+            let delta = deltas.get(&node).copied().unwrap_or_default();
+            let path_count = path_counts.get(&node).copied().unwrap_or_default();
+            for predecessor in predecessors.get(&node).into_iter().flatten() {
+                let predecessor_path_count =
+                    path_counts.get(predecessor).copied().unwrap_or_default();
+                *deltas.entry(*predecessor).or_default() +=
+                    predecessor_path_count / path_count * (1.0 + delta);
+            }
+            if node != source {
+                *centrality.entry(node).or_default() += delta;
+            }
+        }
+    }
+    centrality
+}
+
+/// Statistics about a package graph.
+#[derive(Debug)]
+pub struct GraphStatistics<'a> {
+    /// The number of packages.
+    pub packages: usize,
+    /// The number of dependency edges.
+    pub dependencies: usize,
+    /// The number of explicitly installed packages.
+    pub explicit: usize,
+    /// The number of strongly connected components.
+    pub components: usize,
+    /// The number of strongly connected components with more than one package.
+    pub cycles: usize,
+    /// The longest chain of dependencies.
+    pub longest_chain: Vec<PackageNode<'a>>,
+    /// Packages with the most direct dependents.
+    pub most_required: Vec<(PackageNode<'a>, usize)>,
+    /// Packages with the most transitive dependents.
+    pub most_required_transitively: Vec<(PackageNode<'a>, usize)>,
+    /// Packages with the highest betweenness centrality.
+    pub highest_betweenness: Vec<(PackageNode<'a>, f64)>,
+}
+
+/// Take the `top` packages with the highest values.
+fn top_packages<'a, T: PartialOrd>(
+    values: impl IntoIterator<Item = (PackageNode<'a>, T)>,
+    top: usize,
+) -> Vec<(PackageNode<'a>, T)> {
+    let mut values = values.into_iter().collect::<Vec<_>>();
+    // Sort by value, highest first, and then by name
+    values.sort_by(|(node_a, value_a), (node_b, value_b)| {
+        value_b
+            .partial_cmp(value_a)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| node_a.name().cmp(node_b.name()))
+    });
+    values.truncate(top);
+    values
+}
+
+/// Compute statistics about a package graph.
+///
+/// Include the `top` packages with the most direct and transitive dependents,
+/// and with the highest betweenness centrality.
+pub fn graph_statistics<'a, G>(graph: G, top: usize) -> GraphStatistics<'a>
+where
+    G: GraphRef
+        + Data<EdgeWeight = DependencyEdge, NodeWeight = PackageNode<'a>>
+        + NodeIndexable
+        + Visitable<NodeId = PackageNode<'a>>
+        + IntoNeighborsDirected
+        + IntoNodeIdentifiers
+        + IntoEdgeReferences,
+{
+    let components = tarjan_scc(graph);
+    GraphStatistics {
+        packages: graph.node_identifiers().count(),
+        dependencies: graph.edge_references().count(),
+        explicit: graph
+            .node_identifiers()
+            .filter(|node| node.reason() == PackageReason::Explicit)
+            .count(),
+        components: components.len(),
+        cycles: components
+            .iter()
+            .filter(|component| component.len() > 1)
+            .count(),
+        longest_chain: longest_chain(graph),
+        most_required: top_packages(in_degrees(graph), top),
+        most_required_transitively: top_packages(transitive_in_degrees(graph), top),
+        highest_betweenness: top_packages(betweenness_centrality(graph), top),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use petgraph::prelude::DiGraphMap;
+
+    use super::*;
+
+    fn graph<'a>(edges: &[(&'a str, &'a str)]) -> DiGraphMap<&'a str, ()> {
+        DiGraphMap::from_edges(edges)
+    }
+
+    fn assert_centrality(centrality: &HashMap<&str, f64>, node: &str, expected: f64) {
+        let actual = centrality.get(node).copied();
+        assert!(
+            actual.is_some_and(|actual| (actual - expected).abs() < 1e-9),
+            "{node}: expected {expected}, got {actual:?}"
+        );
+    }
+
+    #[test]
+    fn in_degrees_of_diamond() {
+        let graph = graph(&[("a", "b"), ("a", "c"), ("b", "d"), ("c", "d")]);
+        let degrees = in_degrees(&graph);
+        assert_eq!(degrees.get("a"), Some(&0));
+        assert_eq!(degrees.get("b"), Some(&1));
+        assert_eq!(degrees.get("d"), Some(&2));
+        let degrees = transitive_in_degrees(&graph);
+        assert_eq!(degrees.get("a"), Some(&0));
+        assert_eq!(degrees.get("b"), Some(&1));
+        assert_eq!(degrees.get("d"), Some(&3));
+    }
+
+    #[test]
+    fn longest_chain_of_empty_graph() {
+        assert!(longest_chain(&graph(&[])).is_empty());
+    }
+
+    #[test]
+    fn longest_chain_of_single_node() {
+        let mut graph = graph(&[]);
+        graph.add_node("a");
+        assert_eq!(longest_chain(&graph), vec!["a"]);
+    }
+
+    #[test]
+    fn longest_chain_picks_longest_path() {
+        let graph = graph(&[("a", "b"), ("b", "c"), ("c", "d"), ("a", "d"), ("x", "d")]);
+        assert_eq!(longest_chain(&graph), vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn longest_chain_collapses_cycles() {
+        let graph = graph(&[("a", "b"), ("b", "c"), ("c", "b"), ("c", "d")]);
+        assert_eq!(longest_chain(&graph), vec!["a", "b", "d"]);
+    }
+
+    #[test]
+    fn longest_chain_of_cycle() {
+        let graph = graph(&[("a", "b"), ("b", "a")]);
+        assert_eq!(longest_chain(&graph).len(), 1);
+    }
+
+    #[test]
+    fn betweenness_centrality_of_path() {
+        let graph = graph(&[("a", "b"), ("b", "c")]);
+        let centrality = betweenness_centrality(&graph);
+        assert_centrality(&centrality, "a", 0.0);
+        assert_centrality(&centrality, "b", 1.0);
+        assert_centrality(&centrality, "c", 0.0);
+    }
+
+    #[test]
+    fn betweenness_centrality_splits_shortest_paths() {
+        let graph = graph(&[("a", "b"), ("a", "c"), ("b", "d"), ("c", "d"), ("d", "e")]);
+        let centrality = betweenness_centrality(&graph);
+        assert_centrality(&centrality, "a", 0.0);
+        // Half of the paths from a to d and e each
+        assert_centrality(&centrality, "b", 1.0);
+        assert_centrality(&centrality, "c", 1.0);
+        // Paths from a, b, and c to e
+        assert_centrality(&centrality, "d", 3.0);
+        assert_centrality(&centrality, "e", 0.0);
+    }
+
+    #[test]
+    fn betweenness_centrality_ignores_longer_paths() {
+        let graph = graph(&[("a", "b"), ("b", "c"), ("a", "c")]);
+        let centrality = betweenness_centrality(&graph);
+        assert_centrality(&centrality, "b", 0.0);
+    }
+}
@@ -0,0 +1,269 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL-1.2 OR GPL-3.0
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Preview the effect of a system upgrade on the dependency graph.
+
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet, VecDeque},
+};
+
+use alpm::{AlpmList, Db, Dep, Package, PackageReason};
+use alpm_utils::DbListExt as _;
+use petgraph::visit::{GraphRef, IntoNeighbors, IntoNodeIdentifiers, NodeCount, Visitable};
+use tracing::{debug, warn};
+
+use crate::{
+    alpm::satisfies_package,
+    conflicts::{Conflict, find_conflicts},
+    dependencies::{orphans, orphans_with_roots},
+    graph::{AlpmDepGraphMap, DependencyEdge, PackageNode},
+    upstream::{Replacement, replaced_packages},
+};
+
+/// An installed package with a newer version in a sync database.
+#[derive(Debug, Clone, Copy)]
+pub struct Upgrade<'a> {
+    /// The installed package.
+    pub installed: &'a Package,
+    /// The newer package from a sync database.
+    pub upgrade: &'a Package,
+}
+
+/// The effect of upgrading all installed packages.
+#[derive(Debug)]
+pub struct UpgradePreview<'a> {
+    /// Installed packages with newer versions in sync databases, sorted by name.
+    pub upgrades: Vec<Upgrade<'a>>,
+    /// Installed packages which packages in sync databases replace, sorted by
+    /// the name of the installed package.
+    pub replacements: Vec<Replacement<'a, 'a>>,
+    /// Packages from sync databases which the upgrade pulls in to satisfy new
+    /// dependencies, sorted by name.
+    pub new_packages: Vec<&'a Package>,
+    /// Conflicts between packages after the upgrade which do not exist between
+    /// installed packages, sorted like [`find_conflicts`].
+    pub new_conflicts: Vec<Conflict<'a>>,
+    /// The dependency graph after the upgrade.
+    ///
+    /// This graph contains all installed packages which the upgrade keeps as
+    /// they are, and upgraded, replacing and new packages from sync databases.
+    pub graph: AlpmDepGraphMap<'a>,
+    /// The names of all explicitly installed packages after the upgrade.
+    ///
+    /// Packages from sync databases have no installation reason, so this set
+    /// records the reason of the corresponding installed packages instead.
+    /// Replacing packages inherit the reason of the packages they replace.
+    pub explicit: HashSet<&'a str>,
+}
+
+impl<'a> UpgradePreview<'a> {
+    /// Find packages which become orphans with the upgrade.
+    ///
+    /// `before` is the dependency graph of installed packages, and `after` is
+    /// the [`Self::graph`] of this preview, with the same edges filtered out as
+    /// in `before`, e.g. without optional dependencies.
+    ///
+    /// Return all orphans in `after` whose name is no orphan in `before`,
+    /// sorted by name.
+    pub fn new_orphans<G, H>(&self, before: G, after: H) -> Vec<PackageNode<'a>>
+    where
+        G: GraphRef
+            + NodeCount
+            + Visitable<NodeId = PackageNode<'a>>
+            + IntoNeighbors
+            + IntoNodeIdentifiers,
+        H: GraphRef
+            + NodeCount
+            + Visitable<NodeId = PackageNode<'a>>
+            + IntoNeighbors
+            + IntoNodeIdentifiers,
+    {
+        let orphans_before = orphans(before)
+            .node_identifiers()
+            .map(|node| node.package().name())
+            .collect::<HashSet<_>>();
+        let mut new_orphans =
+            orphans_with_roots(after, |node| self.explicit.contains(node.package().name()))
+                .node_identifiers()
+                .filter(|node| !orphans_before.contains(node.package().name()))
+                .collect::<Vec<_>>();
+        new_orphans.sort_by_key(|node| node.package().name());
+        new_orphans
+    }
+}
+
+/// Packages indexed by their names and the names of their provisions.
+#[derive(Debug, Default)]
+struct Providers<'a>(HashMap<&'a str, Vec<&'a Package>>);
+
+impl<'a> Providers<'a> {
+    fn add(&mut self, package: &'a Package) {
+        self.0.entry(package.name()).or_default().push(package);
+        for provide in package.provides() {
+            self.0.entry(provide.name()).or_default().push(package);
+        }
+    }
+
+    /// Get all packages which satisfy `dep`.
+    fn satisfiers<'b>(&'b self, dep: &'b Dep) -> impl Iterator<Item = &'a Package> + 'b {
+        self.0
+            .get(dep.name())
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|package| satisfies_package(dep, package))
+    }
+}
+
+/// Build a dependency graph of `packages`.
+///
+/// Add edges from every package to all packages which satisfy any of its
+/// dependencies or optional dependencies, either by name or by a provision.
+fn build_graph<'a>(packages: &[&'a Package], providers: &Providers<'a>) -> AlpmDepGraphMap<'a> {
+    let mut graph = AlpmDepGraphMap::new();
+    for package in packages {
+        graph.add_node(PackageNode::new(package));
+    }
+    for package in packages {
+        let dependencies = package
+            .depends()
+            .into_iter()
+            .map(|dep| (dep, DependencyEdge::Required))
+            .chain(
+                package
+                    .optdepends()
+                    .into_iter()
+                    .map(|dep| (dep, DependencyEdge::Optional)),
+            );
+        for (dep, edge) in dependencies {
+            for provider in providers.satisfiers(dep) {
+                let (from, to) = (PackageNode::new(package), PackageNode::new(provider));
+                if from != to && graph.edge_weight(from, to) != Some(&DependencyEdge::Required) {
+                    graph.add_edge(from, to, edge);
+                }
+            }
+        }
+    }
+    graph
+}
+
+/// Preview an upgrade of all installed packages in `localdb` from `syncdbs`.
+///
+/// Upgrade every installed package to the package of the same name in the
+/// first sync database which has it, if that package has a newer version, and
+/// replace installed packages which packages in sync databases replace.  Then
+/// pull in packages from sync databases for all dependencies which no package
+/// satisfies after the upgrade, like pacman does for new dependencies.
+///
+/// Do not consider `IgnorePkg` and similar settings from `pacman.conf`.
+#[must_use]
+pub fn preview_upgrade<'a>(localdb: &'a Db, syncdbs: AlpmList<&'a Db>) -> UpgradePreview<'a> {
+    let mut replacements = replaced_packages(localdb, syncdbs);
+    replacements.sort_by_key(|replacement| replacement.installed.name());
+    let replaced = replacements
+        .iter()
+        .map(|replacement| replacement.installed.name())
+        .collect::<HashSet<_>>();
+
+    let mut explicit = HashSet::new();
+    let mut upgrades = Vec::new();
+    let mut packages = Vec::new();
+    let mut names = HashSet::new();
+    for installed in localdb.pkgs() {
+        if replaced.contains(installed.name()) {
+            continue;
+        }
+        let package = match syncdbs.pkg(installed.name()) {
+            Ok(upgrade)
+                if alpm::vercmp(upgrade.version().as_str(), installed.version().as_str())
+                    == Ordering::Greater =>
+            {
+                upgrades.push(Upgrade { installed, upgrade });
+                upgrade
+            }
+            _ => installed,
+        };
+        if installed.reason() == PackageReason::Explicit {
+            explicit.insert(installed.name());
+        }
+        names.insert(package.name());
+        packages.push(package);
+    }
+    for replacement in &replacements {
+        if replacement.installed.reason() == PackageReason::Explicit {
+            explicit.insert(replacement.replacement.name());
+        }
+        if names.insert(replacement.replacement.name()) {
+            packages.push(replacement.replacement);
+        }
+    }
+
+    let mut providers = Providers::default();
+    for package in &packages {
+        providers.add(package);
+    }
+    let mut new_packages = Vec::new();
+    let mut queue = packages.iter().copied().collect::<VecDeque<_>>();
+    while let Some(package) = queue.pop_front() {
+        for dep in package.depends() {
+            if providers.satisfiers(dep).next().is_some() {
+                continue;
+            }
+            match syncdbs.find_satisfier(dep.to_string()) {
+                Some(dependency) if names.insert(dependency.name()) => {
+                    debug!(
+                        package = package.name(),
+                        "{} pulls in {} for {dep}",
+                        package.name(),
+                        dependency.name()
+                    );
+                    providers.add(dependency);
+                    packages.push(dependency);
+                    new_packages.push(dependency);
+                    queue.push_back(dependency);
+                }
+                Some(_) => {}
+                None => warn!(
+                    package = package.name(),
+                    "No package satisfies dependency {dep} of {}",
+                    package.name()
+                ),
+            }
+        }
+    }
+
+    let conflicts_before = find_conflicts(&localdb.pkgs().into_iter().collect::<Vec<_>>())
+        .iter()
+        .map(|conflict| {
+            (
+                conflict.package.package().name(),
+                conflict.conflicting.package().name(),
+            )
+        })
+        .collect::<HashSet<_>>();
+    let new_conflicts = find_conflicts(&packages)
+        .into_iter()
+        .filter(|conflict| {
+            !conflicts_before.contains(&(
+                conflict.package.package().name(),
+                conflict.conflicting.package().name(),
+            ))
+        })
+        .collect();
+
+    let graph = build_graph(&packages, &providers);
+    upgrades.sort_by_key(|upgrade| upgrade.installed.name());
+    new_packages.sort_by_key(|package| package.name());
+    UpgradePreview {
+        upgrades,
+        replacements,
+        new_packages,
+        new_conflicts,
+        graph,
+        explicit,
+    }
+}
@@ -0,0 +1,303 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL-1.2 OR GPL-3.0
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Compare installed packages to sync databases.
+
+use std::collections::{BTreeMap, HashSet};
+
+use alpm::{AlpmList, Db, Dep, Package, PackageValidation};
+use alpm_utils::depends::satisfies_dep;
+use petgraph::{
+    Direction,
+    visit::{
+        Data, EdgeRef, GraphBase, GraphRef, IntoEdges, IntoNeighbors, IntoNeighborsDirected,
+        IntoNodeIdentifiers, NodeCount, Visitable,
+    },
+};
+use tracing::debug;
+
+use crate::{
+    alpm::{conflicts_with, is_foreign, satisfies_package},
+    dependencies::{GraphPosition, dependents, orphans},
+    graph::{DependencyEdge, PackageNode},
+};
+
+/// Find installed packages which were dropped from their repository.
+///
+/// The local database does not record the repository a package was installed
+/// from, so this function considers all foreign packages (see
+/// [`crate::alpm::is_foreign`]) which were validated with a signature upon
+/// installation.  Locally built packages and packages from the AUR are usually
+/// not signed, whereas pacman verifies the signatures of all packages it
+/// installs from sync databases.
+pub fn dropped_packages<'a>(localdb: &'a Db, syncdbs: AlpmList<&Db>) -> Vec<&'a Package> {
+    localdb
+        .pkgs()
+        .into_iter()
+        .filter(|package| {
+            package.validation().contains(PackageValidation::SIGNATURE)
+                && is_foreign(package, syncdbs)
+        })
+        .collect()
+}
+
+/// An installed package which a package in a sync database replaces.
+#[derive(Debug, Clone, Copy)]
+pub struct Replacement<'a, 'b> {
+    /// The installed package.
+    pub installed: &'a Package,
+    /// The package from a sync database which replaces the installed package.
+    pub replacement: &'b Package,
+}
+
+/// Find installed packages which packages in sync databases replace.
+///
+/// Check the `replaces` of every package in `syncdbs` against the installed
+/// packages in `localdb`, and return all installed packages which are replaced
+/// by a different package.
+pub fn replaced_packages<'a, 'b>(
+    localdb: &'a Db,
+    syncdbs: AlpmList<&'b Db>,
+) -> Vec<Replacement<'a, 'b>> {
+    let mut replacements = Vec::new();
+    for replacement in syncdbs.iter().flat_map(|db| db.pkgs()) {
+        for replaces in replacement.replaces() {
+            let Ok(installed) = localdb.pkg(replaces.name()) else {
+                continue;
+            };
+            if installed.name() != replacement.name()
+                && satisfies_dep(replaces, installed.name(), installed.version())
+            {
+                debug!(
+                    package = installed.name(),
+                    "{} replaces {}",
+                    replacement.name(),
+                    installed.name()
+                );
+                replacements.push(Replacement {
+                    installed,
+                    replacement,
+                });
+            }
+        }
+    }
+    replacements
+}
+
+/// An installed package which upstream dropped or replaced.
+#[derive(Debug)]
+pub struct StalePackage<'a, 'b> {
+    /// The installed package.
+    pub package: PackageNode<'a>,
+    /// The position of the package in the dependency graph.
+    pub position: GraphPosition,
+    /// All packages which replace this package, sorted by name.
+    ///
+    /// Empty if upstream dropped this package.
+    pub replaced_by: Vec<&'b Package>,
+    /// Installed packages which directly depend on this package, sorted by name.
+    pub required_by: Vec<PackageNode<'a>>,
+    /// All installed packages which transitively depend on this package,
+    /// sorted by name.
+    pub dependents: Vec<PackageNode<'a>>,
+}
+
+/// Find all installed packages which upstream dropped or replaced.
+///
+/// Combine [`dropped_packages`] and [`replaced_packages`], and report every
+/// installed package only once.  A renamed package is foreign as well, so
+/// report it as replaced rather than dropped, with all packages which replace
+/// it.  Like pacman, take every replacing package from the first sync database
+/// which has it.
+///
+/// Return stale packages sorted by name.
+pub fn stale_packages<'a, 'b, G>(
+    graph: G,
+    localdb: &'a Db,
+    syncdbs: AlpmList<&'b Db>,
+) -> Vec<StalePackage<'a, 'b>>
+where
+    G: GraphRef
+        + NodeCount
+        + Visitable<NodeId = PackageNode<'a>>
+        + IntoNeighbors
+        + IntoNeighborsDirected
+        + IntoNodeIdentifiers,
+{
+    let mut stale = BTreeMap::<&str, (&Package, Vec<&Package>)>::new();
+    for package in dropped_packages(localdb, syncdbs) {
+        stale.insert(package.name(), (package, Vec::new()));
+    }
+    for replacement in replaced_packages(localdb, syncdbs) {
+        let (_, replaced_by) = stale
+            .entry(replacement.installed.name())
+            .or_insert_with(|| (replacement.installed, Vec::new()));
+        if replaced_by
+            .iter()
+            .all(|package| package.name() != replacement.replacement.name())
+        {
+            replaced_by.push(replacement.replacement);
+        }
+    }
+
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let orphans = orphans(graph).node_identifiers().collect::<HashSet<_>>();
+    stale
+        .into_values()
+        .map(|(installed, mut replaced_by)| {
+            let package = PackageNode::new(installed);
+            replaced_by.sort_by_key(|package| package.name());
+            let mut required_by = graph
+                .neighbors_directed(package, Direction::Incoming)
+                .collect::<Vec<_>>();
+            required_by.sort_by_key(|node| node.package().name());
+            let mut dependents = dependents(graph, installed)
+                .node_identifiers()
+                .filter(|node| *node != package)
+                .collect::<Vec<_>>();
+            dependents.sort_by_key(|node| node.package().name());
+            StalePackage {
+                package,
+                position: GraphPosition::of(package, &orphans),
+                replaced_by,
+                required_by,
+                dependents,
+            }
+        })
+        .collect()
+}
+
+/// A package from a sync database which could replace an installed package.
+#[derive(Debug, Clone)]
+pub struct Alternative<'a, 'b> {
+    /// The package from a sync database.
+    pub candidate: &'b Package,
+    /// Whether a package of the same name as the candidate is installed.
+    pub installed: bool,
+    /// Dependents whose dependencies on the installed package the candidate
+    /// fully satisfies, sorted by name.
+    pub satisfied: Vec<PackageNode<'a>>,
+    /// Dependents with dependencies on the installed package which the
+    /// candidate does not satisfy, sorted by name.
+    pub unsatisfied: Vec<PackageNode<'a>>,
+    /// Installed packages which conflict with the candidate, sorted by name.
+    ///
+    /// This excludes the installed package itself, and any installed package of
+    /// the same name as the candidate.
+    pub conflicts: Vec<PackageNode<'a>>,
+}
+
+/// Find alternatives for an installed package in sync databases.
+///
+/// Collect all dependencies of the dependents of `package` in `graph` which
+/// `package` satisfies, and find all packages in `syncdbs` which satisfy any of
+/// these dependencies, either by name or by their provisions.  For every such
+/// candidate determine which dependents it fully satisfies, and which installed
+/// packages in `localdb` it conflicts with.  If several sync databases have a
+/// package of the same name, only consider the package from the first one.
+///
+/// Only consider optional dependencies if `graph` contains optional edges.
+///
+/// Return candidates sorted by the number of satisfied dependents, highest
+/// first, and then by name.
+pub fn alternatives<'a, 'b, G>(
+    graph: G,
+    localdb: &'a Db,
+    syncdbs: AlpmList<&'b Db>,
+    package: &'a Package,
+) -> Vec<Alternative<'a, 'b>>
+where
+    G: GraphBase<NodeId = PackageNode<'a>>
+        + Data<EdgeWeight = DependencyEdge>
+        + IntoNeighborsDirected
+        + IntoEdges,
+{
+    let node = PackageNode::new(package);
+    // The dependencies of every dependent which the package satisfies
+    let mut requirements: Vec<(PackageNode<'a>, Vec<&'a Dep>)> = Vec::new();
+    for dependent in graph.neighbors_directed(node, Direction::Incoming) {
+        let mut deps = Vec::new();
+        for edge in graph.edges(dependent).filter(|edge| edge.target() == node) {
+            let candidates = match *edge.weight() {
+                DependencyEdge::Required => dependent.package().depends(),
+                DependencyEdge::Optional => dependent.package().optdepends(),
+                DependencyEdge::Make | DependencyEdge::Check => continue,
+            };
+            deps.extend(
+                candidates
+                    .into_iter()
+                    .filter(|dep| satisfies_package(dep, package)),
+            );
+        }
+        if !deps.is_empty() {
+            requirements.push((dependent, deps));
+        }
+    }
+
+    let mut alternatives = Vec::new();
+    let mut seen = HashSet::new();
+    for candidate in syncdbs.iter().flat_map(|db| db.pkgs()) {
+        // Like pacman, skip packages shadowed by a package of the same name in
+        // an earlier sync database.
+        if !seen.insert(candidate.name()) || candidate.name() == package.name() {
+            continue;
+        }
+        let mut satisfied = Vec::new();
+        let mut unsatisfied = Vec::new();
+        for (dependent, deps) in &requirements {
+            if deps.iter().all(|dep| satisfies_package(dep, candidate)) {
+                satisfied.push(*dependent);
+            } else {
+                unsatisfied.push(*dependent);
+            }
+        }
+        if satisfied.is_empty()
+            && !requirements
+                .iter()
+                .flat_map(|(_, deps)| deps)
+                .any(|dep| satisfies_package(dep, candidate))
+        {
+            continue;
+        }
+        debug!(
+            package = package.name(),
+            "{} can replace {} for {} dependents",
+            candidate.name(),
+            package.name(),
+            satisfied.len()
+        );
+        let mut conflicts = localdb
+            .pkgs()
+            .into_iter()
+            .filter(|installed| {
+                installed.name() != package.name()
+                    && installed.name() != candidate.name()
+                    && conflicts_with(installed, candidate)
+            })
+            .map(PackageNode::new)
+            .collect::<Vec<_>>();
+        conflicts.sort_by_key(|node| node.package().name());
+        satisfied.sort_by_key(|node| node.package().name());
+        unsatisfied.sort_by_key(|node| node.package().name());
+        alternatives.push(Alternative {
+            candidate,
+            installed: localdb.pkg(candidate.name()).is_ok(),
+            satisfied,
+            unsatisfied,
+            conflicts,
+        });
+    }
+    alternatives.sort_by(|a, b| {
+        b.satisfied
+            .len()
+            .cmp(&a.satisfied.len())
+            .then_with(|| a.candidate.name().cmp(b.candidate.name()))
+    });
+    alternatives
+}
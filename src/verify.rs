@@ -0,0 +1,442 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL-1.2 OR GPL-3.0
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Verify installed files against the mtree data of packages.
+
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    fs::File,
+    io::BufReader,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use flate2::bufread::GzDecoder;
+use tracing::{debug, warn};
+
+use crate::mtree::{EntryKind, MtreeEntry, read_mtree};
+
+/// A difference between an installed file and its mtree entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mismatch {
+    /// The file has a different type, e.g. a directory instead of a file.
+    Type,
+    /// The permission bits differ.
+    Mode,
+    /// The owner or group differs.
+    Owner,
+    /// The size differs.
+    Size,
+    /// The modification time differs.
+    Mtime,
+    /// The SHA-256 digest differs.
+    Checksum,
+    /// The target of a symbolic link differs.
+    Link,
+}
+
+impl Mismatch {
+    /// Whether this mismatch concerns permissions rather than contents.
+    #[must_use]
+    pub fn is_permissions(self) -> bool {
+        matches!(self, Mismatch::Mode | Mismatch::Owner)
+    }
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Mismatch::Type => write!(f, "type"),
+            Mismatch::Mode => write!(f, "mode"),
+            Mismatch::Owner => write!(f, "owner"),
+            Mismatch::Size => write!(f, "size"),
+            Mismatch::Mtime => write!(f, "mtime"),
+            Mismatch::Checksum => write!(f, "checksum"),
+            Mismatch::Link => write!(f, "link"),
+        }
+    }
+}
+
+/// The state of an installed file which differs from its mtree entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileState {
+    /// The file does not exist.
+    Missing,
+    /// The file differs from its mtree entry.
+    Changed(Vec<Mismatch>),
+}
+
+/// A file which differs from its mtree entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileProblem {
+    /// The path of the file, relative to the root directory.
+    pub path: String,
+    /// How the file differs.
+    pub state: FileState,
+}
+
+/// A package to verify.
+#[derive(Debug, Clone)]
+pub struct VerifyPackage {
+    /// The name of the package.
+    pub name: String,
+    /// The path to the compressed mtree file of the package.
+    pub mtree: PathBuf,
+    /// Paths of backup files of the package, relative to the root directory.
+    pub backup: HashSet<String>,
+}
+
+/// The result of verifying a package.
+#[derive(Debug)]
+pub struct PackageVerification {
+    /// The name of the package.
+    pub name: String,
+    /// All files of the package which differ from their mtree entries, or an
+    /// error if the mtree file of the package could not be read.
+    pub problems: std::io::Result<Vec<FileProblem>>,
+}
+
+/// Compare a single file to its mtree entry.
+///
+/// Log and ignore errors other than a missing file, e.g. missing permissions.
+fn verify_entry(root: &Path, entry: &MtreeEntry) -> Option<FileState> {
+    let path = root.join(&entry.path);
+    let metadata = match path.symlink_metadata() {
+        Ok(metadata) => metadata,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Some(FileState::Missing);
+        }
+        Err(error) => {
+            warn!("Failed to read metadata of {}: {error}", path.display());
+            return None;
+        }
+    };
+    let file_type = metadata.file_type();
+    let kind_matches = match entry.kind {
+        EntryKind::File => file_type.is_file(),
+        EntryKind::Directory => file_type.is_dir(),
+        EntryKind::Link => file_type.is_symlink(),
+    };
+    if !kind_matches {
+        return Some(FileState::Changed(vec![Mismatch::Type]));
+    }
+
+    let mut mismatches = Vec::new();
+    if entry.kind != EntryKind::Link && entry.mode.is_some_and(|m| m != metadata.mode() & 0o7777) {
+        mismatches.push(Mismatch::Mode);
+    }
+    if entry.uid.is_some_and(|uid| uid != metadata.uid())
+        || entry.gid.is_some_and(|gid| gid != metadata.gid())
+    {
+        mismatches.push(Mismatch::Owner);
+    }
+    match entry.kind {
+        EntryKind::Directory => {}
+        EntryKind::Link => {
+            let target = std::fs::read_link(&path).ok();
+            if entry.link.as_deref().map(Path::new) != target.as_deref() {
+                mismatches.push(Mismatch::Link);
+            }
+        }
+        EntryKind::File => {
+            if entry.mtime.is_some_and(|mtime| mtime != metadata.mtime()) {
+                mismatches.push(Mismatch::Mtime);
+            }
+            if entry.size.is_some_and(|size| size != metadata.size()) {
+                mismatches.push(Mismatch::Size);
+            } else if let Some(sha256) = &entry.sha256 {
+                // Only compute the checksum if the size matches; otherwise we
+                // already know that the file changed.
+                match alpm::compute_sha256sum(path.as_os_str().as_encoded_bytes()) {
+                    Ok(actual) if actual != *sha256 => mismatches.push(Mismatch::Checksum),
+                    Ok(_) => {}
+                    Err(error) => {
+                        warn!("Failed to compute checksum of {}: {error}", path.display());
+                    }
+                }
+            }
+        }
+    }
+    (!mismatches.is_empty()).then_some(FileState::Changed(mismatches))
+}
+
+/// Verify the files of a package under `root`.
+///
+/// Compare every file of `package` to the corresponding entry in its mtree
+/// file.  Expect the contents of backup files to change, and only report
+/// missing backup files or backup files with changed permissions.
+///
+/// # Errors
+///
+/// Return an error if the mtree file of `package` cannot be read.
+pub fn verify_package(root: &Path, package: &VerifyPackage) -> std::io::Result<Vec<FileProblem>> {
+    debug!(package = package.name, "Verifying {}", package.name);
+    let mtree = File::open(&package.mtree)?;
+    let entries = read_mtree(BufReader::new(GzDecoder::new(BufReader::new(mtree))))?;
+    let mut problems = Vec::new();
+    for entry in entries {
+        let state = match verify_entry(root, &entry) {
+            Some(FileState::Changed(mismatches)) if package.backup.contains(&entry.path) => {
+                let mismatches = mismatches
+                    .into_iter()
+                    .filter(|mismatch| mismatch.is_permissions())
+                    .collect::<Vec<_>>();
+                (!mismatches.is_empty()).then_some(FileState::Changed(mismatches))
+            }
+            state => state,
+        };
+        if let Some(state) = state {
+            problems.push(FileProblem {
+                path: entry.path,
+                state,
+            });
+        }
+    }
+    Ok(problems)
+}
+
+/// Verify the files of many packages in parallel.
+///
+/// Verify `packages` with [`verify_package`] on as many threads as there are
+/// CPUs, and return results sorted by package name.
+pub fn verify_packages(root: &Path, packages: &[VerifyPackage]) -> Vec<PackageVerification> {
+    let threads = std::thread::available_parallelism().map_or(1, std::num::NonZero::get);
+    let next = AtomicUsize::new(0);
+    let mut results = std::thread::scope(|scope| {
+        let workers = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    while let Some(package) = packages.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let verification = PackageVerification {
+                            name: package.name.clone(),
+                            problems: verify_package(root, package),
+                        };
+                        results.push(verification);
+                    }
+                    results
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|worker| match worker.join() {
+                Ok(results) => results,
+                Err(panic) => std::panic::resume_unwind(panic),
+            })
+            .collect::<Vec<_>>()
+    });
+    results.sort_by(|a, b| a.name.cmp(&b.name));
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write as _, os::unix::fs::PermissionsExt};
+
+    use flate2::{Compression, write::GzEncoder};
+
+    use super::*;
+
+    /// The SHA-256 digest of `hello`.
+    const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("packit-verify-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    fn write_file(root: &Path, path: &str, contents: &str, mode: u32) {
+        let path = root.join(path);
+        std::fs::write(&path, contents).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    fn file_entry(path: &str) -> MtreeEntry {
+        MtreeEntry {
+            path: path.to_owned(),
+            kind: EntryKind::File,
+            mode: Some(0o644),
+            uid: None,
+            gid: None,
+            size: Some(5),
+            mtime: None,
+            sha256: Some(HELLO_SHA256.to_owned()),
+            link: None,
+        }
+    }
+
+    #[test]
+    fn verify_entry_unchanged_file() {
+        let root = temp_root("unchanged");
+        write_file(&root, "foo", "hello", 0o644);
+        let state = verify_entry(&root, &file_entry("foo"));
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(state, None);
+    }
+
+    #[test]
+    fn verify_entry_missing_file() {
+        let root = temp_root("missing");
+        let state = verify_entry(&root, &file_entry("foo"));
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(state, Some(FileState::Missing));
+    }
+
+    #[test]
+    fn verify_entry_changed_type() {
+        let root = temp_root("type");
+        std::fs::create_dir(root.join("foo")).unwrap();
+        let state = verify_entry(&root, &file_entry("foo"));
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(state, Some(FileState::Changed(vec![Mismatch::Type])));
+    }
+
+    #[test]
+    fn verify_entry_changed_mode() {
+        let root = temp_root("mode");
+        write_file(&root, "foo", "hello", 0o600);
+        let state = verify_entry(&root, &file_entry("foo"));
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(state, Some(FileState::Changed(vec![Mismatch::Mode])));
+    }
+
+    #[test]
+    fn verify_entry_changed_owner() {
+        let root = temp_root("owner");
+        write_file(&root, "foo", "hello", 0o644);
+        let metadata = root.join("foo").metadata().unwrap();
+        let entry = MtreeEntry {
+            uid: Some(metadata.uid().wrapping_add(1)),
+            gid: Some(metadata.gid()),
+            ..file_entry("foo")
+        };
+        let state = verify_entry(&root, &entry);
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(state, Some(FileState::Changed(vec![Mismatch::Owner])));
+    }
+
+    #[test]
+    fn verify_entry_changed_mtime() {
+        let root = temp_root("mtime");
+        write_file(&root, "foo", "hello", 0o644);
+        let metadata = root.join("foo").metadata().unwrap();
+        let entry = MtreeEntry {
+            mtime: Some(metadata.mtime() - 60),
+            ..file_entry("foo")
+        };
+        let state = verify_entry(&root, &entry);
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(state, Some(FileState::Changed(vec![Mismatch::Mtime])));
+    }
+
+    #[test]
+    fn verify_entry_changed_size_skips_checksum() {
+        let root = temp_root("size");
+        write_file(&root, "foo", "hello, world", 0o644);
+        let state = verify_entry(&root, &file_entry("foo"));
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(state, Some(FileState::Changed(vec![Mismatch::Size])));
+    }
+
+    #[test]
+    fn verify_entry_changed_checksum_with_equal_size() {
+        let root = temp_root("checksum");
+        write_file(&root, "foo", "world", 0o644);
+        let state = verify_entry(&root, &file_entry("foo"));
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(state, Some(FileState::Changed(vec![Mismatch::Checksum])));
+    }
+
+    #[test]
+    fn verify_entry_link_target() {
+        let root = temp_root("link");
+        std::os::unix::fs::symlink("bar", root.join("foo")).unwrap();
+        let entry = |target: &str| MtreeEntry {
+            path: "foo".to_owned(),
+            kind: EntryKind::Link,
+            mode: Some(0o777),
+            uid: None,
+            gid: None,
+            size: None,
+            mtime: None,
+            sha256: None,
+            link: Some(target.to_owned()),
+        };
+        let matching = verify_entry(&root, &entry("bar"));
+        let wrong = verify_entry(&root, &entry("baz"));
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(matching, None);
+        assert_eq!(wrong, Some(FileState::Changed(vec![Mismatch::Link])));
+    }
+
+    #[test]
+    fn verify_package_ignores_changed_contents_of_backup_files() {
+        let root = temp_root("package");
+        std::fs::create_dir_all(root.join("etc")).unwrap();
+        std::fs::create_dir_all(root.join("usr/bin")).unwrap();
+        write_file(&root, "etc/foo.conf", "hello, world", 0o644);
+        write_file(&root, "etc/bar.conf", "world", 0o600);
+        write_file(&root, "usr/bin/foo", "world", 0o644);
+        let mtree_path = root.join("mtree");
+        let mut mtree = GzEncoder::new(File::create(&mtree_path).unwrap(), Compression::fast());
+        write!(
+            mtree,
+            "#mtree\n\
+/set type=file mode=644\n\
+./.PKGINFO size=100\n\
+./etc type=dir mode=755\n\
+./etc/foo.conf size=5 sha256digest={HELLO_SHA256}\n\
+./etc/bar.conf size=5 sha256digest={HELLO_SHA256}\n\
+./etc/baz.conf size=5 sha256digest={HELLO_SHA256}\n\
+./usr/bin/foo size=5 sha256digest={HELLO_SHA256}\n"
+        )
+        .unwrap();
+        mtree.finish().unwrap();
+        let package = VerifyPackage {
+            name: "foo".to_owned(),
+            mtree: mtree_path,
+            backup: ["etc/foo.conf", "etc/bar.conf", "etc/baz.conf"]
+                .into_iter()
+                .map(ToOwned::to_owned)
+                .collect(),
+        };
+        let problems = verify_package(&root, &package);
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            problems.unwrap(),
+            vec![
+                FileProblem {
+                    path: "etc/bar.conf".to_owned(),
+                    state: FileState::Changed(vec![Mismatch::Mode]),
+                },
+                FileProblem {
+                    path: "etc/baz.conf".to_owned(),
+                    state: FileState::Missing,
+                },
+                FileProblem {
+                    path: "usr/bin/foo".to_owned(),
+                    state: FileState::Changed(vec![Mismatch::Checksum]),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn verify_package_without_mtree() {
+        let package = VerifyPackage {
+            name: "foo".to_owned(),
+            mtree: PathBuf::from("/nonexistent/packit/mtree"),
+            backup: HashSet::new(),
+        };
+        let error = verify_package(Path::new("/"), &package).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+}